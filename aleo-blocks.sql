@@ -17,6 +17,40 @@ USE `aleo_blocks`;
 /*!40101 SET @OLD_SQL_MODE=@@SQL_MODE, SQL_MODE='NO_AUTO_VALUE_ON_ZERO' */;
 /*!40111 SET @OLD_SQL_NOTES=@@SQL_NOTES, SQL_NOTES=0 */;
 
+--
+-- Table structure for table `address_groups`
+--
+
+DROP TABLE IF EXISTS `address_groups`;
+/*!40101 SET @saved_cs_client     = @@character_set_client */;
+/*!50503 SET character_set_client = utf8mb4 */;
+CREATE TABLE `address_groups` (
+  `id` int unsigned NOT NULL AUTO_INCREMENT,
+  `name` varchar(100) NOT NULL,
+  `label` varchar(200) NOT NULL DEFAULT '',
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `name_UNIQUE` (`name`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb3;
+/*!40101 SET character_set_client = @saved_cs_client */;
+
+--
+-- Table structure for table `address_group_members`
+--
+
+DROP TABLE IF EXISTS `address_group_members`;
+/*!40101 SET @saved_cs_client     = @@character_set_client */;
+/*!50503 SET character_set_client = utf8mb4 */;
+CREATE TABLE `address_group_members` (
+  `id` int unsigned NOT NULL AUTO_INCREMENT,
+  `group_id` int unsigned NOT NULL,
+  `address` varchar(100) NOT NULL,
+  `label` varchar(200) NOT NULL DEFAULT '',
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `group_address_UNIQUE` (`group_id`,`address`),
+  KEY `address_idx` (`address`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb3;
+/*!40101 SET character_set_client = @saved_cs_client */;
+
 --
 -- Table structure for table `block_solutions`
 --
@@ -34,7 +68,9 @@ CREATE TABLE `block_solutions` (
   `timestamp` int unsigned NOT NULL,
   PRIMARY KEY (`id`),
   UNIQUE KEY `id_UNIQUE` (`id`),
-  UNIQUE KEY `commitment_UNIQUE` (`commitment`)
+  UNIQUE KEY `commitment_UNIQUE` (`commitment`),
+  KEY `timestamp_address_idx` (`timestamp`,`address`),
+  KEY `address_timestamp_id_idx` (`address`,`timestamp`,`id`)
 ) ENGINE=InnoDB AUTO_INCREMENT=6 DEFAULT CHARSET=utf8mb3;
 /*!40101 SET character_set_client = @saved_cs_client */;
 
@@ -65,6 +101,48 @@ CREATE TABLE `blocks` (
   UNIQUE KEY `previous_block_hash_UNIQUE` (`previous_block_hash`)
 ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb3;
 /*!40101 SET character_set_client = @saved_cs_client */;
+
+--
+-- Table structure for table `payout_periods`
+--
+
+DROP TABLE IF EXISTS `payout_periods`;
+/*!40101 SET @saved_cs_client     = @@character_set_client */;
+/*!50503 SET character_set_client = utf8mb4 */;
+CREATE TABLE `payout_periods` (
+  `id` int unsigned NOT NULL AUTO_INCREMENT,
+  `scheme` varchar(100) NOT NULL,
+  `period_start` bigint NOT NULL,
+  `period_end` bigint NOT NULL,
+  `closed_at` bigint NOT NULL,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `scheme_period_UNIQUE` (`scheme`,`period_start`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb3;
+/*!40101 SET character_set_client = @saved_cs_client */;
+
+--
+-- Table structure for table `payout_ledger`
+--
+
+DROP TABLE IF EXISTS `payout_ledger`;
+/*!40101 SET @saved_cs_client     = @@character_set_client */;
+/*!50503 SET character_set_client = utf8mb4 */;
+CREATE TABLE `payout_ledger` (
+  `id` int unsigned NOT NULL AUTO_INCREMENT,
+  `scheme` varchar(100) NOT NULL,
+  `period_start` bigint NOT NULL,
+  `period_end` bigint NOT NULL,
+  `line` int unsigned NOT NULL,
+  `beneficiary` varchar(100) NOT NULL,
+  `solutions` bigint unsigned NOT NULL,
+  `gross_reward` bigint unsigned NOT NULL,
+  `fee` bigint unsigned NOT NULL,
+  `net_reward` bigint unsigned NOT NULL,
+  PRIMARY KEY (`id`),
+  UNIQUE KEY `scheme_period_beneficiary_UNIQUE` (`scheme`,`period_start`,`beneficiary`),
+  UNIQUE KEY `scheme_period_line_UNIQUE` (`scheme`,`period_start`,`line`)
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb3;
+/*!40101 SET character_set_client = @saved_cs_client */;
 /*!40103 SET TIME_ZONE=@OLD_TIME_ZONE */;
 
 /*!40101 SET SQL_MODE=@OLD_SQL_MODE */;
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::info;
+use parking_lot::Mutex;
+
+/// Requests faster than this may grow the range size and the concurrency.
+const FAST_LATENCY: Duration = Duration::from_secs(2);
+/// Requests slower than this shrink the range size.
+const SLOW_LATENCY: Duration = Duration::from_secs(10);
+
+/// The result of one block range request.
+#[derive(Debug, Clone, Copy)]
+pub enum Outcome {
+    Success(Duration),
+    /// The api answered 429.
+    Throttled,
+    /// Any other error, including a body that failed to deserialize.
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    request: u32,
+    concurrent: usize,
+    // 连续的快速请求数
+    fast: usize,
+}
+
+/// The blocks per request and the requests in flight of every api, adjusted from the observed requests.
+///
+/// Throttling halves the concurrency, errors and slow responses shrink the range, and a full round of
+/// fast responses grows both by one step. The clones share the limits, so every sync task learns from the others.
+#[derive(Debug, Clone)]
+pub struct Adaptive {
+    initial: Limits,
+    request_bounds: (u32, u32),
+    concurrent_bounds: (usize, usize),
+    apis: Arc<Mutex<HashMap<String, Limits>>>,
+}
+
+impl Adaptive {
+    /// `request` and `concurrent` are the starting point, clamped into their `(min, max)` bounds.
+    pub fn new(request: u32, concurrent: usize, request_bounds: (u32, u32), concurrent_bounds: (usize, usize)) -> Self {
+        let initial = Limits {
+            request: request.clamp(request_bounds.0, request_bounds.1),
+            concurrent: concurrent.clamp(concurrent_bounds.0, concurrent_bounds.1),
+            fast: 0,
+        };
+        Self { initial, request_bounds, concurrent_bounds, apis: Default::default() }
+    }
+
+    /// Returns the blocks per request and the requests in flight for the api.
+    pub fn limits(&self, api: &str) -> (u32, usize) {
+        let apis = self.apis.lock();
+        let limits = apis.get(api).unwrap_or(&self.initial);
+        (limits.request, limits.concurrent)
+    }
+
+    pub fn report(&self, api: &str, outcome: Outcome) {
+        let (request_min, request_max) = self.request_bounds;
+        let (concurrent_min, concurrent_max) = self.concurrent_bounds;
+
+        let mut apis = self.apis.lock();
+        let limits = apis.entry(api.to_string()).or_insert(self.initial);
+        let before = (limits.request, limits.concurrent);
+        match outcome {
+            Outcome::Throttled => {
+                limits.concurrent = (limits.concurrent / 2).max(concurrent_min);
+                limits.request = (limits.request * 3 / 4).max(request_min);
+                limits.fast = 0;
+            }
+            Outcome::Failed => {
+                limits.request = (limits.request / 2).max(request_min);
+                limits.fast = 0;
+            }
+            Outcome::Success(latency) if latency >= SLOW_LATENCY => {
+                limits.request = (limits.request * 3 / 4).max(request_min);
+                limits.fast = 0;
+            }
+            Outcome::Success(latency) if latency < FAST_LATENCY => {
+                limits.fast += 1;
+                // 一轮并发请求都较快时才放大
+                if limits.fast >= limits.concurrent {
+                    limits.fast = 0;
+                    limits.concurrent = (limits.concurrent + 1).min(concurrent_max);
+                    limits.request = (limits.request + (limits.request / 4).max(1)).min(request_max);
+                }
+            }
+            Outcome::Success(_) => limits.fast = 0,
+        }
+
+        if before != (limits.request, limits.concurrent) {
+            info!("api {api} {:?}: batch_request {} -> {}, batch_concurrent {} -> {}", outcome, before.0, limits.request, before.1, limits.concurrent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const API: &str = "http://127.0.0.1/testnet3";
+    const FAST: Outcome = Outcome::Success(Duration::from_millis(100));
+
+    fn adaptive() -> Adaptive {
+        Adaptive::new(100, 4, (10, 200), (1, 8))
+    }
+
+    #[test]
+    fn starts_clamped_into_the_bounds() {
+        assert_eq!(Adaptive::new(1000, 0, (10, 200), (1, 8)).limits(API), (200, 1));
+    }
+
+    #[test]
+    fn throttling_halves_the_concurrency() {
+        let adaptive = adaptive();
+        let limits: Vec<_> = (0..3).map(|_| {
+            adaptive.report(API, Outcome::Throttled);
+            adaptive.limits(API)
+        }).collect();
+        assert_eq!(limits, vec![(75, 2), (56, 1), (42, 1)]);
+    }
+
+    #[test]
+    fn failures_halve_the_range_down_to_the_minimum() {
+        let adaptive = adaptive();
+        let limits: Vec<_> = (0..4).map(|_| {
+            adaptive.report(API, Outcome::Failed);
+            adaptive.limits(API)
+        }).collect();
+        assert_eq!(limits, vec![(50, 4), (25, 4), (12, 4), (10, 4)]);
+    }
+
+    #[test]
+    fn slow_responses_shrink_the_range() {
+        let adaptive = adaptive();
+        adaptive.report(API, Outcome::Success(SLOW_LATENCY));
+        assert_eq!(adaptive.limits(API), (75, 4));
+    }
+
+    #[test]
+    fn a_full_round_of_fast_responses_grows_both() {
+        let adaptive = adaptive();
+        for _ in 0..3 {
+            adaptive.report(API, FAST);
+        }
+        assert_eq!(adaptive.limits(API), (100, 4));
+        adaptive.report(API, FAST);
+        assert_eq!(adaptive.limits(API), (125, 5));
+    }
+
+    #[test]
+    fn a_response_neither_fast_nor_slow_restarts_the_round() {
+        let adaptive = adaptive();
+        for _ in 0..3 {
+            adaptive.report(API, FAST);
+        }
+        adaptive.report(API, Outcome::Success(FAST_LATENCY));
+        for _ in 0..3 {
+            adaptive.report(API, FAST);
+        }
+        assert_eq!(adaptive.limits(API), (100, 4));
+    }
+
+    #[test]
+    fn growth_stops_at_the_maximum() {
+        let adaptive = Adaptive::new(200, 8, (10, 200), (1, 8));
+        for _ in 0..16 {
+            adaptive.report(API, FAST);
+        }
+        assert_eq!(adaptive.limits(API), (200, 8));
+    }
+
+    #[test]
+    fn every_api_has_its_limits_shared_by_the_clones() {
+        let adaptive = adaptive();
+        let clone = adaptive.clone();
+        clone.report(API, Outcome::Failed);
+        assert_eq!(adaptive.limits(API), (50, 4));
+        assert_eq!(adaptive.limits("http://127.0.0.2/testnet3"), (100, 4));
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use log::{error, info};
+use parking_lot::RwLock;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use warp::{Filter, Rejection};
+
+use snarkvm_console_network::Network;
+
+use crate::server::ApiError;
+use crate::storage::{Storage, Store};
+
+/// Every key starts with this prefix, followed by the key id and the secret.
+const KEY_PREFIX: &str = "ak";
+/// How often the server checks the key file for changes made by the cli.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What a key may query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// Every address and group, including the leaderboard and the group management.
+    All,
+    Address(String),
+    /// The group rewards and the addresses of the group members.
+    Group(String),
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            _ if s == "*" => Ok(Self::All),
+            Some(("address", address)) if !address.is_empty() => Ok(Self::Address(address.to_string())),
+            Some(("group", name)) if !name.is_empty() => Ok(Self::Group(name.to_string())),
+            _ => bail!("invalid scope {s}, expected *, address:<address> or group:<name>"),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => write!(f, "*"),
+            Self::Address(address) => write!(f, "address:{address}"),
+            Self::Group(name) => write!(f, "group:{name}"),
+        }
+    }
+}
+
+/// The scopes of the key of a request.
+#[derive(Debug, Clone)]
+pub struct Scopes {
+    /// The id of the key, None when the authentication is disabled.
+    key: Option<String>,
+    scopes: Vec<Scope>,
+}
+
+impl Scopes {
+    /// Used when the authentication is disabled.
+    pub fn all() -> Self {
+        Self { key: None, scopes: vec![Scope::All] }
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.scopes.contains(&Scope::All)
+    }
+
+    pub fn require_all(&self) -> Result<(), ApiError> {
+        if self.is_all() { Ok(()) } else { Err(ApiError::Forbidden) }
+    }
+
+    pub fn allows_group(&self, name: &str) -> bool {
+        self.is_all() || self.scopes.iter().any(|scope| matches!(scope, Scope::Group(group) if group == name))
+    }
+
+    /// Checks the address is in scope directly or as a member of a group in scope.
+    pub async fn check_address<N: Network, S: Storage<N>>(&self, address: &str, store: &Store<N, S>) -> Result<(), ApiError> {
+        if self.is_all() || self.scopes.iter().any(|scope| matches!(scope, Scope::Address(a) if a == address)) {
+            return Ok(());
+        }
+        if self.scopes.iter().any(|scope| matches!(scope, Scope::Group(_))) {
+            let groups = store.get_groups().await.map_err(ApiError::Storage)?;
+            let member = groups.iter()
+                .filter(|group| self.allows_group(&group.name))
+                .any(|group| group.members.iter().any(|member| member.address == address));
+            if member {
+                return Ok(());
+            }
+        }
+        Err(ApiError::Forbidden)
+    }
+}
+
+/// An api key, only the sha256 of the key is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub hash: String,
+    #[serde(default)]
+    pub label: String,
+    pub scopes: Vec<String>,
+    pub created_at: i64,
+    #[serde(default)]
+    pub revoked: bool,
+}
+
+fn hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// The yaml file holding the api keys, written by the cli and read by the server.
+pub struct KeyFile {
+    path: String,
+}
+
+impl KeyFile {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_string() }
+    }
+
+    pub fn load(&self) -> Result<Vec<ApiKey>> {
+        match fs::read(&self.path) {
+            Ok(data) => serde_yaml::from_slice(&data).with_context(|| format!("failed to parse api key file {}", self.path)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e).with_context(|| format!("failed to read api key file {}", self.path)),
+        }
+    }
+
+    fn save(&self, keys: &[ApiKey]) -> Result<()> {
+        let tmp = format!("{}.tmp", self.path);
+        fs::write(&tmp, serde_yaml::to_string(keys)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Creates a key and returns it with the plain key, which is not stored anywhere.
+    pub fn create(&self, label: &str, scopes: &[Scope]) -> Result<(ApiKey, String)> {
+        if scopes.is_empty() {
+            bail!("a key needs at least one scope");
+        }
+        let mut keys = self.load()?;
+        let mut rng = rand::thread_rng();
+        let id = loop {
+            let id = hex::encode(rng.gen::<[u8; 4]>());
+            if keys.iter().all(|key| key.id != id) {
+                break id;
+            }
+        };
+        let token = format!("{KEY_PREFIX}_{id}_{}", hex::encode(rng.gen::<[u8; 32]>()));
+
+        let key = ApiKey {
+            id,
+            hash: hash(&token),
+            label: label.to_string(),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64,
+            revoked: false,
+        };
+        keys.push(key.clone());
+        self.save(&keys)?;
+        Ok((key, token))
+    }
+
+    /// Returns false if the key does not exist or is already revoked.
+    pub fn revoke(&self, id: &str) -> Result<bool> {
+        let mut keys = self.load()?;
+        match keys.iter_mut().find(|key| key.id == id && !key.revoked) {
+            Some(key) => key.revoked = true,
+            None => return Ok(false),
+        }
+        self.save(&keys)?;
+        Ok(true)
+    }
+}
+
+/// The keys checked by the server, reloaded when the key file changes.
+pub struct KeyStore {
+    file: KeyFile,
+    // (modified time of the file, id -> key)
+    keys: RwLock<(Option<SystemTime>, HashMap<String, ApiKey>)>,
+}
+
+impl KeyStore {
+    pub fn open(path: &str) -> Result<Self> {
+        let store = Self { file: KeyFile::new(path), keys: Default::default() };
+        store.reload()?;
+        Ok(store)
+    }
+
+    fn reload(&self) -> Result<()> {
+        let modified = fs::metadata(&self.file.path).and_then(|m| m.modified()).ok();
+        if self.keys.read().0 == modified && modified.is_some() {
+            return Ok(());
+        }
+        let keys = self.file.load()?;
+        info!("load {} api keys from {}", keys.len(), self.file.path);
+        *self.keys.write() = (modified, keys.into_iter().map(|key| (key.id.clone(), key)).collect());
+        Ok(())
+    }
+
+    /// Polls the key file, so keys created or revoked by the cli take effect without a restart.
+    pub async fn watch(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.reload() {
+                error!("reload api keys failed, keep the current keys: {:?}", e);
+            }
+        }
+    }
+
+    pub fn authenticate(&self, token: &str) -> Result<Scopes, ApiError> {
+        let id = token.strip_prefix(KEY_PREFIX)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .and_then(|rest| rest.split_once('_'))
+            .map(|(id, _)| id)
+            .ok_or(ApiError::Unauthorized)?;
+        let keys = self.keys.read();
+        let key = keys.1.get(id).filter(|key| !key.revoked && key.hash == hash(token)).ok_or(ApiError::Unauthorized)?;
+        // 无法解析的scope忽略，不放宽权限
+        Ok(Scopes { key: Some(key.id.clone()), scopes: key.scopes.iter().filter_map(|scope| scope.parse().ok()).collect() })
+    }
+}
+
+/// Extracts the scopes of the request from `Authorization: Bearer <key>` or `X-Api-Key: <key>`, every scope when `keys` is None.
+pub fn with_auth(keys: Option<Arc<KeyStore>>) -> impl Filter<Extract = (Arc<Scopes>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::header::optional::<String>("x-api-key"))
+        .and_then(move |authorization: Option<String>, api_key: Option<String>| {
+            let keys = keys.clone();
+            async move {
+                let keys = match keys {
+                    Some(keys) => keys,
+                    None => return Ok(Arc::new(Scopes::all())),
+                };
+                let token = api_key
+                    .or_else(|| authorization.and_then(|value| value.strip_prefix("Bearer ").map(str::to_string)))
+                    .ok_or(ApiError::Unauthorized)?;
+                keys.authenticate(token.trim()).map(Arc::new).map_err(Rejection::from)
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_file(name: &str) -> KeyFile {
+        let path = std::env::temp_dir().join(format!("aleo-blocks-keys-{name}-{}.yml", std::process::id()));
+        let _ = fs::remove_file(&path);
+        KeyFile::new(path.to_str().unwrap())
+    }
+
+    #[test]
+    fn scopes_round_trip() {
+        for scope in ["*", "address:aleo1abc", "group:pool"] {
+            assert_eq!(scope.parse::<Scope>().unwrap().to_string(), scope);
+        }
+        assert_eq!("group:a:b".parse::<Scope>().unwrap(), Scope::Group(String::from("a:b")));
+    }
+
+    #[test]
+    fn invalid_scopes_are_rejected() {
+        for scope in ["", "all", "address:", "group:", "prover:aleo1abc", "**"] {
+            assert!(scope.parse::<Scope>().is_err(), "{scope}");
+        }
+    }
+
+    #[test]
+    fn authenticates_the_created_key_only() {
+        let file = key_file("authenticate");
+        let (key, token) = file.create("pool", &[Scope::Group(String::from("pool"))]).unwrap();
+        let keys = KeyStore::open(&file.path).unwrap();
+
+        let scopes = keys.authenticate(&token).unwrap();
+        assert_eq!(scopes.key(), Some(key.id.as_str()));
+        assert!(scopes.allows_group("pool"));
+        assert!(!scopes.allows_group("other"));
+        assert!(scopes.require_all().is_err());
+
+        let last = if token.ends_with('0') { '1' } else { '0' };
+        let wrong_secret = format!("{}{last}", &token[..token.len() - 1]);
+        let id_only = format!("{KEY_PREFIX}_{}_", key.id);
+        for token in [wrong_secret.as_str(), id_only.as_str(), "", "ak", "ak_", "bearer"] {
+            assert!(matches!(keys.authenticate(token), Err(ApiError::Unauthorized)), "{token}");
+        }
+        fs::remove_file(&file.path).unwrap();
+    }
+
+    #[test]
+    fn revoked_keys_are_rejected() {
+        let file = key_file("revoke");
+        let (key, token) = file.create("", &[Scope::All]).unwrap();
+        assert!(file.revoke(&key.id).unwrap());
+        assert!(!file.revoke(&key.id).unwrap());
+        let keys = KeyStore::open(&file.path).unwrap();
+        assert!(matches!(keys.authenticate(&token), Err(ApiError::Unauthorized)));
+        fs::remove_file(&file.path).unwrap();
+    }
+
+    #[test]
+    fn unknown_scopes_in_the_file_grant_nothing() {
+        let file = key_file("unknown-scope");
+        let (mut key, token) = file.create("", &[Scope::Group(String::from("pool"))]).unwrap();
+        key.scopes = vec![String::from("admin"), String::from("group:pool")];
+        file.save(&[key]).unwrap();
+        let scopes = KeyStore::open(&file.path).unwrap().authenticate(&token).unwrap();
+        assert!(!scopes.is_all());
+        assert!(scopes.allows_group("pool"));
+        fs::remove_file(&file.path).unwrap();
+    }
+}
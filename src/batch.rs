@@ -1,77 +1,85 @@
 use anyhow::{anyhow, bail, Result};
+use futures::stream::FuturesOrdered;
 use futures::StreamExt;
 use parking_lot::RwLock;
+use std::collections::HashSet;
 use std::{sync::Arc, vec};
 use log::{info, trace};
 use std::time::Instant;
+use crate::adaptive::{Adaptive, Outcome};
 use crate::manage::ApiManage;
 use crate::utils::{handle_dispatch_error, log_progress};
 use crate::message::Message;
+use crate::parse::{compute_block, ParseError, ParsedBlock};
+use crate::recover::{recover, ParseOptions};
+use crate::watch::Watchlist;
 use tokio::sync::mpsc;
 
 use snarkvm_console_network::Network;
+use snarkvm_console_types_address::Address;
 use snarkvm_synthesizer::Block;
 
 #[derive(Debug)]
-pub struct Batch<'a, N: Network> {
+pub struct Batch<N: Network> {
     api_manage: ApiManage,
     start_height: u32,
     end_height: Option<u32>,
-    address: &'a Vec<String>,
-    blocks: RwLock<Option<(u32, Block<N>)>>,
-    batch_request: u32,
-    batch_concurrent: usize,
+    address: Watchlist<N>,
+    adaptive: Adaptive,
     sender: mpsc::Sender<Message<N>>,
-    store_block: bool,
+    parse: ParseOptions,
 }
 
-impl<'a, N:Network> Batch<'a, N> {
+impl<N:Network> Batch<N> {
     pub fn new(
         api_manage: ApiManage,
         start_height: u32, 
         end_height: Option<u32>, 
-        address: &'a Vec<String>, 
-        batch_request: u32,
-        batch_concurrent: usize,
+        address: Watchlist<N>, 
+        adaptive: Adaptive,
         sender: mpsc::Sender<Message<N>>,
-        store_block: bool,
+        parse: ParseOptions,
     ) -> Self {
         Self { 
             api_manage,
             start_height, 
             end_height, 
             address, 
-            blocks: RwLock::new(None), 
-            batch_request, 
-            batch_concurrent, 
+            adaptive, 
             sender,
-            store_block,
+            parse,
         }
     }
     /// Loads blocks from a CDN and process them with the given function.
     ///
+    /// Without an end height the blocks are loaded up to the CDN height, an end height is taken as is,
+    /// the blocks below it were already parsed by the live sync.
+    ///
     /// On success, this function returns the completed block height.
     /// On failure, this function returns the last successful block height (if any), along with the error.
     pub async fn get_blocks(&self) -> Result<u32, (u32, anyhow::Error)> {
 
         let start_height = self.start_height;
-        // Fetch the CDN height.
-        let cdn_height = match self.cdn_height().await {
-            Ok(cdn_height) => cdn_height,
-            Err(error) => return Err((start_height, error)),
+        let end_height = match self.end_height {
+            // 指定的结束高度以下的块已被实时同步解析过，api一定可以提供，不受CDN高度限制
+            Some(end_height) => end_height,
+            None => {
+                // Fetch the CDN height.
+                let cdn_height = match self.cdn_height().await {
+                    Ok(cdn_height) => cdn_height,
+                    Err(error) => return Err((start_height, error)),
+                };
+                // If the CDN height is less than the start height, return.
+                if cdn_height < start_height {
+                    return Err((
+                        start_height,
+                        anyhow!("The given start height ({start_height}) must be less than the CDN height ({cdn_height})"),
+                    ));
+                }
+                // If the end height is not specified, set it to the CDN height.
+                cdn_height
+            }
         };
-        // If the CDN height is less than the start height, return.
-        if cdn_height < start_height {
-            return Err((
-                start_height,
-                anyhow!("The given start height ({start_height}) must be less than the CDN height ({cdn_height})"),
-            ));
-        }
-
-        // If the end height is not specified, set it to the CDN height.
-        let end_height = self.end_height.unwrap_or(cdn_height);
-        // If the end height is greater than the CDN height, set the end height to the CDN height.
-        let end_height = if end_height > cdn_height { cdn_height } else { end_height };
         // If the end height is less than the start height, return.
         if end_height < start_height {
             return Err((
@@ -99,85 +107,126 @@ impl<'a, N:Network> Batch<'a, N> {
         // Start a timer.
         let timer = Instant::now();
 
-        futures::stream::iter(cdn_range.clone().step_by(self.batch_request as usize))
-            .map(|start| {
-                // Prepare the end height.
-                let end = start + self.batch_request;
+        // The number of blocks parsed in parallel, parsing is cpu bound.
+        let parse_concurrent = num_cpus::get().max(1);
 
-                // If the sync *has not* failed, log the progress.
-                let ctx = format!("blocks {start} to {end}");
-                if failed.read().is_none() {
-                    info!("Requesting {ctx} (of {cdn_end})");
+        // Requests are cut from the range one by one, with the size and concurrency adjusted to the api.
+        let fetched = futures::stream::unfold((cdn_start, FuturesOrdered::new()), |(mut next, mut in_flight)| {
+            let failed = failed.clone();
+            async move {
+                let (batch_request, batch_concurrent) = self.adaptive.limits(&self.api_manage.current());
+                while in_flight.len() < batch_concurrent && next < cdn_end {
+                    let end = (next + batch_request).min(cdn_end);
+                    // If the sync *has not* failed, log the progress.
+                    if failed.read().is_none() {
+                        info!("Requesting blocks {next} to {end} (of {cdn_end})");
+                    }
+                    in_flight.push_back(self.fetch_range(next, end, failed.clone()));
+                    next = end;
                 }
+                in_flight.next().await.map(|result| (result, (next, in_flight)))
+            }
+        });
+
+        // The block refetched in place of the last committed one, the next block is parsed again against it.
+        let refetched: RwLock<Option<Block<N>>> = RwLock::new(None);
 
-                // Download the blocks with an exponential backoff retry policy.
-                let failed_clone = failed.clone();
-                handle_dispatch_error(move || {
-                    let ctx = ctx.clone();
-                    let failed = failed_clone.clone();
-                    async move {
-                        // If the sync failed, return with an empty vector.
-                        if failed.read().is_some() {
-                            return std::future::ready(Ok(vec![])).await
-                        }
-                        // 取指定高度block
-                        // let blocks_url = format!("/block/{start}");
-                        // let blocks: Vec<Block<N>> = cdn_get_one(client, &blocks_url, &ctx).await?;
-                        // 取范围内 [start, end)
-                        let blocks_url = format!("/blocks?start={start}&end={end}");
-                        // Fetch the blocks.
-                        let blocks: Vec<Block<N>> = self.cdn_get_range(&blocks_url, &ctx).await?;
-                        // Return the blocks.
-                        std::future::ready(Ok(blocks)).await
+        fetched
+            // Attach the last block of the previous request, the first block is parsed against it.
+            .scan(None, |previous: &mut Option<Block<N>>, result: Result<(String, Vec<Block<N>>)>| {
+                let chunk = result.map(|(api, mut blocks)| {
+                    // Only retain blocks that are at or above the start height and below the end height.
+                    blocks.retain(|block| block.height() >= start_height && block.height() < end_height);
+                    let chunk = Chunk { api, previous: previous.clone(), blocks };
+                    if let Some(last) = chunk.blocks.last() {
+                        previous.replace(last.clone());
                     }
-                })
+                    chunk
+                });
+                std::future::ready(Some(chunk))
+            })
+            .map(|chunk| {
+                let failed = failed.clone();
+                async move {
+                    let chunk = chunk?;
+                    // If the sync failed, skip the parsing.
+                    let (address, generation) = self.address.snapshot();
+                    if failed.read().is_some() {
+                        return Ok((vec![], vec![], address, generation, chunk.api));
+                    }
+                    // 解析在阻塞线程池中并行，结果按高度顺序提交
+                    let store_block = self.parse.store_block;
+                    let chunk_address = address.clone();
+                    let api = chunk.api.clone();
+                    let (parsed, blocks) = tokio::task::spawn_blocking(move || parse_chunk(chunk, start_height, &chunk_address, store_block)).await??;
+                    Ok::<_, anyhow::Error>((parsed, blocks, address, generation, api))
+                }
             })
-            .buffered(self.batch_concurrent) // The number of concurrent requests.
+            .buffered(parse_concurrent)
             .for_each(|result| async {
                 // If the sync previously failed, return early.
                 if failed.read().is_some() {
                     return;
                 }
-                // Unwrap the blocks.
-                let mut blocks = match result {
-                    Ok(blocks) => blocks,
+                // Unwrap the parsed blocks.
+                let (parsed, blocks, address, generation, api) = match result {
+                    Ok(result) => result,
                     Err(error) => {
                         failed.write().replace(error);
                         return;
                     }
                 };
 
-                // Only retain blocks that are at or above the start height and below the end height.
-                blocks.retain(|block| block.height() >= start_height && block.height() < end_height);
-
-                #[cfg(debug_assertions)]
-                // Ensure the blocks are in order by height.
-                for (i, block) in blocks.iter().enumerate() {
-                    if i > 0 {
-                        assert_eq!(block.height(), blocks[i - 1].height() + 1);
-                    }
-                }
-              
                 // Fetch the last height in the blocks.
-                let curr_height = blocks.last().map(|block| block.height()).unwrap_or(start_height);
+                let curr_height = parsed.last()
+                    .map(|block| match block {
+                        Ok(block) => block.height,
+                        Err(error) => error.height(),
+                    })
+                    .unwrap_or(start_height);
 
-                // Process each of the blocks.
-                for block in blocks {
+                // Commit each of the blocks in order.
+                let mut previous = refetched.write().take();
+                for (block, raw) in parsed.into_iter().zip(blocks) {
+                    // 前一个块是重新获取的，当前块已基于原来的块解析，需重新解析
+                    let block = match previous.take() {
+                        Some(previous) => compute_block(&raw, &previous, &address, self.parse.store_block),
+                        None => block,
+                    };
+                    // A block failing to parse is refetched from the other apis, then skipped or the sync stops.
+                    let block = match block {
+                        Ok(block) => block,
+                        Err(error) => match recover(&self.api_manage, &api, &self.parse, &address, error).await {
+                            Ok((block, current)) => {
+                                previous = current;
+                                block
+                            },
+                            Err(error) => {
+                                failed.write().replace(error);
+                                return;
+                            }
+                        },
+                    };
                     // Retrieve the block height.
-                    let block_height = block.height();
+                    let block_height = block.height;
+                    trace!("commit block {}", block_height);
                     // If the sync failed, set the failed flag, and return.
-                    if let Err(error) = self.process_block(&block).await {
+                    if let Err(error) = crate::parse::emit_block(block, &self.sender).await {
                         let error = anyhow!("Failed to process block {block_height}: {error}");
                         failed.write().replace(error);
                         return;
                     }
+                    self.address.observe(generation, block_height);
 
                     // On success, update the completed height.
                     *completed_height.write() = block_height;
                 }
+                // The first block of the next chunk was parsed against the last block of this one.
+                *refetched.write() = previous;
 
                 // Log the progress.
-                log_progress(timer, curr_height, &cdn_range, "block", self.batch_request);
+                let (batch_request, _) = self.adaptive.limits(&self.api_manage.current());
+                log_progress(timer, curr_height, &cdn_range, "block", batch_request);
             })
             .await;
 
@@ -192,40 +241,14 @@ impl<'a, N:Network> Batch<'a, N> {
         }
     }
 
-    async fn process_block(&self, current_block: &Block<N>) -> anyhow::Result<()> {
-        trace!("current block {}", current_block.height());
-        // 刚启动，起始高度已在库里面，并且已计算过奖励，只用于下一个块的计算
-        if current_block.height() == self.start_height {
-            self.blocks.write().replace((self.start_height, current_block.clone()));
-            return Ok(())
-        }
-    
-        // 使用缓存的上一个块，计算当前块
-        {
-            let latest_block = self.blocks.read().as_ref().unwrap().clone();
-            trace!("latest block {}", latest_block.0);
-            crate::parse::parse_block::<N>(
-                &current_block, 
-                &latest_block.1, 
-                &self.address, 
-                self.sender.clone(),
-                self.store_block,
-            ).await?;
-        }
-        
-        // 当前块计算完成后，缓存，成为下一个块的计算依赖
-        self.blocks.write().replace((current_block.height(), current_block.clone()));
-        Ok(())
-    }
-
     /// Retrieves the CDN height with the given base URL.
     ///
     /// Note: This function decrements the tip by a few blocks, to ensure the
     /// tip is not on a block that is not yet available on the CDN.
     async fn cdn_height(&self) -> Result<u32> {
         // Send the request.
-        let response = match self.api_manage.get("/latest/height").await {
-            Ok(response) => response,
+        let (api, response) = match self.api_manage.get("/latest/height").await {
+            Ok(answer) => answer,
             Err(error) => bail!("Failed to fetch the CDN height: {error}"),
         };
         // Parse the response.
@@ -241,15 +264,16 @@ impl<'a, N:Network> Batch<'a, N> {
         // Decrement the tip by a few blocks to ensure the CDN is caught up.
         let tip = tip.saturating_sub(10);
         // Round the tip down to the nearest multiple.
-        Ok(tip - (tip % self.batch_request))
+        let (batch_request, _) = self.adaptive.limits(&api);
+        Ok(tip - (tip % batch_request))
     }
 
     /// Retrieves the objects from the CDN with the given URL.
     #[allow(unused)]
     async fn _cdn_get_one(&self, url: &str, ctx: &str) -> Result<Vec<Block<N>>> {
         // Fetch the bytes from the given URL.
-        let response = match self.api_manage.get(url).await {
-            Ok(response) => response,
+        let (_, response) = match self.api_manage.get(url).await {
+            Ok(answer) => answer,
             Err(error) => bail!("Failed to fetch {ctx}: {error}"),
         };
 
@@ -266,27 +290,238 @@ impl<'a, N:Network> Batch<'a, N> {
         }
     }
 
-    async fn cdn_get_range(&self, url: &str, ctx: &str) -> Result<Vec<Block<N>>> {
-        let response = match self.api_manage.get(url).await {
-            Ok(response) => response,
+    /// Fetches the blocks [start, end) with an exponential backoff retry policy, returns them with the api that answered.
+    async fn fetch_range(&self, start: u32, end: u32, failed: Arc<RwLock<Option<anyhow::Error>>>) -> Result<(String, Vec<Block<N>>)> {
+        let ctx = format!("blocks {start} to {end}");
+        handle_dispatch_error(move || {
+            let ctx = ctx.clone();
+            let failed = failed.clone();
+            async move {
+                // If the sync failed, return with an empty vector.
+                if failed.read().is_some() {
+                    return std::future::ready(Ok((String::new(), vec![]))).await
+                }
+                // 取指定高度block
+                // let blocks_url = format!("/block/{start}");
+                // let blocks: Vec<Block<N>> = cdn_get_one(client, &blocks_url, &ctx).await?;
+                // 取范围内 [start, end)
+                let blocks_url = format!("/blocks?start={start}&end={end}");
+                // Fetch the blocks.
+                let blocks = self.cdn_get_range(&blocks_url, &ctx).await?;
+                // Return the blocks.
+                std::future::ready(Ok(blocks)).await
+            }
+        }).await
+    }
+
+    /// Fetches the blocks and reports the latency or the failure of the api to the adaptive limits.
+    async fn cdn_get_range(&self, url: &str, ctx: &str) -> Result<(String, Vec<Block<N>>)> {
+        let timer = Instant::now();
+        // 重试耗尽的api记为失败，结果记在实际应答的api上
+        let (api, response) = match self.api_manage.get_reporting(url, |api| self.adaptive.report(api, Outcome::Failed)).await {
+            Ok(answer) => answer,
             Err(error) => bail!("Failed to fetch {ctx}: {error}"),
         };
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            self.adaptive.report(&api, Outcome::Throttled);
+            bail!("Failed to fetch {ctx}: rate limited by {api}");
+        }
 
         let res = match response.text().await {
-            Ok(body) => serde_json::from_str(&body),
-            Err(error) => bail!("Failed to parse {ctx}: {error}"),
+            Ok(body) if status.is_success() => serde_json::from_str(&body),
+            Ok(body) => {
+                self.adaptive.report(&api, Outcome::Failed);
+                bail!("Failed to fetch {ctx}: status {status}, body {body}")
+            },
+            Err(error) => {
+                self.adaptive.report(&api, Outcome::Failed);
+                bail!("Failed to parse {ctx}: {error}")
+            },
         };
 
         match res {
             Ok(body) => {
-                Ok(body)
+                self.adaptive.report(&api, Outcome::Success(timer.elapsed()));
+                Ok((api, body))
+            },
+            Err(error) => {
+                self.adaptive.report(&api, Outcome::Failed);
+                bail!("Failed to deserialize {ctx}: {error}")
             },
-            Err(error) => bail!("Failed to deserialize {ctx}: {error}"),
         }
     }
-    
 }
 
+/// The blocks of one request, with the block before the first one.
+struct Chunk<N: Network> {
+    /// The api the blocks were fetched from.
+    api: String,
+    previous: Option<Block<N>>,
+    blocks: Vec<Block<N>>,
+}
 
+/// The parse results of a chunk, with the blocks they were parsed from.
+type ParsedChunk<N> = (Vec<Result<ParsedBlock<N>, ParseError>>, Vec<Block<N>>);
 
+/// Parses the blocks of a chunk, the block at the start height is already synced and only used for the next block.
+///
+/// A block failing to parse does not stop the chunk, the error is returned in its place.
+fn parse_chunk<N: Network>(
+    chunk: Chunk<N>,
+    start_height: u32,
+    address: &HashSet<Address<N>>,
+    store_block: bool,
+) -> Result<ParsedChunk<N>> {
+    let mut parsed = Vec::with_capacity(chunk.blocks.len());
+    let mut blocks = Vec::with_capacity(chunk.blocks.len());
+    let mut previous = chunk.previous;
+    for block in chunk.blocks {
+        trace!("current block {}", block.height());
+        if block.height() != start_height {
+            let latest_block = previous.as_ref().ok_or_else(|| anyhow!("missing the block before {}", block.height()))?;
+            parsed.push(compute_block(&block, latest_block, address, store_block));
+            blocks.push(block.clone());
+        }
+        previous = Some(block);
+    }
+    Ok((parsed, blocks))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use reqwest::Client;
+    use snarkvm_console_network::Testnet3;
+
+    use super::*;
+    use crate::mock::{Endpoint, Fault, MockApi};
+    use crate::recover::ParseErrorPolicy;
+
+    fn batch(apis: Vec<String>, start_height: u32, end_height: Option<u32>) -> (Batch<Testnet3>, mpsc::Receiver<Message<Testnet3>>) {
+        let (sender, receiver) = mpsc::channel(4096);
+        let parse = ParseOptions {
+            store_block: false,
+            on_parse_error: ParseErrorPolicy::Stop,
+            quarantine_file: String::from("quarantine.jsonl"),
+        };
+        let batch = Batch::new(
+            ApiManage::new(Client::new(), apis),
+            start_height,
+            end_height,
+            Watchlist::new(HashSet::new()),
+            Adaptive::new(20, 4, (1, 50), (1, 8)),
+            sender,
+            parse,
+        );
+        (batch, receiver)
+    }
+
+    /// The heights of the blocks the sync moved past, in order.
+    fn synced_heights(mut receiver: mpsc::Receiver<Message<Testnet3>>) -> Vec<u32> {
+        let mut heights = Vec::new();
+        while let Ok(message) = receiver.try_recv() {
+            if let Message::SyncHeight(sync) = message {
+                heights.push(sync.height);
+            }
+        }
+        heights
+    }
+
+    #[tokio::test]
+    async fn cdn_height_stays_below_the_tip() {
+        let mock = MockApi::start(BTreeMap::new());
+        mock.set_height(100);
+        let (batch, _receiver) = batch(vec![mock.url()], 0, None);
+
+        // 低于最新高度10个块，并按batch_request取整
+        assert_eq!(batch.cdn_height().await.unwrap(), 80);
+    }
+
+    #[tokio::test]
+    async fn throttling_halves_the_concurrency() {
+        let mock = MockApi::start(BTreeMap::new());
+        mock.fault(Endpoint::Blocks, Fault::Status(429));
+        let (batch, _receiver) = batch(vec![mock.url()], 0, None);
 
+        assert!(batch.cdn_get_range("/blocks?start=0&end=20", "blocks 0 to 20").await.is_err());
+        assert_eq!(batch.adaptive.limits(&mock.url()), (15, 2));
+    }
+
+    #[tokio::test]
+    async fn truncated_blocks_halve_the_request() {
+        let mock = MockApi::start(BTreeMap::new());
+        mock.fault(Endpoint::Blocks, Fault::Truncate);
+        let (batch, _receiver) = batch(vec![mock.url()], 0, None);
+
+        assert!(batch.cdn_get_range("/blocks?start=0&end=20", "blocks 0 to 20").await.is_err());
+        assert_eq!(batch.adaptive.limits(&mock.url()), (10, 4));
+        // 故障只生效一次，重试成功
+        assert!(batch.cdn_get_range("/blocks?start=0&end=10", "blocks 0 to 10").await.unwrap().1.is_empty());
+    }
+
+    #[tokio::test]
+    async fn syncs_the_fixture_blocks() {
+        let mock = MockApi::from_fixtures().unwrap();
+        let start = *crate::mock::load_blocks(&crate::mock::fixtures_dir()).unwrap().keys().next().unwrap();
+        let (batch, receiver) = batch(vec![mock.url()], start, Some(start + 20));
+        mock.fault(Endpoint::Blocks, Fault::Status(500));
+        mock.fault(Endpoint::Blocks, Fault::Truncate);
+
+        assert_eq!(batch.get_blocks().await.unwrap(), start + 19);
+        drop(batch);
+        assert_eq!(synced_heights(receiver), (start + 1..start + 20).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn refetches_a_forked_block_from_another_api() {
+        let forked = MockApi::from_fixtures().unwrap();
+        let canonical = MockApi::from_fixtures().unwrap();
+        let start = *crate::mock::load_blocks(&crate::mock::fixtures_dir()).unwrap().keys().next().unwrap();
+        // 分叉的api在start + 5处少了一个块，下一个块与前一个块不连续
+        let height = start + 5;
+        let bodies = (height + 1..=canonical.height()).filter_map(|h| canonical.block(h)).collect();
+        forked.fork(height, bodies);
+        let (batch, _receiver) = batch(vec![forked.url(), canonical.url()], start, Some(start + 20));
+
+        assert_eq!(batch.get_blocks().await.unwrap(), start + 19);
+        let refetch = format!("/testnet3/blocks?start={height}&end={}", height + 2);
+        assert!(canonical.requests().contains(&refetch), "{:?}", canonical.requests());
+    }
+
+    #[tokio::test]
+    async fn parses_the_next_block_against_the_refetched_one() {
+        let forked = MockApi::from_fixtures().unwrap();
+        let canonical = MockApi::from_fixtures().unwrap();
+        let branch = crate::mock::load_blocks(&crate::mock::fork_dir()).unwrap();
+        // 分叉的api只有一个块来自另一分支，它的下一个块基于重新获取的块可以解析
+        let height = *branch.keys().next().unwrap() + 1;
+        let bodies = std::iter::once(branch[&height].clone())
+            .chain((height + 1..=canonical.height()).filter_map(|h| canonical.block(h)))
+            .collect();
+        forked.fork(height, bodies);
+        let start = height - 6;
+        let (batch, receiver) = batch(vec![forked.url(), canonical.url()], start, Some(start + 20));
+
+        assert_eq!(batch.get_blocks().await.unwrap(), start + 19);
+        drop(batch);
+        assert_eq!(synced_heights(receiver), (start + 1..start + 20).collect::<Vec<_>>());
+        let refetch = format!("/testnet3/blocks?start={}&end={}", height - 1, height + 1);
+        assert_eq!(canonical.requests(), vec![refetch]);
+    }
+
+    #[tokio::test]
+    async fn stops_at_a_forked_block() {
+        let forked = MockApi::from_fixtures().unwrap();
+        let start = *crate::mock::load_blocks(&crate::mock::fixtures_dir()).unwrap().keys().next().unwrap();
+        let height = start + 5;
+        let bodies = (height + 1..=forked.height()).filter_map(|h| forked.block(h)).collect();
+        forked.fork(height, bodies);
+        let (batch, _receiver) = batch(vec![forked.url()], start, Some(start + 20));
+
+        let (completed, error) = batch.get_blocks().await.unwrap_err();
+        assert_eq!(completed, height - 1);
+        assert!(error.to_string().contains("does not follow"), "{error}");
+    }
+}
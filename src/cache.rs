@@ -0,0 +1,255 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use http::header::CONTENT_TYPE;
+use log::{debug, error};
+use parking_lot::Mutex;
+use serde::Serialize;
+use warp::hyper::body::{Body, Bytes};
+
+use snarkvm_console_network::Network;
+
+use crate::storage::{Storage, Store};
+
+/// Responses of ranges ending at or before the latest recorded timestamp only change when the sync backfills
+/// an address added to the watch list, they are dropped when the backfilled solutions are recorded.
+const FINALIZED_TTL: Duration = Duration::from_secs(24 * 3600);
+/// Responses of ranges touching the tip, also dropped as soon as a new block is recorded.
+const TIP_TTL: Duration = Duration::from_secs(30);
+/// How often the latest recorded timestamp and solution id are polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+struct Entry {
+    body: Bytes,
+    // 查询区间的结束时间戳（不含）
+    end: i64,
+    tip: bool,
+    expires: Instant,
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    // tick -> key, the least recently used entry first
+    lru: BTreeMap<u64, String>,
+    tick: u64,
+    size: usize,
+    // 已入库的最新时间戳
+    finalized: i64,
+}
+
+impl Inner {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.tick);
+            self.size -= key.len() + entry.body.len();
+        }
+    }
+}
+
+/// A read-through cache of serialized api responses, keyed by route and parameters and limited in bytes.
+pub struct ResponseCache {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+impl ResponseCache {
+    /// `capacity` is in bytes, 0 disables the cache.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, inner: Default::default() }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Bytes> {
+        let mut inner = self.inner.lock();
+        let (body, expired, tick) = {
+            let entry = inner.entries.get(key)?;
+            (entry.body.clone(), entry.expires <= Instant::now(), entry.tick)
+        };
+        if expired {
+            inner.remove(key);
+            return None;
+        }
+
+        inner.tick += 1;
+        let next = inner.tick;
+        inner.lru.remove(&tick);
+        inner.lru.insert(next, key.to_string());
+        inner.entries.get_mut(key).unwrap().tick = next;
+        Some(body)
+    }
+
+    /// Serializes the response and caches it, `end` is the exclusive end timestamp of the queried range.
+    pub fn insert<T: Serialize>(&self, key: String, end: i64, response: &T) -> Result<Bytes, serde_json::Error> {
+        let body = Bytes::from(serde_json::to_vec(response)?);
+        let size = key.len() + body.len();
+        if size > self.capacity {
+            return Ok(body);
+        }
+
+        let mut inner = self.inner.lock();
+        inner.remove(&key);
+        while inner.size + size > self.capacity {
+            let oldest = match inner.lru.values().next() {
+                Some(oldest) => oldest.clone(),
+                None => break,
+            };
+            inner.remove(&oldest);
+        }
+
+        let tip = end > inner.finalized;
+        let expires = Instant::now() + if tip { TIP_TTL } else { FINALIZED_TTL };
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.lru.insert(tick, key.clone());
+        inner.size += size;
+        inner.entries.insert(key, Entry { body: body.clone(), end, tip, expires, tick });
+        Ok(body)
+    }
+
+    /// Records the latest recorded timestamp, the tip entries are dropped when it moves.
+    pub fn set_finalized(&self, timestamp: i64) {
+        let mut inner = self.inner.lock();
+        if inner.finalized == timestamp {
+            return;
+        }
+        inner.finalized = timestamp;
+        let tips: Vec<String> = inner.entries.iter().filter(|(_, e)| e.tip).map(|(k, _)| k.clone()).collect();
+        debug!("latest timestamp {}, drop {} cached tip responses", timestamp, tips.len());
+        for key in tips {
+            inner.remove(&key);
+        }
+    }
+
+    /// Drops the responses of the ranges ending after `timestamp`, solutions at `timestamp` were recorded.
+    pub fn invalidate_from(&self, timestamp: i64) {
+        let mut inner = self.inner.lock();
+        let keys: Vec<String> = inner.entries.iter().filter(|(_, e)| e.end > timestamp).map(|(k, _)| k.clone()).collect();
+        debug!("solutions recorded from {}, drop {} cached responses", timestamp, keys.len());
+        for key in keys {
+            inner.remove(&key);
+        }
+    }
+}
+
+/// Returns the cached json body as a reply.
+pub fn reply(body: Bytes) -> warp::reply::Response {
+    let mut response = warp::reply::Response::new(Body::from(body));
+    response.headers_mut().insert(CONTENT_TYPE, http::HeaderValue::from_static("application/json"));
+    response
+}
+
+/// Polls the latest recorded timestamp, so responses touching the tip are invalidated when new blocks are recorded,
+/// and the solutions recorded since the last poll, so responses of the ranges a backfill writes to are invalidated.
+pub async fn watch_finalized<N: Network, S: Storage<N>>(cache: Arc<ResponseCache>, store: Arc<Store<N, S>>) {
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut last_id: Option<u64> = None;
+    loop {
+        interval.tick().await;
+        // 先检查新记录的solution，再移动最新时间戳
+        match store.get_last_solution_id().await {
+            Ok(id) if id > last_id => {
+                if let Some(last_id) = last_id {
+                    match store.get_earliest_timestamp_after(last_id).await {
+                        Ok(Some(timestamp)) => cache.invalidate_from(timestamp),
+                        Ok(None) => {}
+                        Err(e) => {
+                            error!("get the solutions recorded after {} failed: {:?}", last_id, e);
+                            continue;
+                        }
+                    }
+                }
+                last_id = id;
+            }
+            Ok(_) => {}
+            Err(e) => error!("get last solution id failed: {:?}", e),
+        }
+        match store.get_latest_timestamp().await {
+            Ok(timestamp) => cache.set_finalized(timestamp.unwrap_or_default()),
+            Err(e) => error!("get latest timestamp failed: {:?}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every entry takes 7 bytes, a one byte key and a 6 bytes body.
+    fn insert(cache: &ResponseCache, key: &str, end: i64) {
+        cache.insert(key.to_string(), end, &"body").unwrap();
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let cache = ResponseCache::new(21);
+        for key in ["a", "b", "c"] {
+            insert(&cache, key, 0);
+        }
+        assert!(cache.get("a").is_some());
+        insert(&cache, "d", 0);
+        assert_eq!(cache.get("b"), None);
+        for key in ["a", "c", "d"] {
+            assert_eq!(cache.get(key), Some(Bytes::from_static(b"\"body\"")));
+        }
+    }
+
+    #[test]
+    fn replacing_an_entry_keeps_the_size() {
+        let cache = ResponseCache::new(14);
+        insert(&cache, "a", 0);
+        insert(&cache, "a", 0);
+        insert(&cache, "b", 0);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_some());
+        assert_eq!(cache.inner.lock().size, 14);
+    }
+
+    #[test]
+    fn larger_responses_are_not_cached() {
+        let cache = ResponseCache::new(6);
+        insert(&cache, "a", 0);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.inner.lock().size, 0);
+    }
+
+    #[test]
+    fn a_new_block_drops_the_tip_entries() {
+        let cache = ResponseCache::new(1024);
+        cache.set_finalized(100);
+        insert(&cache, "f", 100);
+        insert(&cache, "t", 101);
+        cache.set_finalized(100);
+        assert!(cache.get("t").is_some());
+
+        cache.set_finalized(115);
+        assert_eq!(cache.get("t"), None);
+        assert!(cache.get("f").is_some());
+    }
+
+    #[test]
+    fn expired_entries_are_dropped() {
+        let cache = ResponseCache::new(1024);
+        insert(&cache, "a", 0);
+        cache.inner.lock().entries.get_mut("a").unwrap().expires = Instant::now();
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.inner.lock().size, 0);
+    }
+
+    #[test]
+    fn finalized_entries_are_kept_until_solutions_are_recorded_in_their_range() {
+        let cache = ResponseCache::new(1024);
+        cache.set_finalized(300);
+        for (key, end) in [("a", 50), ("b", 100), ("c", 200)] {
+            insert(&cache, key, end);
+        }
+        assert!(cache.inner.lock().entries.values().all(|e| e.expires > Instant::now() + Duration::from_secs(3600)));
+
+        // 回填记录了时间戳100的solution，[.., 100) 的区间不受影响
+        cache.invalidate_from(100);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_some());
+        assert_eq!(cache.get("c"), None);
+    }
+}
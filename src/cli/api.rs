@@ -1,12 +1,11 @@
-use std::net::{SocketAddr, IpAddr, Ipv4Addr};
-use std::str::FromStr;
+use std::net::SocketAddr;
 
 use clap::Parser;
 use anyhow::Result;
 
+use crate::auth::KeyStore;
 use crate::cli::config::load_config;
-use crate::storage::Store;
-use crate::mysql::MysqlClient;
+use crate::storage::{Store, Backend};
 use crate::server::Server;
 use crate::utils::runtime;
 
@@ -26,14 +25,20 @@ impl Api {
     pub fn parse(self) -> Result<String> {
         match self {
             Self::Start{ config } => {
-                let config = load_config(config);
+                let config = load_config(&config)?;
+                let ip: SocketAddr = config.listen_ip.parse()?;
+                // 启动前检查证书，避免服务在后台启动失败
+                #[cfg(feature = "tls")]
+                if let Some(tls) = &config.tls {
+                    crate::tls::load(tls)?;
+                }
+                let keys = match config.api_auth {
+                    true => Some(KeyStore::open(&config.api_keys_file)?),
+                    false => None,
+                };
                 runtime().block_on(async move {
-                    #[cfg(feature = "mysql")]
-                    let store = Store::<Testnet3, MysqlClient>::new(String::from(config.mysqldsn));
-                    let default = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 9898);
-                    let ip = SocketAddr::from_str(&config.listen_ip).unwrap_or(default);
-                    #[cfg(feature = "mysql")]
-                    Server::<Testnet3, MysqlClient>::start(ip, store);
+                    let store = Store::<Testnet3, Backend>::reader(config.storage_url());
+                    Server::<Testnet3, Backend>::start(ip, store, config.cache_size * 1024 * 1024, keys, config.rate_limits, config.api_docs, config.graphql_complexity, config.tls);
                     std::future::pending::<()>().await;
                 });
                 
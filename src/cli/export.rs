@@ -0,0 +1,107 @@
+use std::io::{BufWriter, Write};
+
+use clap::Parser;
+use anyhow::Result;
+
+use crate::cli::config::load_config;
+use crate::export::RewardExport;
+use crate::storage::{Store, Backend};
+use crate::utils::{parse_address, runtime};
+#[cfg(feature = "parquet")]
+use crate::{columnar::{ParquetExport, Partition}, utils::{parse_utc_offset, read_synced_height}};
+
+use snarkvm_console_network::Testnet3;
+
+/// Export stored data
+#[derive(Debug, Parser)]
+pub enum Export {
+    /// Export the solution rewards of an address, timestamp -> [from, to)
+    Rewards {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(long = "address")]
+        address: String,
+        #[clap(long = "from")]
+        from: i64,
+        #[clap(long = "to")]
+        to: i64,
+        #[clap(default_value = "csv", long = "format", possible_values = ["csv", "json", "jsonl"])]
+        format: String,
+        /// Aggregate the rewards per day
+        #[clap(long = "daily")]
+        daily: bool,
+        /// Convert microcredits to credits
+        #[clap(long = "credits")]
+        credits: bool,
+        /// Time zone of the day boundary, a utc offset like +08:00 or a zone name like Asia/Shanghai
+        #[clap(default_value = "+00:00", long = "tz", allow_hyphen_values = true)]
+        tz: String,
+        /// Write to the file instead of stdout
+        #[clap(long = "output")]
+        output: Option<String>,
+    },
+    /// Export blocks and block solutions to parquet partitions, incrementally from the last watermark
+    #[cfg(feature = "parquet")]
+    Parquet {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(long = "out-dir")]
+        out_dir: String,
+        #[clap(default_value = "height", long = "partition", possible_values = ["height", "day"])]
+        partition: String,
+        /// Heights per partition when partitioned by height
+        #[clap(default_value = "100000", long = "partition-size")]
+        partition_size: u32,
+        /// Fixed utc offset of the day boundary when partitioned by day, e.g. +08:00
+        #[clap(default_value = "+00:00", long = "tz", allow_hyphen_values = true)]
+        tz: String,
+    },
+}
+
+impl Export {
+    pub fn parse(self) -> Result<String> {
+        match self {
+            Self::Rewards { config, address, from, to, format, daily, credits, tz, output } => {
+                if from >= to {
+                    anyhow::bail!("from {from} must be less than to {to}");
+                }
+                let export = RewardExport {
+                    address: parse_address::<Testnet3>(&address)?.to_string(),
+                    begin: from,
+                    end: to,
+                    format: format.parse()?,
+                    daily,
+                    credits,
+                    tz: tz.parse()?,
+                };
+                let config = load_config(&config)?;
+                let store = Store::<Testnet3, Backend>::reader(config.storage_url());
+
+                let writer: Box<dyn Write> = match &output {
+                    Some(output) => Box::new(std::fs::File::create(output)?),
+                    None => Box::new(std::io::stdout().lock()),
+                };
+                let rows = runtime().block_on(export.run(&store, BufWriter::new(writer)))?;
+                match output {
+                    Some(output) => Ok(format!("{rows} rows written to {output}")),
+                    None => Ok(String::new()),
+                }
+            },
+            #[cfg(feature = "parquet")]
+            Self::Parquet { config, out_dir, partition, partition_size, tz } => {
+                let partition = match partition.as_str() {
+                    "day" => Partition::Day(parse_utc_offset(&tz)?),
+                    _ if partition_size == 0 => anyhow::bail!("partition-size must be greater than 0"),
+                    _ => Partition::Height(partition_size),
+                };
+                let config = load_config(&config)?;
+                let synced_height = read_synced_height(&config.synced_height_file)?;
+                let store = Store::<Testnet3, Backend>::reader(config.storage_url());
+
+                let export = ParquetExport { out_dir: out_dir.into(), partition };
+                let watermark = runtime().block_on(export.run(&store, synced_height))?;
+                Ok(format!("parquet exported below height {watermark}"))
+            },
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use clap::Parser;
+use anyhow::{bail, Result};
+
+use crate::cli::config::load_config;
+use crate::storage::{Store, Backend};
+use crate::utils::{parse_address, runtime};
+
+use snarkvm_console_network::Testnet3;
+
+/// Manage address groups and labels
+#[derive(Debug, Parser)]
+pub enum Group {
+    /// Create a group
+    Create {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(long = "name")]
+        name: String,
+        #[clap(default_value = "", long = "label")]
+        label: String,
+    },
+    /// Delete a group and its members
+    Delete {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(long = "name")]
+        name: String,
+    },
+    /// Add an address to a group, or update its label
+    Add {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(long = "name")]
+        name: String,
+        #[clap(long = "address")]
+        address: String,
+        #[clap(default_value = "", long = "label")]
+        label: String,
+    },
+    /// Remove an address from a group
+    Remove {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(long = "name")]
+        name: String,
+        #[clap(long = "address")]
+        address: String,
+    },
+    /// List groups and their members
+    List {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+    },
+}
+
+impl Group {
+    pub fn parse(self) -> Result<String> {
+        match self {
+            Self::Create { config, name, label } => {
+                let store = Self::store(&config)?;
+                if !runtime().block_on(store.create_group(&name, &label))? {
+                    bail!("group {name} already exists");
+                }
+                Ok(format!("group {name} created"))
+            },
+            Self::Delete { config, name } => {
+                let store = Self::store(&config)?;
+                if !runtime().block_on(store.delete_group(&name))? {
+                    bail!("group {name} not found");
+                }
+                Ok(format!("group {name} deleted"))
+            },
+            Self::Add { config, name, address, label } => {
+                let address = parse_address::<Testnet3>(&address)?.to_string();
+                let store = Self::store(&config)?;
+                if !runtime().block_on(store.add_group_member(&name, &address, &label))? {
+                    bail!("group {name} not found");
+                }
+                Ok(format!("{address} added to group {name}"))
+            },
+            Self::Remove { config, name, address } => {
+                let address = parse_address::<Testnet3>(&address)?.to_string();
+                let store = Self::store(&config)?;
+                if !runtime().block_on(store.remove_group_member(&name, &address))? {
+                    bail!("{address} is not a member of group {name}");
+                }
+                Ok(format!("{address} removed from group {name}"))
+            },
+            Self::List { config } => {
+                let store = Self::store(&config)?;
+                let mut output = String::new();
+                for group in runtime().block_on(store.get_groups())? {
+                    output.push_str(&format!("{} {}\n", group.name, group.label));
+                    for member in group.members {
+                        output.push_str(&format!("    {} {}\n", member.address, member.label));
+                    }
+                }
+                Ok(output)
+            },
+        }
+    }
+
+    fn store(config: &str) -> Result<Store<Testnet3, Backend>> {
+        let config = load_config(config)?;
+        Ok(Store::<Testnet3, Backend>::reader(config.storage_url()))
+    }
+}
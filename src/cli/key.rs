@@ -0,0 +1,74 @@
+use clap::Parser;
+use anyhow::{bail, Result};
+
+use snarkvm_console_network::Testnet3;
+
+use crate::auth::{KeyFile, Scope};
+use crate::cli::config::load_config;
+use crate::utils::parse_address;
+
+/// Manage the api keys
+#[derive(Debug, Parser)]
+pub enum Key {
+    /// Create a key, the key is only printed once
+    Create {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(default_value = "", long = "label")]
+        label: String,
+        /// *, address:<address> or group:<name>, repeat for several scopes
+        #[clap(long = "scope", required = true)]
+        scope: Vec<String>,
+    },
+    /// List the keys
+    List {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+    },
+    /// Revoke a key
+    Revoke {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(long = "id")]
+        id: String,
+    },
+}
+
+impl Key {
+    pub fn parse(self) -> Result<String> {
+        match self {
+            Self::Create { config, label, scope } => {
+                let scopes = scope.iter().map(|s| Self::scope(s)).collect::<Result<Vec<Scope>>>()?;
+                let (key, token) = Self::file(&config)?.create(&label, &scopes)?;
+                Ok(format!("key {} created, keep it safe, it can not be shown again:\n{token}", key.id))
+            },
+            Self::List { config } => {
+                let mut output = String::new();
+                for key in Self::file(&config)?.load()? {
+                    let status = if key.revoked { "revoked" } else { "active" };
+                    output.push_str(&format!("{} {} {} {} [{}]\n", key.id, status, key.created_at, key.label, key.scopes.join(", ")));
+                }
+                Ok(output)
+            },
+            Self::Revoke { config, id } => {
+                if !Self::file(&config)?.revoke(&id)? {
+                    bail!("key {id} not found or already revoked");
+                }
+                Ok(format!("key {id} revoked"))
+            },
+        }
+    }
+
+    /// Parses the scope, the address of an address scope is checked and stored in its canonical form.
+    fn scope(scope: &str) -> Result<Scope> {
+        match scope.parse()? {
+            Scope::Address(address) => Ok(Scope::Address(parse_address::<Testnet3>(&address)?.to_string())),
+            scope => Ok(scope),
+        }
+    }
+
+    fn file(config: &str) -> Result<KeyFile> {
+        let config = load_config(config)?;
+        Ok(KeyFile::new(&config.api_keys_file))
+    }
+}
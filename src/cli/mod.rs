@@ -4,7 +4,11 @@ use clap::AppSettings::ColoredHelp;
 
 mod sync;
 mod api;
-mod config;
+pub(crate) mod config;
+mod group;
+mod payout;
+mod export;
+mod key;
 
 #[derive(Debug, Parser)]
 #[clap(name = "aleo-tools", author = "https://github.com/labs3", setting = ColoredHelp)]
@@ -20,6 +24,16 @@ pub enum Command {
     Sync(sync::Sync),
     #[clap(subcommand)]
     Server(api::Api),
+    #[clap(subcommand)]
+    Config(config::ConfigCommand),
+    #[clap(subcommand)]
+    Group(group::Group),
+    #[clap(subcommand)]
+    Payout(payout::Payout),
+    #[clap(subcommand)]
+    Export(export::Export),
+    #[clap(subcommand)]
+    Key(key::Key),
 }
 
 impl Command {
@@ -27,6 +41,11 @@ impl Command {
         match self {
             Self::Sync(command) => command.parse(),
             Self::Server(command) => command.parse(),
+            Self::Config(command) => command.parse(),
+            Self::Group(command) => command.parse(),
+            Self::Payout(command) => command.parse(),
+            Self::Export(command) => command.parse(),
+            Self::Key(command) => command.parse(),
         }
     }
 }
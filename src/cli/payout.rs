@@ -0,0 +1,71 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+use anyhow::Result;
+
+use crate::cli::config::load_config;
+use crate::payout::{PayoutScheme, statement};
+use crate::storage::{Store, Backend};
+use crate::utils::runtime;
+
+use snarkvm_console_network::Testnet3;
+
+/// Pool payout ledger
+#[derive(Debug, Parser)]
+pub enum Payout {
+    /// Close a payout period and export its statement, closing a closed period exports the stored statement
+    Close {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(default_value = "payout.yml", long = "scheme")]
+        scheme: String,
+        /// Unix timestamp of the period start, aligned to the scheme period
+        #[clap(long = "period-start")]
+        period_start: i64,
+        #[clap(default_value = "csv", long = "format", possible_values = ["csv", "json"])]
+        format: String,
+        /// Write the statement to the file instead of stdout
+        #[clap(long = "output")]
+        output: Option<String>,
+    },
+    /// Preview the statement of a period without closing it
+    Preview {
+        #[clap(default_value = "config.yml", long = "config")]
+        config: String,
+        #[clap(default_value = "payout.yml", long = "scheme")]
+        scheme: String,
+        #[clap(long = "period-start")]
+        period_start: i64,
+        #[clap(default_value = "csv", long = "format", possible_values = ["csv", "json"])]
+        format: String,
+    },
+}
+
+impl Payout {
+    pub fn parse(self) -> Result<String> {
+        match self {
+            Self::Close { config, scheme, period_start, format, output } => {
+                let config = load_config(&config)?;
+                let scheme = PayoutScheme::load::<Testnet3>(&scheme)?;
+                let store = Store::<Testnet3, Backend>::reader(config.storage_url());
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                let entries = runtime().block_on(scheme.close(&store, period_start, now))?;
+                let statement = statement(&entries, &format)?;
+                match output {
+                    Some(output) => {
+                        std::fs::write(&output, statement)?;
+                        Ok(format!("statement written to {output}"))
+                    }
+                    None => Ok(statement),
+                }
+            },
+            Self::Preview { config, scheme, period_start, format } => {
+                let config = load_config(&config)?;
+                let scheme = PayoutScheme::load::<Testnet3>(&scheme)?;
+                let store = Store::<Testnet3, Backend>::reader(config.storage_url());
+                let entries = runtime().block_on(scheme.compute(&store, period_start))?;
+                statement(&entries, &format)
+            },
+        }
+    }
+}
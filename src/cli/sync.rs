@@ -3,14 +3,20 @@ use anyhow::Result;
 use log::{info, error};
 use memmap2::MmapMut;
 use tokio::sync::mpsc;
+use tokio::task::LocalSet;
 
 use crate::cli::config::{load_config, Config};
 use crate::manage::ApiManage;
-use crate::storage::Store;
-use crate::mysql::MysqlClient;
-use crate::utils::{runtime, open_file};
+use crate::watch::Watchlist;
+use crate::sink::{Fanout, SinkRunner};
+use crate::storage::{Store, Backend};
+use crate::utils::{runtime, open_file, parse_addresses};
 
 use snarkvm_console_network::Testnet3;
+use snarkvm_console_types_address::Address;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicU32;
+use std::sync::Arc;
 
 /// Sync block server and check or update block height file
 #[derive(Debug, Parser)]
@@ -38,10 +44,13 @@ impl Sync {
     pub fn parse(self) -> Result<String> {
         match self {
             Self::Start{ config } => {
-                let config = load_config(config);
-                info!("sync block data with address only: {:?}", &config.address);
+                let config_path = config;
+                let config = load_config(&config_path)?;
+                let address = parse_addresses::<Testnet3>(&config.address)?;
+                info!("sync block data with address only: {:?}", &address);
                 runtime().block_on(async move { 
-                    Self::sync(config).await;
+                    // 地址回填任务在本地任务集中运行
+                    LocalSet::new().run_until(Self::sync(config, config_path, address)).await;
                 });
                 
                 Ok(String::new())
@@ -68,12 +77,11 @@ impl Sync {
         }
     }
 
-    async fn sync(config: Config){
-        let apis = config.aleoapi;
+    async fn sync(config: Config, config_path: String, address: HashSet<Address<Testnet3>>){
+        let apis = config.aleoapi.clone();
         info!("use aleo api: {:?}", apis);
 
-        let address = config.address;
-        let file = open_file(config.synced_height_file);
+        let file = open_file(config.synced_height_file.clone());
         let mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
         // 获取已同步的高度
         let mut buf: [u8; 4] = [0, 0, 0, 0];
@@ -84,14 +92,49 @@ impl Sync {
         let client = reqwest::Client::builder().build().unwrap();
         let api_manager = ApiManage::new(client.clone(), apis);
         let (sender, receiver) = mpsc::channel(4096);
+        let address = Watchlist::new(address);
+        // 各同步任务共享每个api的批量大小和并发数
+        let adaptive = config.adaptive();
+
+        // kill -HUP 重新加载地址，并回填新增地址的历史solution
+        tokio::task::spawn_local(crate::watch::reload_on_sighup(
+            config_path,
+            address.clone(),
+            api_manager.clone(),
+            adaptive.clone(),
+            sender.clone(),
+        ));
     
-        #[cfg(feature = "mysql")]
-        let store = Store::<Testnet3, MysqlClient>::new(String::from(config.mysqldsn));
+        let store = Store::<Testnet3, Backend>::new(config.storage_url());
+
+        // 其他存储各自维护同步高度，落后时独立追赶
+        let primary_height = Arc::new(AtomicU32::new(latest_height));
+        let names = config.sinks.iter().map(|sink| sink.name.clone()).collect();
+        let (fanout, control) = Fanout::new(names, primary_height.clone());
+        for (index, sink) in config.sinks.iter().enumerate() {
+            let runner = SinkRunner::<Testnet3>::new(
+                index,
+                sink,
+                primary_height.clone(),
+                control.clone(),
+                api_manager.clone(),
+                address.clone(),
+                adaptive.clone(),
+                config.parse_options(),
+            );
+            match runner {
+                Ok(runner) => { tokio::task::spawn_local(runner.run()); },
+                Err(e) => {
+                    error!("open sink {} failed: {:?}", sink.name, e);
+                    return;
+                }
+            }
+        }
+        drop(control);
     
         // 消息处理
         tokio::spawn(async move {
-            #[cfg(feature = "mysql")]
-            crate::message::handle::<Testnet3, MysqlClient>(store, receiver, mmap).await;
+            crate::message::handle::<Testnet3, Backend>(store, receiver, mmap, fanout).await;
         });
 
           // 批量同步历史区块
@@ -99,11 +142,10 @@ impl Sync {
              api_manager.clone(),
             latest_height,
             None, 
-            &address, 
-            config.batch_request, 
-            config.batch_concurrent, 
+            address.clone(), 
+            adaptive.clone(),
             sender.clone(),
-            config.store_block,
+            config.parse_options(),
         );
         let latest_height = match batch_obj.get_blocks().await {
             Ok(height) => height,
@@ -117,9 +159,9 @@ impl Sync {
         let single_obj = crate::single::Single::<Testnet3>::new(
             api_manager.clone(),
             latest_height,
-            &address, 
+            address, 
             sender.clone(),
-            config.store_block,
+            config.parse_options(),
         );
         if let Err(e) = single_obj.get_blocks().await {
             error!("get_blocks_one_by_one: {:?}", e);
@@ -0,0 +1,569 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, DataType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+
+use snarkvm_console_network::Network;
+
+use crate::storage::{BlockRow, SolutionRow, Storage, Store};
+use crate::utils::{day_of, format_day};
+
+/// Heights read from storage per query, each query becomes one row group.
+const HEIGHT_CHUNK: u32 = 1000;
+/// The file holding the next height to export and the partitioning of the exported files.
+const WATERMARK_FILE: &str = "_watermark";
+
+const BLOCKS_SCHEMA: &str = "
+message block {
+    REQUIRED INT64 block_height;
+    REQUIRED BYTE_ARRAY block_hash (UTF8);
+    REQUIRED BYTE_ARRAY previous_block_hash (UTF8);
+    REQUIRED INT32 network;
+    REQUIRED INT64 coinbase_target (UINT_64);
+    REQUIRED INT64 proof_target (UINT_64);
+    REQUIRED INT64 last_coinbase_target (UINT_64);
+    REQUIRED INT64 last_coinbase_timestamp;
+    REQUIRED INT64 timestamp;
+    REQUIRED INT64 solutions_num;
+    REQUIRED INT64 block_reward (UINT_64);
+}";
+
+const SOLUTIONS_SCHEMA: &str = "
+message block_solution {
+    REQUIRED INT64 block_height;
+    REQUIRED BYTE_ARRAY address (UTF8);
+    REQUIRED INT64 nonce (UINT_64);
+    REQUIRED BYTE_ARRAY commitment (UTF8);
+    REQUIRED INT64 solution_reward (UINT_64);
+    REQUIRED INT64 timestamp;
+}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Partition {
+    /// Partitions of a fixed number of heights.
+    Height(u32),
+    /// Partitions of a day at the given utc offset in seconds, a day is exported once a later day is stored.
+    Day(i64),
+}
+
+/// The export progress, a later run must use the same partitioning to append to the same partitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Watermark {
+    height: u32,
+    partition: Partition,
+}
+
+/// Rows that can be written as one parquet row group.
+trait Columns: Sized {
+    const SCHEMA: &'static str;
+    const TABLE: &'static str;
+    fn height(&self) -> u32;
+    fn write(rows: &[Self], row_group: &mut SerializedRowGroupWriter<'_, File>) -> Result<()>;
+}
+
+fn write_column<T: DataType>(row_group: &mut SerializedRowGroupWriter<'_, File>, values: &[T::T]) -> Result<()> {
+    let mut column = row_group.next_column()?.ok_or_else(|| anyhow!("parquet schema has fewer columns than written"))?;
+    column.typed::<T>().write_batch(values, None, None)?;
+    column.close()?;
+    Ok(())
+}
+
+fn strings<'a>(values: impl Iterator<Item = &'a String>) -> Vec<ByteArray> {
+    values.map(|value| ByteArray::from(value.as_str())).collect()
+}
+
+impl Columns for BlockRow {
+    const SCHEMA: &'static str = BLOCKS_SCHEMA;
+    const TABLE: &'static str = "blocks";
+
+    fn height(&self) -> u32 { self.height }
+
+    fn write(rows: &[Self], row_group: &mut SerializedRowGroupWriter<'_, File>) -> Result<()> {
+        // u64 are stored as INT64 annotated UINT_64
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.height as i64).collect::<Vec<_>>())?;
+        write_column::<ByteArrayType>(row_group, &strings(rows.iter().map(|r| &r.hash)))?;
+        write_column::<ByteArrayType>(row_group, &strings(rows.iter().map(|r| &r.previous_hash)))?;
+        write_column::<Int32Type>(row_group, &rows.iter().map(|r| r.network as i32).collect::<Vec<_>>())?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.coinbase_target as i64).collect::<Vec<_>>())?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.proof_target as i64).collect::<Vec<_>>())?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.last_coinbase_target as i64).collect::<Vec<_>>())?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.last_coinbase_timestamp).collect::<Vec<_>>())?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.timestamp).collect::<Vec<_>>())?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.solutions_num as i64).collect::<Vec<_>>())?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.block_reward as i64).collect::<Vec<_>>())?;
+        Ok(())
+    }
+}
+
+impl Columns for SolutionRow {
+    const SCHEMA: &'static str = SOLUTIONS_SCHEMA;
+    const TABLE: &'static str = "block_solutions";
+
+    fn height(&self) -> u32 { self.height }
+
+    fn write(rows: &[Self], row_group: &mut SerializedRowGroupWriter<'_, File>) -> Result<()> {
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.height as i64).collect::<Vec<_>>())?;
+        write_column::<ByteArrayType>(row_group, &strings(rows.iter().map(|r| &r.address)))?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.nonce as i64).collect::<Vec<_>>())?;
+        write_column::<ByteArrayType>(row_group, &strings(rows.iter().map(|r| &r.commitment)))?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.reward as i64).collect::<Vec<_>>())?;
+        write_column::<Int64Type>(row_group, &rows.iter().map(|r| r.timestamp).collect::<Vec<_>>())?;
+        Ok(())
+    }
+}
+
+/// A partition file, written to a hidden temporary file and renamed once the partition is complete.
+struct PartitionFile<T: Columns> {
+    tmp: PathBuf,
+    path: PathBuf,
+    writer: SerializedFileWriter<File>,
+    rows: usize,
+    _t: std::marker::PhantomData<T>,
+}
+
+impl<T: Columns> PartitionFile<T> {
+    fn create(out_dir: &Path, partition: &str, start_height: u32) -> Result<Self> {
+        let dir = out_dir.join(T::TABLE).join(partition);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("part-{start_height:010}.parquet"));
+        let tmp = dir.join(format!(".part-{start_height:010}.parquet.tmp"));
+
+        let schema = Arc::new(parse_message_type(T::SCHEMA)?);
+        let props = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+        let writer = SerializedFileWriter::new(File::create(&tmp)?, schema, props)?;
+        Ok(Self { tmp, path, writer, rows: 0, _t: std::marker::PhantomData })
+    }
+
+    fn write(&mut self, rows: &[T]) -> Result<()> {
+        if rows.is_empty() {
+            return Ok(())
+        }
+        let mut row_group = self.writer.next_row_group()?;
+        T::write(rows, &mut row_group)?;
+        row_group.close()?;
+        self.rows += rows.len();
+        Ok(())
+    }
+
+    /// Publishes the file, partitions without rows are not written.
+    fn finish(self) -> Result<usize> {
+        self.writer.close()?;
+        if self.rows == 0 {
+            fs::remove_file(&self.tmp)?;
+            remove_empty_dir(&self.path);
+        } else {
+            fs::rename(&self.tmp, &self.path)?;
+        }
+        Ok(self.rows)
+    }
+
+    fn discard(self) -> Result<()> {
+        drop(self.writer);
+        fs::remove_file(&self.tmp)?;
+        remove_empty_dir(&self.path);
+        Ok(())
+    }
+}
+
+/// Removes the partition directory of the file if no other file is in it, readers take an empty directory as a partition.
+fn remove_empty_dir(path: &Path) {
+    if let Some(dir) = path.parent() {
+        let _ = fs::remove_dir(dir);
+    }
+}
+
+/// The blocks and solutions files of one partition.
+struct PartitionFiles {
+    name: String,
+    start_height: u32,
+    blocks: PartitionFile<BlockRow>,
+    solutions: PartitionFile<SolutionRow>,
+}
+
+impl PartitionFiles {
+    fn create(out_dir: &Path, name: String, start_height: u32) -> Result<Self> {
+        Ok(Self {
+            blocks: PartitionFile::create(out_dir, &name, start_height)?,
+            solutions: PartitionFile::create(out_dir, &name, start_height)?,
+            name,
+            start_height,
+        })
+    }
+
+    fn finish(self) -> Result<()> {
+        let blocks = self.blocks.finish()?;
+        let solutions = self.solutions.finish()?;
+        info!("exported partition {}: {} blocks, {} solutions", self.name, blocks, solutions);
+        Ok(())
+    }
+
+    fn discard(self) -> Result<()> {
+        self.blocks.discard()?;
+        self.solutions.discard()
+    }
+}
+
+/// Incremental parquet export of the blocks and block solutions tables.
+///
+/// Layout: `<out_dir>/<table>/<partition>/part-<start height>.parquet`, only complete partitions are written,
+/// so nightly runs append new partitions and never rewrite old ones.
+pub struct ParquetExport {
+    pub out_dir: PathBuf,
+    pub partition: Partition,
+}
+
+impl ParquetExport {
+    /// Reads the next height to export, a new export directory is initialized with the partitioning of this export.
+    ///
+    /// Exporting with another partitioning than the directory was exported with is refused, the new partitions
+    /// would not line up with the existing ones.
+    fn watermark(&self) -> Result<u32> {
+        let path = self.out_dir.join(WATERMARK_FILE);
+        let watermark: Watermark = match fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).with_context(|| format!(
+                "invalid parquet export watermark {}, an export of an older version has no partitioning, export to a new directory",
+                path.display()
+            ))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.set_watermark(0)?;
+                return Ok(0);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if watermark.partition != self.partition {
+            bail!(
+                "{} was exported with partition {:?}, not {:?}, export to a new directory to change the partitioning",
+                self.out_dir.display(), watermark.partition, self.partition
+            );
+        }
+        Ok(watermark.height)
+    }
+
+    fn set_watermark(&self, height: u32) -> Result<()> {
+        let tmp = self.out_dir.join(format!(".{WATERMARK_FILE}.tmp"));
+        fs::write(&tmp, serde_json::to_string(&Watermark { height, partition: self.partition })?)?;
+        fs::rename(tmp, self.out_dir.join(WATERMARK_FILE))?;
+        Ok(())
+    }
+
+    /// Exports the heights from the watermark up to the synced height, returns the new watermark.
+    pub async fn run<N: Network, S: Storage<N>>(&self, store: &Store<N, S>, synced_height: u32) -> Result<u32> {
+        fs::create_dir_all(&self.out_dir)?;
+        let start = self.watermark()?;
+        // 已同步高度及以下的数据是完整的
+        let end = synced_height.saturating_add(1);
+        info!("export parquet from height {} to {}", start, end);
+
+        match self.partition {
+            Partition::Height(size) => self.run_by_height(store, start, end, size).await,
+            Partition::Day(utc_offset) => self.run_by_day(store, start, end, utc_offset).await,
+        }
+    }
+
+    async fn run_by_height<N: Network, S: Storage<N>>(&self, store: &Store<N, S>, start: u32, end: u32, size: u32) -> Result<u32> {
+        let mut watermark = start;
+        let mut partition_start = start - start % size;
+        while partition_start + size <= end {
+            let partition_end = partition_start + size;
+            let mut files = PartitionFiles::create(&self.out_dir, format!("height_start={partition_start}"), partition_start)?;
+            for chunk_start in (partition_start..partition_end).step_by(HEIGHT_CHUNK as usize) {
+                let chunk_end = (chunk_start + HEIGHT_CHUNK).min(partition_end);
+                files.blocks.write(&store.get_blocks_by_height_range(chunk_start, chunk_end).await?)?;
+                files.solutions.write(&store.get_solutions_by_height_range(chunk_start, chunk_end).await?)?;
+            }
+            files.finish()?;
+            watermark = partition_end;
+            self.set_watermark(watermark)?;
+            partition_start = partition_end;
+        }
+        Ok(watermark)
+    }
+
+    async fn run_by_day<N: Network, S: Storage<N>>(&self, store: &Store<N, S>, start: u32, end: u32, utc_offset: i64) -> Result<u32> {
+        let mut watermark = start;
+        let mut current: Option<(i64, PartitionFiles)> = None;
+
+        for chunk_start in (start..end).step_by(HEIGHT_CHUNK as usize) {
+            let chunk_end = (chunk_start + HEIGHT_CHUNK).min(end);
+            let blocks = store.get_blocks_by_height_range(chunk_start, chunk_end).await?;
+            let solutions = store.get_solutions_by_height_range(chunk_start, chunk_end).await?;
+
+            // 按高度合并两张表的行，某个高度进入新的一天时，前一天的分区完成
+            // pending: 当前分区在本批次中尚未写入的行
+            let (mut b, mut s) = (0, 0);
+            let (mut pending_b, mut pending_s) = (0, 0);
+            while b < blocks.len() || s < solutions.len() {
+                let (height, timestamp) = match (blocks.get(b), solutions.get(s)) {
+                    (Some(block), Some(solution)) if solution.height < block.height => (solution.height, solution.timestamp),
+                    (Some(block), _) => (block.height, block.timestamp),
+                    (None, Some(solution)) => (solution.height, solution.timestamp),
+                    (None, None) => unreachable!(),
+                };
+
+                let day = day_of(timestamp, utc_offset);
+                if current.as_ref().is_none_or(|(current_day, _)| *current_day != day) {
+                    if let Some((_, mut files)) = current.take() {
+                        files.blocks.write(&blocks[pending_b..b])?;
+                        files.solutions.write(&solutions[pending_s..s])?;
+                        files.finish()?;
+                        watermark = height;
+                        self.set_watermark(watermark)?;
+                    }
+                    (pending_b, pending_s) = (b, s);
+                    current = Some((day, PartitionFiles::create(&self.out_dir, format!("day={}", format_day(day)), height)?));
+                }
+                b += blocks[b..].iter().take_while(|r| r.height() == height).count();
+                s += solutions[s..].iter().take_while(|r| r.height() == height).count();
+            }
+            if let Some((_, files)) = current.as_mut() {
+                files.blocks.write(&blocks[pending_b..])?;
+                files.solutions.write(&solutions[pending_s..])?;
+            }
+        }
+
+        // 最后一天可能尚未结束，下次从该天的起始高度重新导出
+        if let Some((_, files)) = current {
+            info!("partition {} from height {} is not complete yet", files.name, files.start_height);
+            files.discard()?;
+        }
+        Ok(watermark)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use anyhow::bail;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+    use snarkvm_console_network::Testnet3;
+
+    use super::*;
+    use crate::message::{BlockReward, Solution};
+    use crate::storage::{Group, GroupReward, LedgerEntry, Page, Paged, ProverRank, ProverShare, Reward};
+
+    /// 2023-06-30 00:00:00 utc
+    const START: i64 = 1_688_083_200;
+    /// A block a minute, 1440 a day.
+    const HEIGHTS: u32 = 5_000;
+
+    /// The first height of a day has solutions but no block, the day is cut at a solution.
+    fn block(height: u32) -> Option<BlockRow> {
+        (height == 0 || height % 1440 != 0).then(|| BlockRow {
+            height,
+            hash: format!("ab{height}"),
+            previous_hash: format!("ab{}", height.saturating_sub(1)),
+            network: 3,
+            coinbase_target: 1 << 20,
+            proof_target: 1 << 10,
+            last_coinbase_target: 1 << 20,
+            last_coinbase_timestamp: START,
+            timestamp: START + height as i64 * 60,
+            solutions_num: height % 4,
+            block_reward: 2_000_000 + height as u64,
+        })
+    }
+
+    fn solutions(height: u32) -> impl Iterator<Item = SolutionRow> {
+        let count = if height % 1440 == 0 { 2 } else { height % 4 };
+        (0..count).map(move |i| SolutionRow {
+            height,
+            address: format!("aleo1prover{i}"),
+            nonce: (height * 4 + i) as u64,
+            commitment: format!("commitment{height}_{i}"),
+            reward: 1_000_000 + i as u64,
+            timestamp: START + height as i64 * 60,
+        })
+    }
+
+    /// The blocks and solutions of 3.5 days.
+    struct Chain;
+
+    impl Storage<Testnet3> for Chain {
+        fn new(_url: String) -> Self { Self }
+        fn record_block(&self, _block: &BlockReward<Testnet3>) -> anyhow::Result<bool> { bail!("read only") }
+        fn record_solutions(&self, _solutions: &Solution<Testnet3>) -> anyhow::Result<bool> { bail!("read only") }
+        fn get_solutions_page(&self, _address: &str, _begin: i64, _end: i64, _page: &Page) -> anyhow::Result<Paged<Reward>> { bail!("unused") }
+        fn get_top_provers(&self, _begin: i64, _end: i64, _limit: u32) -> anyhow::Result<Vec<ProverRank>> { bail!("unused") }
+        fn get_prover_share(&self, _address: &str, _begin: i64, _end: i64) -> anyhow::Result<ProverShare> { bail!("unused") }
+        fn create_group(&self, _name: &str, _label: &str) -> anyhow::Result<bool> { bail!("unused") }
+        fn delete_group(&self, _name: &str) -> anyhow::Result<bool> { bail!("unused") }
+        fn add_group_member(&self, _name: &str, _address: &str, _label: &str) -> anyhow::Result<bool> { bail!("unused") }
+        fn remove_group_member(&self, _name: &str, _address: &str) -> anyhow::Result<bool> { bail!("unused") }
+        fn get_groups(&self) -> anyhow::Result<Vec<Group>> { bail!("unused") }
+        fn get_group_rewards(&self, _name: &str, _begin: i64, _end: i64) -> anyhow::Result<Option<GroupReward>> { bail!("unused") }
+        fn get_address_rewards(&self, _addresses: &[String], _begin: i64, _end: i64) -> anyhow::Result<Vec<ProverRank>> { bail!("unused") }
+        fn close_payout_period(&self, _scheme: &str, _period_start: i64, _period_end: i64, _entries: &[LedgerEntry]) -> anyhow::Result<bool> { bail!("unused") }
+        fn get_payout_period(&self, _scheme: &str, _period_start: i64) -> anyhow::Result<Option<Vec<LedgerEntry>>> { bail!("unused") }
+        fn get_blocks_by_height_range(&self, start: u32, end: u32) -> anyhow::Result<Vec<BlockRow>> {
+            Ok((start..end.min(HEIGHTS)).filter_map(block).collect())
+        }
+        fn get_solutions_by_height_range(&self, start: u32, end: u32) -> anyhow::Result<Vec<SolutionRow>> {
+            Ok((start..end.min(HEIGHTS)).flat_map(solutions).collect())
+        }
+        fn get_latest_timestamp(&self) -> anyhow::Result<Option<i64>> { bail!("unused") }
+        fn get_last_solution_id(&self) -> anyhow::Result<Option<u64>> { bail!("unused") }
+        fn get_earliest_timestamp_after(&self, _id: u64) -> anyhow::Result<Option<i64>> { bail!("unused") }
+    }
+
+    /// Partition names and the heights of their rows.
+    type Partitions = Vec<(String, Vec<u32>)>;
+
+    /// The `block_height` column of every file of the table, by partition.
+    fn read_heights(out_dir: &Path, table: &str) -> Partitions {
+        let mut partitions: Partitions = fs::read_dir(out_dir.join(table)).unwrap().map(|dir| {
+            let dir = dir.unwrap().path();
+            let mut heights = vec![];
+            for file in fs::read_dir(&dir).unwrap() {
+                let file = file.unwrap().path();
+                assert_eq!(file.extension().unwrap(), "parquet", "{} left behind", file.display());
+                let reader = SerializedFileReader::new(File::open(&file).unwrap()).unwrap();
+                let rows = reader.metadata().file_metadata().num_rows();
+                heights.extend(reader.get_row_iter(None).unwrap().map(|row| row.get_long(0).unwrap() as u32));
+                assert_eq!(rows as usize, heights.len());
+            }
+            (dir.file_name().unwrap().to_string_lossy().to_string(), heights)
+        }).collect();
+        partitions.sort();
+        partitions
+    }
+
+    /// The heights of the stored blocks and solutions in the range.
+    fn stored_heights(heights: Range<u32>) -> (Vec<u32>, Vec<u32>) {
+        (
+            heights.clone().filter_map(block).map(|b| b.height).collect(),
+            heights.flat_map(solutions).map(|s| s.height).collect(),
+        )
+    }
+
+    fn partition_files(out_dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files = vec![];
+        for table in ["blocks", "block_solutions"] {
+            for dir in fs::read_dir(out_dir.join(table)).unwrap() {
+                for file in fs::read_dir(dir.unwrap().path()).unwrap() {
+                    let path = file.unwrap().path();
+                    files.push((path.clone(), fs::read(path).unwrap()));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    fn export(name: &str, partition: Partition) -> ParquetExport {
+        let out_dir = std::env::temp_dir().join(format!("aleo-blocks-parquet-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out_dir);
+        fs::create_dir_all(&out_dir).unwrap();
+        ParquetExport { out_dir, partition }
+    }
+
+    #[test]
+    fn a_new_directory_starts_at_zero() {
+        let export = export("new", Partition::Height(1000));
+        assert_eq!(export.watermark().unwrap(), 0);
+        // 初始化时即记录分区方式
+        let saved: Watermark = serde_json::from_slice(&fs::read(export.out_dir.join(WATERMARK_FILE)).unwrap()).unwrap();
+        assert_eq!(saved, Watermark { height: 0, partition: Partition::Height(1000) });
+        fs::remove_dir_all(&export.out_dir).unwrap();
+    }
+
+    #[test]
+    fn the_watermark_round_trips() {
+        let export = export("round-trip", Partition::Day(8 * 3600));
+        export.set_watermark(123_456).unwrap();
+        assert_eq!(export.watermark().unwrap(), 123_456);
+        fs::remove_dir_all(&export.out_dir).unwrap();
+    }
+
+    #[test]
+    fn another_partitioning_is_refused() {
+        let first = export("mismatch", Partition::Height(1000));
+        first.set_watermark(5000).unwrap();
+
+        for partition in [Partition::Height(2000), Partition::Day(0)] {
+            let other = ParquetExport { out_dir: first.out_dir.clone(), partition };
+            assert!(other.watermark().is_err(), "{partition:?} accepted");
+        }
+        let day = export("mismatch-day", Partition::Day(0));
+        day.set_watermark(10).unwrap();
+        let shifted = ParquetExport { out_dir: day.out_dir.clone(), partition: Partition::Day(3600) };
+        assert!(shifted.watermark().is_err());
+
+        assert_eq!(first.watermark().unwrap(), 5000);
+        fs::remove_dir_all(&first.out_dir).unwrap();
+        fs::remove_dir_all(&day.out_dir).unwrap();
+    }
+
+    #[test]
+    fn a_watermark_without_partitioning_is_refused() {
+        let export = export("legacy", Partition::Height(1000));
+        fs::write(export.out_dir.join(WATERMARK_FILE), "5000").unwrap();
+        assert!(export.watermark().is_err());
+        fs::remove_dir_all(&export.out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn days_are_cut_where_the_next_day_begins() {
+        let store = Store::<Testnet3, Chain>::new(String::new());
+        let export = export("days", Partition::Day(0));
+        // 第三天在同步高度处尚未结束
+        assert_eq!(export.run(&store, 3599).await.unwrap(), 2880);
+        assert_eq!(export.watermark().unwrap(), 2880);
+
+        let (blocks, solutions) = (read_heights(&export.out_dir, "blocks"), read_heights(&export.out_dir, "block_solutions"));
+        for (i, (day, heights)) in [("day=2023-06-30", 0..1440), ("day=2023-07-01", 1440..2880)].into_iter().enumerate() {
+            let (stored_blocks, stored_solutions) = stored_heights(heights);
+            assert_eq!(blocks[i], (day.to_string(), stored_blocks));
+            assert_eq!(solutions[i], (day.to_string(), stored_solutions));
+        }
+        assert_eq!((blocks.len(), solutions.len()), (2, 2));
+        // 第二天从只有解的高度1440开始
+        assert!(export.out_dir.join("block_solutions/day=2023-07-01/part-0000001440.parquet").exists());
+
+        let exported = partition_files(&export.out_dir);
+        assert_eq!(export.run(&store, 4999).await.unwrap(), 4320);
+        let appended = partition_files(&export.out_dir);
+        assert_eq!(appended.len(), exported.len() + 2);
+        assert!(exported.iter().all(|file| appended.contains(file)));
+        let (stored_blocks, stored_solutions) = stored_heights(2880..4320);
+        assert_eq!(read_heights(&export.out_dir, "blocks")[2], ("day=2023-07-02".to_string(), stored_blocks));
+        assert_eq!(read_heights(&export.out_dir, "block_solutions")[2], ("day=2023-07-02".to_string(), stored_solutions));
+        fs::remove_dir_all(&export.out_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn height_partitions_span_several_chunks() {
+        let store = Store::<Testnet3, Chain>::new(String::new());
+        let export = export("heights", Partition::Height(1500));
+        assert_eq!(export.run(&store, 3599).await.unwrap(), 3000);
+
+        let expected = |partitions: &[u32]| -> (Partitions, Partitions) {
+            partitions.iter().map(|start| {
+                let (blocks, solutions) = stored_heights(*start..start + 1500);
+                let name = format!("height_start={start}");
+                ((name.clone(), blocks), (name, solutions))
+            }).unzip()
+        };
+        let (blocks, solutions) = expected(&[0, 1500]);
+        assert_eq!(read_heights(&export.out_dir, "blocks"), blocks);
+        assert_eq!(read_heights(&export.out_dir, "block_solutions"), solutions);
+
+        let exported = partition_files(&export.out_dir);
+        assert_eq!(export.run(&store, 4999).await.unwrap(), 4500);
+        assert!(exported.iter().all(|file| partition_files(&export.out_dir).contains(file)));
+        let (blocks, solutions) = expected(&[0, 1500, 3000]);
+        assert_eq!(read_heights(&export.out_dir, "blocks"), blocks);
+        assert_eq!(read_heights(&export.out_dir, "block_solutions"), solutions);
+        fs::remove_dir_all(&export.out_dir).unwrap();
+    }
+}
@@ -0,0 +1,337 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+
+use snarkvm_console_network::Network;
+
+use crate::storage::{Order, Page, Reward, Storage, Store};
+use crate::utils::{format_credits, format_day, DayZone};
+
+/// Rows read from storage per page, only one page is kept in memory.
+const EXPORT_PAGE_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => bail!("unknown format {s}, expected csv, json or jsonl"),
+        }
+    }
+}
+
+/// Options of the reward export, timestamp -> [begin, end).
+#[derive(Debug, Clone)]
+pub struct RewardExport {
+    pub address: String,
+    pub begin: i64,
+    pub end: i64,
+    pub format: Format,
+    /// Aggregates the rewards per day.
+    pub daily: bool,
+    /// Converts microcredits to credits, credits are written as decimal strings to keep the precision.
+    pub credits: bool,
+    /// Time zone of the day boundary.
+    pub tz: DayZone,
+}
+
+/// The rewards of one day.
+struct Day {
+    day: i64,
+    solutions: u64,
+    reward: u64,
+}
+
+/// Writes the rows one by one, wrapping json output into an array.
+struct RowWriter<W: Write> {
+    writer: W,
+    format: Format,
+    columns: &'static [&'static str],
+    rows: u64,
+}
+
+impl<W: Write> RowWriter<W> {
+    fn new(mut writer: W, format: Format, columns: &'static [&'static str]) -> Result<Self> {
+        match format {
+            Format::Csv => writeln!(writer, "{}", columns.join(","))?,
+            Format::Json => write!(writer, "[")?,
+            Format::Jsonl => {},
+        }
+        Ok(Self { writer, format, columns, rows: 0 })
+    }
+
+    /// `values` are in the order of the columns.
+    fn write(&mut self, values: Vec<Value>) -> Result<()> {
+        match self.format {
+            Format::Csv => {
+                let fields: Vec<String> = values.iter().map(|value| match value {
+                    Value::String(s) => s.clone(),
+                    value => value.to_string(),
+                }).collect();
+                writeln!(self.writer, "{}", fields.join(","))?;
+            }
+            Format::Json | Format::Jsonl => {
+                let object: serde_json::Map<String, Value> = self.columns.iter().map(|c| c.to_string()).zip(values).collect();
+                if self.format == Format::Json {
+                    write!(self.writer, "{}\n  ", if self.rows == 0 { "" } else { "," })?;
+                    serde_json::to_writer(&mut self.writer, &object)?;
+                } else {
+                    serde_json::to_writer(&mut self.writer, &object)?;
+                    writeln!(self.writer)?;
+                }
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<u64> {
+        if self.format == Format::Json {
+            writeln!(self.writer, "{}]", if self.rows == 0 { "" } else { "\n" })?;
+        }
+        self.writer.flush()?;
+        Ok(self.rows)
+    }
+}
+
+impl RewardExport {
+    fn amount(&self, microcredits: u64) -> Value {
+        if self.credits { json!(format_credits(microcredits)) } else { json!(microcredits) }
+    }
+
+    /// Streams the rewards of the address to the writer page by page, returns the number of written rows.
+    pub async fn run<N: Network, S: Storage<N>, W: Write>(&self, store: &Store<N, S>, writer: W) -> Result<u64> {
+        let mut rows = if self.daily {
+            RowWriter::new(writer, self.format, &["address", "day", "solutions", "reward"])?
+        } else {
+            RowWriter::new(writer, self.format, &["address", "height", "nonce", "reward", "timestamp"])?
+        };
+
+        let mut page = Page { limit: EXPORT_PAGE_LIMIT, cursor: None, order: Order::Asc };
+        let mut current: Option<Day> = None;
+        loop {
+            let result = store.get_solutions_page(&self.address, self.begin, self.end, &page).await?;
+            for reward in &result.items {
+                if self.daily {
+                    // 按时间升序读取，日期变化时输出前一天
+                    let day = self.tz.day_of(reward.timestamp);
+                    match current.as_mut() {
+                        Some(current) if current.day == day => {
+                            current.solutions += 1;
+                            current.reward += reward.reward;
+                        }
+                        _ => {
+                            if let Some(previous) = current.replace(Day { day, solutions: 1, reward: reward.reward }) {
+                                self.write_day(&mut rows, &previous)?;
+                            }
+                        }
+                    }
+                } else {
+                    self.write_reward(&mut rows, reward)?;
+                }
+            }
+            match result.next {
+                Some(next) => page.cursor = Some(next),
+                None => break,
+            }
+        }
+        if let Some(last) = current {
+            self.write_day(&mut rows, &last)?;
+        }
+        rows.finish()
+    }
+
+    fn write_reward<W: Write>(&self, rows: &mut RowWriter<W>, reward: &Reward) -> Result<()> {
+        rows.write(vec![
+            json!(reward.address),
+            json!(reward.height),
+            json!(reward.nonce),
+            self.amount(reward.reward),
+            json!(reward.timestamp),
+        ])
+    }
+
+    fn write_day<W: Write>(&self, rows: &mut RowWriter<W>, day: &Day) -> Result<()> {
+        rows.write(vec![
+            json!(self.address),
+            json!(format_day(day.day)),
+            json!(day.solutions),
+            self.amount(day.reward),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+    use snarkvm_console_network::Testnet3;
+
+    use super::*;
+    use crate::message::{BlockReward, Solution};
+    use crate::storage::{BlockRow, Cursor, Group, GroupReward, LedgerEntry, Paged, ProverRank, ProverShare, SolutionRow};
+
+    const PROVER: &str = "aleo1prover";
+    /// 2023-06-30 00:00:00 utc
+    const START: i64 = 1_688_083_200;
+    /// A solution every 2 minutes, 720 a day, every 4th one of another prover.
+    const SOLUTIONS: u64 = 2_600;
+
+    /// Solutions of 3.6 days, the prover has two pages of them.
+    struct Rewards;
+
+    fn solution(i: u64) -> Reward {
+        Reward {
+            id: i + 1,
+            address: if i % 4 == 3 { "aleo1other".to_string() } else { PROVER.to_string() },
+            height: (i / 5) as u32,
+            nonce: i,
+            reward: 1_000_000 + i,
+            timestamp: START + i as i64 * 120,
+        }
+    }
+
+    impl Storage<Testnet3> for Rewards {
+        fn new(_url: String) -> Self { Self }
+        fn record_block(&self, _block: &BlockReward<Testnet3>) -> anyhow::Result<bool> { bail!("read only") }
+        fn record_solutions(&self, _solutions: &Solution<Testnet3>) -> anyhow::Result<bool> { bail!("read only") }
+        fn get_solutions_page(&self, address: &str, begin: i64, end: i64, page: &Page) -> anyhow::Result<Paged<Reward>> {
+            let after = |reward: &Reward| match page.cursor {
+                Some(Cursor { timestamp, id }) => (reward.timestamp, reward.id) > (timestamp, id),
+                None => true,
+            };
+            let rows = (0..SOLUTIONS).map(solution)
+                .filter(|reward| reward.address == address && reward.timestamp >= begin && reward.timestamp < end && after(reward))
+                .take(page.limit as usize + 1)
+                .collect();
+            Ok(Paged::from_rows(rows, page.limit))
+        }
+        fn get_top_provers(&self, _begin: i64, _end: i64, _limit: u32) -> anyhow::Result<Vec<ProverRank>> { bail!("unused") }
+        fn get_prover_share(&self, _address: &str, _begin: i64, _end: i64) -> anyhow::Result<ProverShare> { bail!("unused") }
+        fn create_group(&self, _name: &str, _label: &str) -> anyhow::Result<bool> { bail!("unused") }
+        fn delete_group(&self, _name: &str) -> anyhow::Result<bool> { bail!("unused") }
+        fn add_group_member(&self, _name: &str, _address: &str, _label: &str) -> anyhow::Result<bool> { bail!("unused") }
+        fn remove_group_member(&self, _name: &str, _address: &str) -> anyhow::Result<bool> { bail!("unused") }
+        fn get_groups(&self) -> anyhow::Result<Vec<Group>> { bail!("unused") }
+        fn get_group_rewards(&self, _name: &str, _begin: i64, _end: i64) -> anyhow::Result<Option<GroupReward>> { bail!("unused") }
+        fn get_address_rewards(&self, _addresses: &[String], _begin: i64, _end: i64) -> anyhow::Result<Vec<ProverRank>> { bail!("unused") }
+        fn close_payout_period(&self, _scheme: &str, _period_start: i64, _period_end: i64, _entries: &[LedgerEntry]) -> anyhow::Result<bool> { bail!("unused") }
+        fn get_payout_period(&self, _scheme: &str, _period_start: i64) -> anyhow::Result<Option<Vec<LedgerEntry>>> { bail!("unused") }
+        fn get_blocks_by_height_range(&self, _start: u32, _end: u32) -> anyhow::Result<Vec<BlockRow>> { bail!("unused") }
+        fn get_solutions_by_height_range(&self, _start: u32, _end: u32) -> anyhow::Result<Vec<SolutionRow>> { bail!("unused") }
+        fn get_latest_timestamp(&self) -> anyhow::Result<Option<i64>> { bail!("unused") }
+        fn get_last_solution_id(&self) -> anyhow::Result<Option<u64>> { bail!("unused") }
+        fn get_earliest_timestamp_after(&self, _id: u64) -> anyhow::Result<Option<i64>> { bail!("unused") }
+    }
+
+    fn export(format: Format, daily: bool, credits: bool, tz: &str) -> RewardExport {
+        RewardExport {
+            address: PROVER.to_string(),
+            begin: START,
+            end: START + 10 * 86_400,
+            format,
+            daily,
+            credits,
+            tz: tz.parse().unwrap(),
+        }
+    }
+
+    async fn run(export: &RewardExport) -> (u64, String) {
+        let store = Store::<Testnet3, Rewards>::new(String::new());
+        let mut output = vec![];
+        let rows = export.run(&store, &mut output).await.unwrap();
+        (rows, String::from_utf8(output).unwrap())
+    }
+
+    /// The solutions of the prover as `(nonce, reward)`.
+    fn prover_solutions() -> Vec<(u64, u64)> {
+        (0..SOLUTIONS).filter(|i| i % 4 != 3).map(|i| (i, 1_000_000 + i)).collect()
+    }
+
+    #[tokio::test]
+    async fn every_solution_of_every_page_is_written_once() {
+        let solutions = prover_solutions();
+        assert!(solutions.len() > EXPORT_PAGE_LIMIT as usize);
+
+        let (rows, output) = run(&export(Format::Jsonl, false, false, "Z")).await;
+        assert_eq!(rows, solutions.len() as u64);
+        let written: Vec<(u64, u64)> = output.lines().map(|line| {
+            let row: Value = serde_json::from_str(line).unwrap();
+            assert_eq!(row["address"], PROVER);
+            (row["nonce"].as_u64().unwrap(), row["reward"].as_u64().unwrap())
+        }).collect();
+        assert_eq!(written, solutions);
+    }
+
+    #[tokio::test]
+    async fn days_roll_up_across_page_boundaries() {
+        let (rows, output) = run(&export(Format::Csv, true, false, "+00:00")).await;
+        // 每天720个解，其中540个属于该地址，最后一天只有440个解
+        let day = |i: u64| -> u64 { (i * 720..(i * 720 + 720).min(SOLUTIONS)).filter(|i| i % 4 != 3).map(|i| 1_000_000 + i).sum() };
+        assert_eq!(rows, 4);
+        assert_eq!(output, format!(
+            "address,day,solutions,reward\n{PROVER},2023-06-30,540,{}\n{PROVER},2023-07-01,540,{}\n{PROVER},2023-07-02,540,{}\n{PROVER},2023-07-03,330,{}\n",
+            day(0), day(1), day(2), day(3),
+        ));
+    }
+
+    #[tokio::test]
+    async fn days_begin_at_the_offset_of_the_zone() {
+        let (_, fixed) = run(&export(Format::Jsonl, true, false, "+08:00")).await;
+        let (_, zone) = run(&export(Format::Jsonl, true, false, "Asia/Shanghai")).await;
+        assert_eq!(fixed, zone);
+
+        let days: Vec<(String, u64)> = fixed.lines().map(|line| {
+            let row: Value = serde_json::from_str(line).unwrap();
+            (row["day"].as_str().unwrap().to_string(), row["solutions"].as_u64().unwrap())
+        }).collect();
+        // utc+8 的第一天在 16:00 utc 结束，即前480个解
+        assert_eq!(days, vec![
+            ("2023-06-30".to_string(), 360),
+            ("2023-07-01".to_string(), 540),
+            ("2023-07-02".to_string(), 540),
+            ("2023-07-03".to_string(), 510),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn json_is_one_array() {
+        let (rows, output) = run(&export(Format::Json, true, false, "Z")).await;
+        let days: Vec<Value> = serde_json::from_str(&output).unwrap();
+        assert_eq!((rows, days.len()), (4, 4));
+        assert_eq!(days[0]["day"], "2023-06-30");
+
+        let mut empty = export(Format::Json, false, false, "Z");
+        empty.begin = START - 86_400;
+        empty.end = START;
+        let (rows, output) = run(&empty).await;
+        assert_eq!(rows, 0);
+        assert_eq!(output, "[]\n");
+        assert!(serde_json::from_str::<Vec<Value>>(&output).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn credits_are_exact_decimal_strings() {
+        let (_, output) = run(&export(Format::Jsonl, false, true, "Z")).await;
+        let first: Value = serde_json::from_str(output.lines().next().unwrap()).unwrap();
+        assert_eq!(first["reward"], "1.000000");
+        let last: Value = serde_json::from_str(output.lines().last().unwrap()).unwrap();
+        assert_eq!(last["reward"], "1.002598");
+
+        let (_, output) = run(&export(Format::Csv, true, true, "Z")).await;
+        let first_day: u64 = (0..720).filter(|i| i % 4 != 3).map(|i| 1_000_000 + i).sum();
+        assert_eq!(output.lines().nth(1).unwrap(), format!("{PROVER},2023-06-30,540,{}", format_credits(first_day)));
+        assert_eq!(format_credits(first_day), "540.193860");
+    }
+}
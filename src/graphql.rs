@@ -0,0 +1,456 @@
+use std::sync::Arc;
+
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Enum, ErrorExtensions, Object, Schema, SimpleObject};
+use futures::future::{try_join_all, BoxFuture};
+use log::error;
+
+use snarkvm_console_network::Network;
+
+use crate::auth::Scopes;
+use crate::server::{canonical_address, ApiError};
+use crate::storage::{BlockRow, Cursor, Order, Page, Paged, ProverShare, Reward, SolutionRow, Storage, Store};
+
+/// The default and maximum number of items of a list field.
+const DEFAULT_FIRST: u32 = 10;
+const MAX_FIRST: u32 = 100;
+/// The complexity of the solutions of one block, a block has a bounded number of solutions.
+const BLOCK_SOLUTIONS_COMPLEXITY: usize = 50;
+const MAX_DEPTH: usize = 8;
+/// The most solution pages `recentBlocks` reads, a prover with more solutions in its latest blocks gets fewer blocks.
+const MAX_RECENT_PAGES: usize = 10;
+
+/// The queries of the graphql api, object safe so the schema does not depend on the network and the storage.
+pub trait Source: Send + Sync {
+    fn canonical_address(&self, address: &str) -> Result<String, ApiError>;
+    fn check_address<'a>(&'a self, scopes: &'a Scopes, address: &'a str) -> BoxFuture<'a, Result<(), ApiError>>;
+    fn blocks(&self, start: u32, end: u32) -> BoxFuture<'_, anyhow::Result<Vec<BlockRow>>>;
+    fn solutions(&self, start: u32, end: u32) -> BoxFuture<'_, anyhow::Result<Vec<SolutionRow>>>;
+    fn solutions_page<'a>(&'a self, address: &'a str, begin: i64, end: i64, page: Page) -> BoxFuture<'a, anyhow::Result<Paged<Reward>>>;
+    fn prover_share<'a>(&'a self, address: &'a str, begin: i64, end: i64) -> BoxFuture<'a, anyhow::Result<ProverShare>>;
+}
+
+impl<N: Network, S: Storage<N>> Source for Store<N, S> {
+    fn canonical_address(&self, address: &str) -> Result<String, ApiError> {
+        canonical_address::<N>(address)
+    }
+
+    fn check_address<'a>(&'a self, scopes: &'a Scopes, address: &'a str) -> BoxFuture<'a, Result<(), ApiError>> {
+        Box::pin(scopes.check_address(address, self))
+    }
+
+    fn blocks(&self, start: u32, end: u32) -> BoxFuture<'_, anyhow::Result<Vec<BlockRow>>> {
+        Box::pin(self.get_blocks_by_height_range(start, end))
+    }
+
+    fn solutions(&self, start: u32, end: u32) -> BoxFuture<'_, anyhow::Result<Vec<SolutionRow>>> {
+        Box::pin(self.get_solutions_by_height_range(start, end))
+    }
+
+    fn solutions_page<'a>(&'a self, address: &'a str, begin: i64, end: i64, page: Page) -> BoxFuture<'a, anyhow::Result<Paged<Reward>>> {
+        Box::pin(async move { self.get_solutions_page(address, begin, end, &page).await })
+    }
+
+    fn prover_share<'a>(&'a self, address: &'a str, begin: i64, end: i64) -> BoxFuture<'a, anyhow::Result<ProverShare>> {
+        Box::pin(async move { self.get_prover_share(address, begin, end).await })
+    }
+}
+
+pub type ApiSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// Builds the schema, a query over `complexity` or nested deeper than `MAX_DEPTH` is refused before it runs.
+pub fn schema(source: Arc<dyn Source>, complexity: usize) -> ApiSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(source)
+        .limit_complexity(complexity)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// Converts the api error into a graphql error with the same code in its extensions.
+fn graphql_error(error: ApiError) -> async_graphql::Error {
+    if let ApiError::Storage(e) = &error {
+        error!("storage error: {:?}", e);
+    }
+    let code = error.code();
+    async_graphql::Error::new(error.to_string()).extend_with(|_, extensions| extensions.set("code", code))
+}
+
+fn storage_error(error: anyhow::Error) -> async_graphql::Error {
+    graphql_error(ApiError::Storage(error))
+}
+
+fn source<'a>(ctx: &Context<'a>) -> &'a Arc<dyn Source> {
+    ctx.data_unchecked::<Arc<dyn Source>>()
+}
+
+fn scopes<'a>(ctx: &Context<'a>) -> &'a Scopes {
+    ctx.data_unchecked::<Arc<Scopes>>()
+}
+
+fn page_size(first: Option<u32>) -> u32 {
+    first.unwrap_or(DEFAULT_FIRST).clamp(1, MAX_FIRST)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl From<SortOrder> for Order {
+    fn from(order: SortOrder) -> Self {
+        match order {
+            SortOrder::Asc => Order::Asc,
+            SortOrder::Desc => Order::Desc,
+        }
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// The block at the height, null if it is not synced yet.
+    async fn block(&self, ctx: &Context<'_>, height: u32) -> async_graphql::Result<Option<Block>> {
+        let mut blocks = source(ctx).blocks(height, height.saturating_add(1)).await.map_err(storage_error)?;
+        Ok(blocks.pop().map(Block))
+    }
+
+    /// The synced blocks from the height upwards.
+    #[graphql(complexity = "page_size(first) as usize * child_complexity")]
+    async fn blocks(&self, ctx: &Context<'_>, from: u32, first: Option<u32>) -> async_graphql::Result<Vec<Block>> {
+        let end = from.saturating_add(page_size(first));
+        let blocks = source(ctx).blocks(from, end).await.map_err(storage_error)?;
+        Ok(blocks.into_iter().map(Block).collect())
+    }
+
+    /// A prover in the scopes of the api key.
+    async fn prover(&self, ctx: &Context<'_>, address: String) -> async_graphql::Result<Prover> {
+        let source = source(ctx);
+        let address = source.canonical_address(&address).map_err(graphql_error)?;
+        source.check_address(scopes(ctx), &address).await.map_err(graphql_error)?;
+        Ok(Prover { address })
+    }
+}
+
+pub struct Block(BlockRow);
+
+#[Object]
+impl Block {
+    async fn height(&self) -> u32 {
+        self.0.height
+    }
+
+    async fn hash(&self) -> &str {
+        &self.0.hash
+    }
+
+    async fn previous_hash(&self) -> &str {
+        &self.0.previous_hash
+    }
+
+    async fn timestamp(&self) -> i64 {
+        self.0.timestamp
+    }
+
+    async fn coinbase_target(&self) -> u64 {
+        self.0.coinbase_target
+    }
+
+    async fn proof_target(&self) -> u64 {
+        self.0.proof_target
+    }
+
+    async fn solutions_num(&self) -> u32 {
+        self.0.solutions_num
+    }
+
+    /// The block reward in microcredits.
+    async fn block_reward(&self) -> u64 {
+        self.0.block_reward
+    }
+
+    /// The solutions of every prover in the block, requires the * scope.
+    #[graphql(complexity = "BLOCK_SOLUTIONS_COMPLEXITY * child_complexity")]
+    async fn solutions(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Solution>> {
+        scopes(ctx).require_all().map_err(graphql_error)?;
+        let height = self.0.height;
+        let solutions = source(ctx).solutions(height, height + 1).await.map_err(storage_error)?;
+        Ok(solutions.into_iter().map(Solution).collect())
+    }
+}
+
+pub struct Solution(SolutionRow);
+
+#[Object]
+impl Solution {
+    async fn height(&self) -> u32 {
+        self.0.height
+    }
+
+    async fn address(&self) -> &str {
+        &self.0.address
+    }
+
+    async fn nonce(&self) -> u64 {
+        self.0.nonce
+    }
+
+    async fn commitment(&self) -> &str {
+        &self.0.commitment
+    }
+
+    /// The reward in microcredits.
+    async fn reward(&self) -> u64 {
+        self.0.reward
+    }
+
+    async fn timestamp(&self) -> i64 {
+        self.0.timestamp
+    }
+}
+
+/// A solution of a prover, with the block it is in.
+pub struct ProverSolution(Reward);
+
+#[Object]
+impl ProverSolution {
+    async fn height(&self) -> u32 {
+        self.0.height
+    }
+
+    async fn nonce(&self) -> u64 {
+        self.0.nonce
+    }
+
+    /// The reward in microcredits.
+    async fn reward(&self) -> u64 {
+        self.0.reward
+    }
+
+    async fn timestamp(&self) -> i64 {
+        self.0.timestamp
+    }
+
+    async fn block(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Block>> {
+        let height = self.0.height;
+        let mut blocks = source(ctx).blocks(height, height + 1).await.map_err(storage_error)?;
+        Ok(blocks.pop().map(Block))
+    }
+}
+
+/// A page of solutions, pass `cursor` as `after` to get the next page, null on the last page.
+#[derive(SimpleObject)]
+pub struct SolutionPage {
+    items: Vec<ProverSolution>,
+    cursor: Option<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct Share {
+    solutions: u64,
+    reward: u64,
+    total_solutions: u64,
+    total_reward: u64,
+    solutions_share: f64,
+    reward_share: f64,
+}
+
+impl From<ProverShare> for Share {
+    fn from(share: ProverShare) -> Self {
+        Self {
+            solutions: share.solutions,
+            reward: share.reward,
+            total_solutions: share.total_solutions,
+            total_reward: share.total_reward,
+            solutions_share: share.solutions_share,
+            reward_share: share.reward_share,
+        }
+    }
+}
+
+pub struct Prover {
+    address: String,
+}
+
+#[Object]
+impl Prover {
+    async fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// The share of the prover in the solutions and rewards of [from, to).
+    async fn share(&self, ctx: &Context<'_>, from: i64, to: i64) -> async_graphql::Result<Share> {
+        if from >= to {
+            return Err(graphql_error(ApiError::InvalidRange { begin: from, end: to }));
+        }
+        let share = source(ctx).prover_share(&self.address, from, to).await.map_err(storage_error)?;
+        Ok(share.into())
+    }
+
+    /// The solutions of the prover in [from, to), paged by cursor.
+    #[graphql(complexity = "page_size(first) as usize * child_complexity")]
+    async fn solutions(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<i64>,
+        to: Option<i64>,
+        first: Option<u32>,
+        after: Option<String>,
+        order: Option<SortOrder>,
+    ) -> async_graphql::Result<SolutionPage> {
+        let (begin, end) = (from.unwrap_or(0), to.unwrap_or(i64::MAX));
+        if begin >= end {
+            return Err(graphql_error(ApiError::InvalidRange { begin, end }));
+        }
+        let cursor = after.as_deref()
+            .map(str::parse::<Cursor>)
+            .transpose()
+            .map_err(|e| graphql_error(ApiError::InvalidParam(e.to_string())))?;
+        let page = Page {
+            limit: page_size(first),
+            cursor,
+            order: order.map(Order::from).unwrap_or_default(),
+        };
+        let result = source(ctx).solutions_page(&self.address, begin, end, page).await.map_err(storage_error)?;
+        Ok(SolutionPage {
+            items: result.items.into_iter().map(ProverSolution).collect(),
+            cursor: result.next.map(|c| c.to_string()),
+        })
+    }
+
+    /// The latest blocks the prover has solutions in, newest first, found among its latest 1000 solutions.
+    #[graphql(complexity = "page_size(first) as usize * child_complexity")]
+    async fn recent_blocks(&self, ctx: &Context<'_>, first: Option<u32>) -> async_graphql::Result<Vec<Block>> {
+        let source = source(ctx);
+        let limit = page_size(first);
+        let mut heights = Vec::new();
+        let mut cursor = None;
+        // 同一区块可能有多个solution，按页读取直到凑够区块数，最多读MAX_RECENT_PAGES页
+        for _ in 0..MAX_RECENT_PAGES {
+            if heights.len() >= limit as usize {
+                break;
+            }
+            let page = Page { limit: MAX_FIRST, cursor, order: Order::Desc };
+            let result = source.solutions_page(&self.address, 0, i64::MAX, page).await.map_err(storage_error)?;
+            for reward in result.items {
+                if heights.last() != Some(&reward.height) && heights.len() < limit as usize {
+                    heights.push(reward.height);
+                }
+            }
+            match result.next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        let blocks = try_join_all(heights.iter().map(|height| source.blocks(*height, height + 1))).await.map_err(storage_error)?;
+        Ok(blocks.into_iter().flatten().map(Block).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// The solutions of one prover, `solutions` per block in the blocks `1..=blocks`.
+    struct Solutions {
+        rewards: Vec<Reward>,
+        pages: AtomicUsize,
+    }
+
+    impl Solutions {
+        fn new(blocks: u32, solutions: u32) -> Arc<Self> {
+            let rewards = (1..=blocks).flat_map(|height| (0..solutions).map(move |i| Reward {
+                id: (height * solutions + i) as u64,
+                address: String::from("aleo1abc"),
+                height,
+                nonce: i as u64,
+                reward: 1,
+                timestamp: height as i64,
+            })).collect();
+            Arc::new(Self { rewards, pages: AtomicUsize::new(0) })
+        }
+    }
+
+    impl Source for Solutions {
+        fn canonical_address(&self, address: &str) -> Result<String, ApiError> {
+            Ok(address.to_string())
+        }
+
+        fn check_address<'a>(&'a self, _scopes: &'a Scopes, _address: &'a str) -> BoxFuture<'a, Result<(), ApiError>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn blocks(&self, start: u32, _end: u32) -> BoxFuture<'_, anyhow::Result<Vec<BlockRow>>> {
+            let block = BlockRow {
+                height: start,
+                hash: format!("ab1{start}"),
+                previous_hash: format!("ab1{}", start - 1),
+                network: 3,
+                coinbase_target: 1,
+                proof_target: 1,
+                last_coinbase_target: 1,
+                last_coinbase_timestamp: 0,
+                timestamp: start as i64,
+                solutions_num: 0,
+                block_reward: 0,
+            };
+            Box::pin(async move { Ok(vec![block]) })
+        }
+
+        fn solutions(&self, _start: u32, _end: u32) -> BoxFuture<'_, anyhow::Result<Vec<SolutionRow>>> {
+            Box::pin(async { Ok(vec![]) })
+        }
+
+        fn solutions_page<'a>(&'a self, _address: &'a str, _begin: i64, _end: i64, page: Page) -> BoxFuture<'a, anyhow::Result<Paged<Reward>>> {
+            self.pages.fetch_add(1, Ordering::SeqCst);
+            // 只支持倒序
+            let items = self.rewards.iter().rev()
+                .filter(|r| page.cursor.is_none_or(|c| (r.timestamp, r.id) < (c.timestamp, c.id)))
+                .take(page.limit as usize + 1)
+                .map(|r| Reward { id: r.id, address: r.address.clone(), height: r.height, nonce: r.nonce, reward: r.reward, timestamp: r.timestamp })
+                .collect();
+            Box::pin(async move { Ok(Paged::from_rows(items, page.limit)) })
+        }
+
+        fn prover_share<'a>(&'a self, _address: &'a str, _begin: i64, _end: i64) -> BoxFuture<'a, anyhow::Result<ProverShare>> {
+            Box::pin(async { anyhow::bail!("not used") })
+        }
+    }
+
+    async fn recent_heights(source: Arc<Solutions>, first: u32) -> serde_json::Value {
+        let query = format!("{{ prover(address: \"aleo1abc\") {{ recentBlocks(first: {first}) {{ height }} }} }}");
+        let response = schema(source, 100_000).execute(async_graphql::Request::new(query).data(Arc::new(Scopes::all()))).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data.into_json().unwrap()
+    }
+
+    fn heights(heights: &[u32]) -> serde_json::Value {
+        json!({"prover": {"recentBlocks": heights.iter().map(|height| json!({"height": height})).collect::<Vec<_>>()}})
+    }
+
+    #[tokio::test]
+    async fn recent_blocks_are_distinct_newest_first() {
+        let source = Solutions::new(50, 3);
+        assert_eq!(recent_heights(source.clone(), 5).await, heights(&[50, 49, 48, 47, 46]));
+        assert_eq!(source.pages.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn recent_blocks_stop_at_the_last_page() {
+        let source = Solutions::new(2, 150);
+        assert_eq!(recent_heights(source.clone(), 5).await, heights(&[2, 1]));
+        assert_eq!(source.pages.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn recent_blocks_read_a_bounded_number_of_pages() {
+        let source = Solutions::new(3, 1000);
+        assert_eq!(recent_heights(source.clone(), 5).await, heights(&[3]));
+        assert_eq!(source.pages.load(Ordering::SeqCst), MAX_RECENT_PAGES);
+    }
+}
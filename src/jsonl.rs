@@ -0,0 +1,643 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use parking_lot::{Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+
+use snarkvm_console_network::Network;
+
+use crate::message::{BlockReward, Message, Solution};
+use crate::storage::{
+    BlockRow, Group, GroupReward, LedgerEntry, Order, Page, Paged, ProverRank, ProverShare, Reward, SolutionRow, Storage,
+};
+
+/// Files are rotated on the next block boundary once they reach this size.
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+const INDEX_FILE: &str = "index.json";
+const SOLUTIONS_PREFIX: &str = "solutions";
+const BLOCKS_PREFIX: &str = "blocks";
+
+/// A solution line.
+#[derive(Debug, Serialize, Deserialize)]
+struct SolutionLine {
+    id: u64,
+    #[serde(flatten)]
+    row: SolutionRow,
+}
+
+/// Bounds of one file, persisted on every block boundary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileIndex {
+    name: String,
+    /// Length at the last block boundary, anything after it is discarded on open.
+    bytes: u64,
+    rows: u64,
+    min_height: u32,
+    max_height: u32,
+    min_timestamp: i64,
+    max_timestamp: i64,
+}
+
+impl FileIndex {
+    fn new(name: String) -> Self {
+        Self { name, bytes: 0, rows: 0, min_height: u32::MAX, max_height: 0, min_timestamp: i64::MAX, max_timestamp: i64::MIN }
+    }
+
+    fn add(&mut self, height: u32, timestamp: i64) {
+        self.rows += 1;
+        self.min_height = self.min_height.min(height);
+        self.max_height = self.max_height.max(height);
+        self.min_timestamp = self.min_timestamp.min(timestamp);
+        self.max_timestamp = self.max_timestamp.max(timestamp);
+    }
+
+    // timestamp -> [begin, end)
+    fn overlaps_time(&self, begin: i64, end: i64) -> bool {
+        self.rows > 0 && self.min_timestamp < end && self.max_timestamp >= begin
+    }
+
+    // height -> [start, end)
+    fn overlaps_height(&self, start: u32, end: u32) -> bool {
+        self.rows > 0 && self.min_height < end && self.max_height >= start
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Index {
+    next_id: u64,
+    solutions: Vec<FileIndex>,
+    blocks: Vec<FileIndex>,
+}
+
+/// The file being appended, always the last one of its index list.
+struct Appender {
+    writer: BufWriter<File>,
+    bytes: u64,
+}
+
+impl Appender {
+    /// Opens the file and truncates what was written after the last block boundary.
+    fn open(path: &Path, bytes: u64) -> Result<Self> {
+        let file = OpenOptions::new().create(true).truncate(false).read(true).write(true).open(path)?;
+        file.set_len(bytes)?;
+        let mut writer = BufWriter::new(file);
+        std::io::Seek::seek(&mut writer, std::io::SeekFrom::Start(bytes))?;
+        Ok(Self { writer, bytes })
+    }
+
+    fn append<T: Serialize>(&mut self, line: &T) -> Result<()> {
+        let mut data = serde_json::to_vec(line)?;
+        data.push(b'\n');
+        self.writer.write_all(&data)?;
+        self.bytes += data.len() as u64;
+        Ok(())
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        Ok(())
+    }
+}
+
+struct Inner {
+    index: Index,
+    solutions: Appender,
+    blocks: Appender,
+}
+
+/// Stores solutions and blocks as JSON Lines files in a directory, a lightweight alternative to a database.
+///
+/// Lines are appended to rotating `solutions-*.jsonl` and `blocks-*.jsonl` files, which are fsynced on every
+/// block boundary together with `index.json`. The index keeps the height and timestamp bounds of every file,
+/// so queries only scan the files overlapping the requested range.
+///
+/// The sync process is the only writer. Other processes, e.g. the api, open the directory read only: they never
+/// touch the files and read `index.json` again on every query, so they see the blocks synced since they started.
+pub struct JsonlStore {
+    dir: PathBuf,
+    /// `None` when opened read only.
+    writer: Option<Mutex<Inner>>,
+}
+
+fn read_index(dir: &Path) -> Result<Index> {
+    match fs::read(dir.join(INDEX_FILE)) {
+        Ok(data) => serde_json::from_slice(&data).context("invalid jsonl index"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Index::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl JsonlStore {
+    /// Opens the directory as its writer, a torn tail written after the last block boundary is truncated.
+    pub fn open(dir: &str) -> Result<Self> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create jsonl dir {}", dir.display()))?;
+        let mut index = read_index(&dir)?;
+        if index.solutions.is_empty() {
+            index.solutions.push(FileIndex::new(format!("{SOLUTIONS_PREFIX}-000000.jsonl")));
+        }
+        if index.blocks.is_empty() {
+            index.blocks.push(FileIndex::new(format!("{BLOCKS_PREFIX}-000000.jsonl")));
+        }
+
+        let solutions = index.solutions.last().unwrap();
+        let solutions = Appender::open(&dir.join(&solutions.name), solutions.bytes)?;
+        let blocks = index.blocks.last().unwrap();
+        let blocks = Appender::open(&dir.join(&blocks.name), blocks.bytes)?;
+        Ok(Self { dir, writer: Some(Mutex::new(Inner { index, solutions, blocks })) })
+    }
+
+    /// Opens the directory without writing anything, the directory may not exist until the sync creates it.
+    pub fn open_read_only(dir: &str) -> Result<Self> {
+        Ok(Self { dir: PathBuf::from(dir), writer: None })
+    }
+
+    fn writer(&self) -> Result<MutexGuard<'_, Inner>> {
+        match &self.writer {
+            Some(inner) => Ok(inner.lock()),
+            None => bail!("the jsonl storage {} is opened read only", self.dir.display()),
+        }
+    }
+
+    /// The index of the synced lines, read from `index.json` when opened read only.
+    fn index(&self) -> Result<Index> {
+        match &self.writer {
+            Some(inner) => Ok(inner.lock().index.clone()),
+            None => read_index(&self.dir),
+        }
+    }
+
+    fn write_index(&self, index: &Index) -> Result<()> {
+        let tmp = self.dir.join(format!("{INDEX_FILE}.tmp"));
+        let mut file = File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec(index)?)?;
+        file.sync_data()?;
+        fs::rename(tmp, self.dir.join(INDEX_FILE))?;
+        Ok(())
+    }
+
+    /// Reads the lines of the files accepted by `filter`, up to their synced length.
+    fn scan<T: for<'de> Deserialize<'de>>(&self, files: Vec<FileIndex>, mut f: impl FnMut(T)) -> Result<()> {
+        for file in files {
+            let reader = BufReader::new(File::open(self.dir.join(&file.name))?);
+            let mut read = 0_u64;
+            for line in reader.lines() {
+                let line = line?;
+                read += line.len() as u64 + 1;
+                if read > file.bytes {
+                    break;
+                }
+                f(serde_json::from_str(&line).with_context(|| format!("invalid line in {}", file.name))?);
+            }
+        }
+        Ok(())
+    }
+
+    // timestamp -> [begin, end)
+    fn scan_solutions(&self, begin: i64, end: i64, mut f: impl FnMut(SolutionLine)) -> Result<()> {
+        let files = self.index()?.solutions.into_iter().filter(|i| i.overlaps_time(begin, end)).collect();
+        self.scan(files, |line: SolutionLine| {
+            if line.row.timestamp >= begin && line.row.timestamp < end {
+                f(line);
+            }
+        })
+    }
+
+    /// The recorded commitments among the heights of the solutions, solutions above every recorded height need no scan.
+    fn recorded_commitments<N: Network>(&self, messages: &[Message<N>]) -> Result<HashSet<String>> {
+        let heights: Vec<u32> = messages.iter().filter_map(|message| match message {
+            Message::Solution(solution) => Some(solution.block_height),
+            _ => None,
+        }).collect();
+        let (start, end) = match (heights.iter().min(), heights.iter().max()) {
+            (Some(start), Some(end)) => (*start, end.saturating_add(1)),
+            _ => return Ok(HashSet::new()),
+        };
+        let files = self.index()?.solutions.into_iter().filter(|i| i.overlaps_height(start, end)).collect();
+        let mut recorded = HashSet::new();
+        self.scan(files, |line: SolutionLine| {
+            if line.row.height >= start && line.row.height < end {
+                recorded.insert(line.row.commitment);
+            }
+        })?;
+        Ok(recorded)
+    }
+
+    /// The solutions of the address in [begin, end), ordered by (timestamp, id).
+    fn solutions_of(&self, address: &str, begin: i64, end: i64) -> Result<Vec<Reward>> {
+        let mut result = Vec::new();
+        self.scan_solutions(begin, end, |line| {
+            if line.row.address == address {
+                result.push(line.into());
+            }
+        })?;
+        result.sort_by_key(|r: &Reward| (r.timestamp, r.id));
+        Ok(result)
+    }
+
+    fn unsupported<T>(&self, what: &str) -> Result<T> {
+        bail!("{what} is not supported by the jsonl storage")
+    }
+}
+
+impl<N: Network> Storage<N> for JsonlStore {
+    fn new(url: String) -> Self {
+        Self::open(&url).unwrap()
+    }
+
+    fn new_reader(url: String) -> Self {
+        Self::open_read_only(&url).unwrap()
+    }
+
+    fn record_block(&self, block: &BlockReward<N>) -> Result<bool> {
+        let row = BlockRow {
+            height: block.block.height(),
+            hash: block.block.hash().to_string(),
+            previous_hash: block.block.previous_hash().to_string(),
+            network: block.block.network(),
+            coinbase_target: block.block.coinbase_target(),
+            proof_target: block.block.proof_target(),
+            last_coinbase_target: block.block.last_coinbase_target(),
+            last_coinbase_timestamp: block.block.last_coinbase_timestamp(),
+            timestamp: block.block.timestamp(),
+            solutions_num: block.solutions_num as u32,
+            block_reward: block.block_reward,
+        };
+        let mut inner = self.writer()?;
+        inner.blocks.append(&row)?;
+        inner.index.blocks.last_mut().unwrap().add(row.height, row.timestamp);
+        Ok(true)
+    }
+
+    fn record_solutions(&self, solution: &Solution<N>) -> Result<bool> {
+        let mut inner = self.writer()?;
+        let line = SolutionLine {
+            id: inner.index.next_id,
+            row: SolutionRow {
+                height: solution.block_height,
+                address: solution.partial_solution.address().to_string(),
+                nonce: solution.partial_solution.nonce(),
+                commitment: solution.partial_solution.commitment().to_string(),
+                reward: solution.solution_reward,
+                timestamp: solution.timestamp,
+            },
+        };
+        inner.solutions.append(&line)?;
+        inner.index.next_id += 1;
+        inner.index.solutions.last_mut().unwrap().add(line.row.height, line.row.timestamp);
+        Ok(true)
+    }
+
+    // 回填的solution可能已经记录过，跳过已有的commitment
+    fn record_batch(&self, messages: &[Message<N>]) -> Result<()> {
+        let recorded = self.recorded_commitments(messages)?;
+        for message in messages {
+            match message {
+                Message::Solution(solution) => {
+                    if !recorded.contains(&solution.partial_solution.commitment().to_string()) {
+                        self.record_solutions(solution)?;
+                    }
+                }
+                Message::BlockReward(block) => { self.record_block(block)?; }
+                Message::SyncHeight(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    // block boundary: fsync, persist the index, and rotate the full files
+    fn flush(&self) -> Result<()> {
+        let mut guard = self.writer()?;
+        let inner = &mut *guard;
+        inner.solutions.sync()?;
+        inner.blocks.sync()?;
+        inner.index.solutions.last_mut().unwrap().bytes = inner.solutions.bytes;
+        inner.index.blocks.last_mut().unwrap().bytes = inner.blocks.bytes;
+
+        if inner.solutions.bytes >= MAX_FILE_SIZE {
+            let name = format!("{SOLUTIONS_PREFIX}-{:06}.jsonl", inner.index.solutions.len());
+            inner.solutions = Appender::open(&self.dir.join(&name), 0)?;
+            inner.index.solutions.push(FileIndex::new(name));
+        }
+        if inner.blocks.bytes >= MAX_FILE_SIZE {
+            let name = format!("{BLOCKS_PREFIX}-{:06}.jsonl", inner.index.blocks.len());
+            inner.blocks = Appender::open(&self.dir.join(&name), 0)?;
+            inner.index.blocks.push(FileIndex::new(name));
+        }
+        self.write_index(&inner.index)
+    }
+
+    fn get_solutions_page(&self, address: &str, begin: i64, end: i64, page: &Page) -> Result<Paged<Reward>> {
+        let mut items = self.solutions_of(address, begin, end)?;
+        if page.order == Order::Desc {
+            items.reverse();
+        }
+        if let Some(cursor) = page.cursor {
+            let key = (cursor.timestamp, cursor.id);
+            items.retain(|r| match page.order {
+                Order::Asc => (r.timestamp, r.id) > key,
+                Order::Desc => (r.timestamp, r.id) < key,
+            });
+        }
+        Ok(Paged::from_rows(items, page.limit))
+    }
+
+    fn get_top_provers(&self, begin: i64, end: i64, limit: u32) -> Result<Vec<ProverRank>> {
+        let mut totals: HashMap<String, (u64, u64)> = HashMap::new();
+        self.scan_solutions(begin, end, |line| {
+            let total = totals.entry(line.row.address).or_default();
+            total.0 += 1;
+            total.1 += line.row.reward;
+        })?;
+        let mut result: Vec<ProverRank> = totals.into_iter()
+            .map(|(address, (solutions, reward))| ProverRank { address, solutions, reward })
+            .collect();
+        result.sort_by(|a, b| b.reward.cmp(&a.reward).then(b.solutions.cmp(&a.solutions)));
+        result.truncate(limit as usize);
+        Ok(result)
+    }
+
+    fn get_prover_share(&self, address: &str, begin: i64, end: i64) -> Result<ProverShare> {
+        let (mut solutions, mut reward, mut total_solutions, mut total_reward) = (0_u64, 0_u64, 0_u64, 0_u64);
+        self.scan_solutions(begin, end, |line| {
+            if line.row.address == address {
+                solutions += 1;
+                reward += line.row.reward;
+            }
+            total_solutions += 1;
+            total_reward += line.row.reward;
+        })?;
+        let share = |part: u64, total: u64| if total == 0 { 0.0 } else { part as f64 / total as f64 };
+        Ok(ProverShare {
+            address: address.to_string(),
+            solutions,
+            reward,
+            total_solutions,
+            total_reward,
+            solutions_share: share(solutions, total_solutions),
+            reward_share: share(reward, total_reward),
+        })
+    }
+
+    fn create_group(&self, _name: &str, _label: &str) -> Result<bool> {
+        self.unsupported("address group")
+    }
+
+    fn delete_group(&self, _name: &str) -> Result<bool> {
+        self.unsupported("address group")
+    }
+
+    fn add_group_member(&self, _name: &str, _address: &str, _label: &str) -> Result<bool> {
+        self.unsupported("address group")
+    }
+
+    fn remove_group_member(&self, _name: &str, _address: &str) -> Result<bool> {
+        self.unsupported("address group")
+    }
+
+    fn get_groups(&self) -> Result<Vec<Group>> {
+        self.unsupported("address group")
+    }
+
+    fn get_group_rewards(&self, _name: &str, _begin: i64, _end: i64) -> Result<Option<GroupReward>> {
+        self.unsupported("address group")
+    }
+
+    fn get_address_rewards(&self, addresses: &[String], begin: i64, end: i64) -> Result<Vec<ProverRank>> {
+        let mut totals: HashMap<String, (u64, u64)> = HashMap::new();
+        self.scan_solutions(begin, end, |line| {
+            if addresses.contains(&line.row.address) {
+                let total = totals.entry(line.row.address).or_default();
+                total.0 += 1;
+                total.1 += line.row.reward;
+            }
+        })?;
+        Ok(totals.into_iter().map(|(address, (solutions, reward))| ProverRank { address, solutions, reward }).collect())
+    }
+
+    fn close_payout_period(&self, _scheme: &str, _period_start: i64, _period_end: i64, _entries: &[LedgerEntry]) -> Result<bool> {
+        self.unsupported("payout ledger")
+    }
+
+    fn get_payout_period(&self, _scheme: &str, _period_start: i64) -> Result<Option<Vec<LedgerEntry>>> {
+        self.unsupported("payout ledger")
+    }
+
+    fn get_blocks_by_height_range(&self, start: u32, end: u32) -> Result<Vec<BlockRow>> {
+        let files = self.index()?.blocks.into_iter().filter(|i| i.overlaps_height(start, end)).collect();
+        let mut result = Vec::new();
+        self.scan(files, |row: BlockRow| {
+            if row.height >= start && row.height < end {
+                result.push(row);
+            }
+        })?;
+        result.sort_by_key(|row| row.height);
+        Ok(result)
+    }
+
+    fn get_solutions_by_height_range(&self, start: u32, end: u32) -> Result<Vec<SolutionRow>> {
+        let files = self.index()?.solutions.into_iter().filter(|i| i.overlaps_height(start, end)).collect();
+        let mut result = Vec::new();
+        self.scan(files, |line: SolutionLine| {
+            if line.row.height >= start && line.row.height < end {
+                result.push(line);
+            }
+        })?;
+        result.sort_by_key(|line| (line.row.height, line.id));
+        Ok(result.into_iter().map(|line| line.row).collect())
+    }
+
+    fn get_latest_timestamp(&self) -> Result<Option<i64>> {
+        let index = self.index()?;
+        let files = index.solutions.iter().chain(index.blocks.iter());
+        Ok(files.filter(|i| i.rows > 0).map(|i| i.max_timestamp).max())
+    }
+
+    fn get_last_solution_id(&self) -> Result<Option<u64>> {
+        Ok(self.index()?.next_id.checked_sub(1))
+    }
+
+    // id按文件顺序递增，之后的文件直接取索引的最小时间戳，只扫描跨过id的文件
+    fn get_earliest_timestamp_after(&self, id: u64) -> Result<Option<i64>> {
+        let index = self.index()?;
+        let mut earliest: Option<i64> = None;
+        let mut file_end = index.next_id;
+        let mut partial = Vec::new();
+        for file in index.solutions.iter().rev() {
+            let file_start = file_end.saturating_sub(file.rows);
+            if file_start > id {
+                if file.rows > 0 {
+                    earliest = Some(earliest.map_or(file.min_timestamp, |t| t.min(file.min_timestamp)));
+                }
+            } else {
+                if file_end > id + 1 {
+                    partial.push(file.clone());
+                }
+                break;
+            }
+            file_end = file_start;
+        }
+        self.scan(partial, |line: SolutionLine| {
+            if line.id > id {
+                earliest = Some(earliest.map_or(line.row.timestamp, |t| t.min(line.row.timestamp)));
+            }
+        })?;
+        Ok(earliest)
+    }
+}
+
+impl From<SolutionLine> for Reward {
+    fn from(line: SolutionLine) -> Self {
+        Self {
+            id: line.id,
+            address: line.row.address,
+            height: line.row.height,
+            nonce: line.row.nonce,
+            reward: line.row.reward,
+            timestamp: line.row.timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use snarkvm_console_network::Testnet3;
+
+    use super::*;
+
+    fn dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("aleo-blocks-jsonl-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn block(height: u32) -> BlockRow {
+        BlockRow {
+            height,
+            hash: format!("ab1{height}"),
+            previous_hash: format!("ab1{}", height.saturating_sub(1)),
+            network: 3,
+            coinbase_target: 1,
+            proof_target: 1,
+            last_coinbase_target: 1,
+            last_coinbase_timestamp: 0,
+            timestamp: 1_000 + height as i64,
+            solutions_num: 0,
+            block_reward: 0,
+        }
+    }
+
+    /// Appends the block rows and flushes them as one block boundary.
+    fn append(store: &JsonlStore, heights: std::ops::Range<u32>) {
+        {
+            let mut inner = store.writer().unwrap();
+            for height in heights {
+                let row = block(height);
+                inner.blocks.append(&row).unwrap();
+                inner.index.blocks.last_mut().unwrap().add(row.height, row.timestamp);
+            }
+        }
+        Storage::<Testnet3>::flush(store).unwrap();
+    }
+
+    /// Appends solutions at the timestamps, a new file for every list, and flushes every list as a block boundary.
+    fn append_solutions(store: &JsonlStore, files: &[&[i64]]) {
+        for (i, timestamps) in files.iter().enumerate() {
+            {
+                let mut inner = store.writer().unwrap();
+                if i > 0 {
+                    let name = format!("{SOLUTIONS_PREFIX}-{:06}.jsonl", inner.index.solutions.len());
+                    inner.solutions = Appender::open(&store.dir.join(&name), 0).unwrap();
+                    inner.index.solutions.push(FileIndex::new(name));
+                }
+                for timestamp in timestamps.iter() {
+                    let line = SolutionLine {
+                        id: inner.index.next_id,
+                        row: SolutionRow {
+                            height: 1,
+                            address: "aleo1prover".to_string(),
+                            nonce: inner.index.next_id,
+                            commitment: format!("commitment{}", inner.index.next_id),
+                            reward: 1,
+                            timestamp: *timestamp,
+                        },
+                    };
+                    inner.solutions.append(&line).unwrap();
+                    inner.index.next_id += 1;
+                    inner.index.solutions.last_mut().unwrap().add(line.row.height, line.row.timestamp);
+                }
+            }
+            Storage::<Testnet3>::flush(store).unwrap();
+        }
+    }
+
+    fn heights(store: &JsonlStore) -> Vec<u32> {
+        Storage::<Testnet3>::get_blocks_by_height_range(store, 0, u32::MAX).unwrap().iter().map(|b| b.height).collect()
+    }
+
+    #[test]
+    fn a_reader_sees_the_blocks_flushed_after_it_opened() {
+        let dir = dir("reader");
+        let writer = JsonlStore::open(&dir).unwrap();
+        let reader = JsonlStore::open_read_only(&dir).unwrap();
+        assert_eq!(heights(&reader), Vec::<u32>::new());
+        append(&writer, 0..3);
+        assert_eq!(heights(&reader), vec![0, 1, 2]);
+        append(&writer, 3..5);
+        assert_eq!(heights(&reader), vec![0, 1, 2, 3, 4]);
+        assert_eq!(Storage::<Testnet3>::get_latest_timestamp(&reader).unwrap(), Some(1_004));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_reader_never_writes() {
+        let dir = dir("read-only");
+        let reader = JsonlStore::open_read_only(&dir).unwrap();
+        assert!(!Path::new(&dir).exists());
+        assert!(Storage::<Testnet3>::flush(&reader).is_err());
+        assert_eq!(heights(&reader), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn only_the_writer_truncates_a_torn_tail() {
+        let dir = dir("torn");
+        let writer = JsonlStore::open(&dir).unwrap();
+        append(&writer, 0..2);
+        drop(writer);
+
+        // 写入中途崩溃留下的半行
+        let path = Path::new(&dir).join(format!("{BLOCKS_PREFIX}-000000.jsonl"));
+        let synced = fs::metadata(&path).unwrap().len();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"height\":2,").unwrap();
+
+        let reader = JsonlStore::open_read_only(&dir).unwrap();
+        assert_eq!(heights(&reader), vec![0, 1]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), synced + 12);
+
+        let writer = JsonlStore::open(&dir).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), synced);
+        append(&writer, 2..3);
+        assert_eq!(heights(&reader), vec![0, 1, 2]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_earliest_timestamp_after_an_id_spans_the_files() {
+        let dir = dir("recorded-after");
+        let writer = JsonlStore::open(&dir).unwrap();
+        let reader = JsonlStore::open_read_only(&dir).unwrap();
+        assert_eq!(Storage::<Testnet3>::get_last_solution_id(&reader).unwrap(), None);
+        assert_eq!(Storage::<Testnet3>::get_earliest_timestamp_after(&reader, 0).unwrap(), None);
+
+        // 回填的solution时间戳早于之前记录的
+        append_solutions(&writer, &[&[100, 90, 110], &[120, 130]]);
+        assert_eq!(Storage::<Testnet3>::get_last_solution_id(&reader).unwrap(), Some(4));
+        let earliest: Vec<Option<i64>> = (0..5).map(|id| Storage::<Testnet3>::get_earliest_timestamp_after(&reader, id).unwrap()).collect();
+        assert_eq!(earliest, vec![Some(90), Some(110), Some(120), Some(130), None]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -4,13 +4,34 @@ use clap::Parser;
 mod single;
 mod batch;
 mod parse;
+mod recover;
+mod reward;
+#[cfg(feature = "mysql")]
 mod mysql;
+#[cfg(feature = "jsonl")]
+mod jsonl;
 mod storage;
 mod utils;
 mod message;
 mod server;
+mod cache;
+mod auth;
+mod ratelimit;
+mod metrics;
+mod openapi;
+mod graphql;
+mod tls;
 mod cli;
 mod manage;
+mod adaptive;
+mod watch;
+mod sink;
+mod payout;
+mod export;
+#[cfg(test)]
+mod mock;
+#[cfg(feature = "parquet")]
+mod columnar;
 
 fn main() {
     if let Err(e) = std::env::var("RUST_LOG") {
@@ -2,6 +2,7 @@ use reqwest::{Client, Response};
 use log::{error, debug};
 use crate::utils::{backoffset, from_reqwest_err};
 use backoff::future::retry;
+use backoff::ExponentialBackoff;
 use std::collections::VecDeque;
 use std::cell::RefCell;
 
@@ -9,40 +10,75 @@ use std::cell::RefCell;
 pub struct ApiManage {
     client: Client,
     apis: RefCell<VecDeque<String>>,
+    backoff: ExponentialBackoff,
     _len: usize,
 }
 
 impl ApiManage {
     pub fn new(client: Client, apis: Vec<String>) -> Self {
-        Self {client, _len: apis.len(), apis: RefCell::new(VecDeque::from(apis))}
+        Self {client, _len: apis.len(), apis: RefCell::new(VecDeque::from(apis)), backoff: backoffset()}
     }
 
-    pub async fn get(&self, url_path: &str) -> anyhow::Result<Response> {
+    /// Replaces the retry policy of every api before failing over, tests use a short one.
+    #[cfg(test)]
+    pub fn with_backoff(mut self, backoff: ExponentialBackoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Returns the api currently in use, requests fail over to the next one after the retries are exhausted.
+    pub fn current(&self) -> String {
+        self.apis.borrow().front().cloned().unwrap_or_default()
+    }
+
+    /// Returns every api, the one in use first.
+    pub fn apis(&self) -> Vec<String> {
+        self.apis.borrow().iter().cloned().collect()
+    }
+
+    /// Moves to the next api when `api`, whose answer was unusable, is still the one in use.
+    pub fn fail_over(&self, api: &str) {
+        let mut apis_queue = self.apis.borrow_mut();
+        if apis_queue.front().map(String::as_str) == Some(api) {
+            let first = apis_queue.pop_front().unwrap();
+            apis_queue.push_back(first);
+        }
+    }
+
+    /// Fetches from the given api only, without failing over to the others.
+    pub async fn get_from(&self, api: &str, url_path: &str) -> anyhow::Result<Response> {
+        let url = format!("{}{}", api, url_path);
+        retry(self.backoff.clone(), || async {
+            self.client.get(&url).send().await.map_err(from_reqwest_err)
+        }).await.map_err(|error| anyhow::anyhow!("failed to fetch {url}: {error}"))
+    }
+
+    /// Fetches from the api in use, failing over until one answers. Returns the api that answered with its response.
+    pub async fn get(&self, url_path: &str) -> anyhow::Result<(String, Response)> {
+        self.get_reporting(url_path, |_| {}).await
+    }
+
+    /// Like `get`, `failed` is called with every api given up on before the one that answered.
+    pub async fn get_reporting(&self, url_path: &str, failed: impl Fn(&str)) -> anyhow::Result<(String, Response)> {
         {
             debug!("{:?}", self.apis.borrow());
         }
         // loop {} 无限循环，直到有一个api可用
         // for _ in 0..self.len {  // 否则遍历完api，如果此时在batch阶段，遇错传递返回，进程就终止了。而在single阶段，调用方则不断continue重试
         loop {
-            let apis_queue = self.apis.borrow();
-            let api = apis_queue.front().unwrap();
-            let url = format!("{}{}", *api, url_path);
-            drop(apis_queue);
+            let api = self.current();
+            let url = format!("{}{}", api, url_path);
 
-            match retry(backoffset(), || async {
+            match retry(self.backoff.clone(), || async {
                 self.client.get(&url).send().await.map_err(from_reqwest_err) 
             }).await {
                 Ok(response) => {
-                    return Ok(response)
+                    return Ok((api, response))
                 },
                 Err(error) => {
                     error!("reach backoffset max retry, failed to fetch {url}: {error}");
-                    
-                    let mut apis_queue = self.apis.borrow_mut();
-                    let first = apis_queue.pop_front().unwrap();
-                    apis_queue.push_back(first);
-
-                    drop(apis_queue);
+                    failed(&api);
+                    self.fail_over(&api);
                     continue
                 }
             }
@@ -50,4 +86,90 @@ impl ApiManage {
 
         // bail!("finally failed to fetch {url}")
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock::{Endpoint, Fault, MockApi};
+
+    /// Gives up on an api after about 100ms.
+    fn short_backoff() -> ExponentialBackoff {
+        ExponentialBackoff {
+            initial_interval: Duration::from_millis(10),
+            max_interval: Duration::from_millis(50),
+            max_elapsed_time: Some(Duration::from_millis(100)),
+            ..Default::default()
+        }
+    }
+
+    fn api_manage(client: Client, apis: Vec<String>) -> ApiManage {
+        ApiManage::new(client, apis).with_backoff(short_backoff())
+    }
+
+    #[tokio::test]
+    async fn fails_over_from_an_unreachable_api() {
+        let mock = MockApi::start(BTreeMap::new());
+        // 端口1没有服务监听
+        let manage = api_manage(Client::new(), vec![String::from("http://127.0.0.1:1/testnet3"), mock.url()]);
+
+        let (api, response) = manage.get("/latest/height").await.unwrap();
+        assert!(response.status().is_success());
+        assert_eq!(api, mock.url());
+        assert_eq!(manage.current(), mock.url());
+        assert_eq!(manage.apis()[1], "http://127.0.0.1:1/testnet3");
+    }
+
+    #[tokio::test]
+    async fn fails_over_from_an_api_slower_than_the_timeout() {
+        let slow = MockApi::start(BTreeMap::new());
+        let fast = MockApi::start(BTreeMap::new());
+        slow.fault_times(Endpoint::Height, Fault::Delay(Duration::from_secs(1)), 20);
+        let client = Client::builder().timeout(Duration::from_millis(50)).build().unwrap();
+        let manage = api_manage(client, vec![slow.url(), fast.url()]);
+
+        let failed = RefCell::new(Vec::new());
+        let (api, _) = manage.get_reporting("/latest/height", |api| failed.borrow_mut().push(api.to_string())).await.unwrap();
+        assert_eq!(api, fast.url());
+        assert_eq!(failed.into_inner(), vec![slow.url()]);
+        assert_eq!(manage.current(), fast.url());
+        assert_eq!(fast.requests(), vec![String::from("/testnet3/latest/height")]);
+    }
+
+    #[tokio::test]
+    async fn returns_error_statuses_without_failing_over() {
+        let first = MockApi::start(BTreeMap::new());
+        let second = MockApi::start(BTreeMap::new());
+        first.fault(Endpoint::Height, Fault::Status(500));
+        let manage = api_manage(Client::new(), vec![first.url(), second.url()]);
+
+        // 只有请求失败才切换api，错误状态码由调用方处理
+        let (api, response) = manage.get("/latest/height").await.unwrap();
+        assert_eq!(api, first.url());
+        assert_eq!(response.status(), reqwest::StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(manage.current(), first.url());
+        assert!(second.requests().is_empty());
+    }
+
+    #[test]
+    fn fails_over_only_from_the_api_in_use() {
+        let manage = ApiManage::new(Client::new(), vec![String::from("http://a"), String::from("http://b")]);
+        manage.fail_over("http://a");
+        assert_eq!(manage.apis(), vec![String::from("http://b"), String::from("http://a")]);
+        // 已经切换过的api不再重复切换
+        manage.fail_over("http://a");
+        assert_eq!(manage.current(), "http://b");
+    }
+
+    #[tokio::test]
+    async fn get_from_does_not_fail_over() {
+        let mock = MockApi::start(BTreeMap::new());
+        let manage = api_manage(Client::new(), vec![mock.url(), String::from("http://127.0.0.1:1/testnet3")]);
+
+        assert!(manage.get_from("http://127.0.0.1:1/testnet3", "/latest/height").await.is_err());
+        assert_eq!(manage.current(), mock.url());
+        assert!(mock.requests().is_empty());
+    }
+}
@@ -1,11 +1,16 @@
+use std::sync::Arc;
+
+use anyhow::Context;
 use log::debug;
 use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration, Instant};
 use log::error;
 use memmap2::MmapMut;
 
 use snarkvm_synthesizer::{Block, PartialSolution};
 use snarkvm_console_network::Network;
 
+use crate::sink::Fanout;
 use crate::storage::Storage;
 use crate::storage::Store;
 
@@ -13,7 +18,7 @@ pub trait MessageTrait<N: Network> {
     fn name(&self) -> String;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Message<N: Network> {
     Solution(Solution<N>),
     BlockReward(BlockReward<N>),
@@ -28,9 +33,18 @@ impl<N: Network> Message<N>  {
             Self::SyncHeight(msg) => msg.name(),
         }
     }
+
+    /// The height of the block the message belongs to.
+    pub fn height(&self) -> u32 {
+        match self {
+            Self::Solution(msg) => msg.block_height,
+            Self::BlockReward(msg) => msg.block.height(),
+            Self::SyncHeight(msg) => msg.height,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Solution<N: Network> {
     pub block_height: u32,
     pub partial_solution: PartialSolution<N>,
@@ -44,7 +58,7 @@ impl<N: Network> MessageTrait<N> for Solution<N> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BlockReward<N: Network> {
     pub block: Block<N>,
     pub solutions_num: usize,
@@ -57,7 +71,7 @@ impl<N: Network> MessageTrait<N> for BlockReward<N> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SyncHeight<N: Network> {
     pub height: u32,
     pub _p: std::marker::PhantomData<N>
@@ -69,37 +83,95 @@ impl<N: Network> MessageTrait<N> for SyncHeight<N> {
     }
 }
 
+/// Blocks written to the storage at once during the batch sync.
+const WRITE_BATCH_BLOCKS: usize = 100;
+/// Buffered blocks are written after this time without new messages, so the live sync is not delayed.
+const WRITE_BATCH_INTERVAL: Duration = Duration::from_secs(1);
+
 pub async fn handle<N: Network, S: Storage<N>>(
     store: Store<N, S>, 
     mut receiver: mpsc::Receiver<Message<N>>,
     mut mmap: MmapMut,
+    mut fanout: Fanout<N>,
 ) {
     debug!("start to listen to message...");
+    // 当前区块未收到同步高度前的消息
+    let mut pending: Vec<Message<N>> = Vec::new();
+    // 完整区块的消息，批量写入
+    let mut ready: Vec<Message<N>> = Vec::new();
+    let mut ready_blocks = 0_usize;
+    let mut last_write = Instant::now();
     loop {
-        let message = receiver.recv().await; 
+        let message = tokio::select! {
+            message = receiver.recv() => message,
+            Some(attach) = fanout.attach_request() => {
+                fanout.attach(attach);
+                continue;
+            }
+            _ = sleep(WRITE_BATCH_INTERVAL), if ready_blocks > 0 => {
+                if let Err(e) = commit(&store, &mut mmap, &mut fanout, &mut ready).await {
+                    error!("{:?}", e);
+                    return
+                }
+                ready_blocks = 0;
+                last_write = Instant::now();
+                continue;
+            }
+        };
         if message.is_none() {
             error!("receive None from message channel");
+            if let Err(e) = commit(&store, &mut mmap, &mut fanout, &mut ready).await {
+                error!("{:?}", e);
+            }
             break;
         }
         let message = message.unwrap();
         debug!("receive {} message", message.name());
-        match message {
-            Message::Solution(msg) => {
-                if let Err(e) = store.record_solutions(&msg) {
-                    error!("record block {} solution failed {:?}", msg.block_height, e);
-                    return 
-                }
-            },
-            Message::BlockReward(msg) => {
-                if let Err(e) = store.record_block(&msg) {
-                    error!("record block {} failed {:?}", msg.block.height(), e);
-                    return 
-                }
-            },
-            Message::SyncHeight(msg) => {
-                let height = u32::to_le_bytes(msg.height);
-                mmap.copy_from_slice(&height[..]);
+        let synced = matches!(message, Message::SyncHeight(_));
+        pending.push(message);
+        if !synced {
+            continue;
+        }
+
+        ready.append(&mut pending);
+        ready_blocks += 1;
+        if ready_blocks >= WRITE_BATCH_BLOCKS || last_write.elapsed() >= WRITE_BATCH_INTERVAL {
+            if let Err(e) = commit(&store, &mut mmap, &mut fanout, &mut ready).await {
+                error!("{:?}", e);
+                return
             }
+            ready_blocks = 0;
+            last_write = Instant::now();
         }
     }
-}
\ No newline at end of file
+}
+
+/// Writes the messages of complete blocks in one batch, then updates the synced height and forwards them.
+async fn commit<N: Network, S: Storage<N>>(
+    store: &Store<N, S>,
+    mmap: &mut MmapMut,
+    fanout: &mut Fanout<N>,
+    ready: &mut Vec<Message<N>>,
+) -> anyhow::Result<()> {
+    let height = match ready.iter().rev().find_map(|message| match message {
+        Message::SyncHeight(msg) => Some(msg.height),
+        _ => None,
+    }) {
+        Some(height) => height,
+        None => return Ok(()),
+    };
+
+    let timer = Instant::now();
+    let messages = Arc::new(std::mem::take(ready));
+    store.record_batch(messages.clone()).await.with_context(|| format!("record blocks up to {height} failed"))?;
+    // 区块数据落盘后再更新同步高度
+    store.flush().await.with_context(|| format!("flush block {height} failed"))?;
+    mmap.copy_from_slice(&u32::to_le_bytes(height)[..]);
+    debug!("write {} messages up to block {} in {:?}", messages.len(), height, timer.elapsed());
+
+    // 主存储写入成功后再分发给其他存储
+    for message in messages.iter() {
+        fanout.forward(message);
+    }
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use parking_lot::Mutex;
+
+/// Counters of the api server, rendered in the prometheus text format at `GET /metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    // route -> requests rejected by the rate limit
+    rate_limited: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn rate_limited(&self, route: &'static str) {
+        *self.rate_limited.lock().entry(route).or_default() += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        output.push_str("# HELP aleo_blocks_rate_limited_total Requests rejected by the rate limit.\n");
+        output.push_str("# TYPE aleo_blocks_rate_limited_total counter\n");
+        for (route, count) in self.rate_limited.lock().iter() {
+            let _ = writeln!(output, "aleo_blocks_rate_limited_total{{route=\"{route}\"}} {count}");
+        }
+        output
+    }
+}
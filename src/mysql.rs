@@ -3,8 +3,8 @@ use mysql::prelude::*;
 
 use snarkvm_console_network::Network;
 
-use crate::storage::{Storage, Reward};
-use crate::message::{Solution, BlockReward};
+use crate::storage::{Storage, Reward, ProverRank, ProverShare, Page, Paged, Order, Group, GroupMember, GroupReward, LedgerEntry, BlockRow, SolutionRow};
+use crate::message::{Message, Solution, BlockReward};
 
 pub struct MysqlClient {
     pool: Pool,
@@ -13,6 +13,54 @@ pub struct MysqlClient {
 const TABLE_BLOCKS_NAME: &str = "blocks";
 // const TABLE_TRANSACTIONS_NAME: &str = "transactions";
 const TABLE_SOLUTIONS_NAME: &str = "block_solutions";
+const TABLE_GROUPS_NAME: &str = "address_groups";
+const TABLE_GROUP_MEMBERS_NAME: &str = "address_group_members";
+const TABLE_PAYOUT_PERIODS_NAME: &str = "payout_periods";
+const TABLE_PAYOUT_LEDGER_NAME: &str = "payout_ledger";
+
+const BLOCK_FIELDS: &str = "block_height,block_hash,previous_block_hash,network,coinbase_target,proof_target,last_coinbase_target,last_coinbase_timestamp,timestamp,solutions_num,block_reward";
+const SOLUTION_FIELDS: &str = "block_height,address,nonce,commitment,solution_reward,timestamp";
+/// Recording a solution again is a no-op, e.g. a backfill of an address that was watched before.
+const SOLUTION_ON_DUPLICATE: &str = "ON DUPLICATE KEY UPDATE commitment = commitment";
+/// Rows per multi-row insert, keeps the placeholders far below the mysql limit of 65535.
+const INSERT_CHUNK_ROWS: usize = 1000;
+
+fn block_values<N: Network>(block: &BlockReward<N>) -> Vec<Value> {
+    vec![
+        block.block.height().into(),
+        block.block.hash().to_string().into(),
+        block.block.previous_hash().to_string().into(),
+        block.block.network().into(),
+        block.block.coinbase_target().into(),
+        block.block.proof_target().into(),
+        block.block.last_coinbase_target().into(),
+        block.block.last_coinbase_timestamp().into(),
+        block.block.timestamp().into(),
+        block.solutions_num.into(),
+        block.block_reward.into(),
+    ]
+}
+
+fn solution_values<N: Network>(solution: &Solution<N>) -> Vec<Value> {
+    vec![
+        solution.block_height.into(),
+        solution.partial_solution.address().to_string().into(),
+        solution.partial_solution.nonce().into(),
+        solution.partial_solution.commitment().to_string().into(),
+        solution.solution_reward.into(),
+        solution.timestamp.into(),
+    ]
+}
+
+/// Inserts the rows with multi-row inserts, `fields` are in the order of the row values, `suffix` is appended to every insert.
+fn insert_rows(tx: &mut Transaction, table: &str, fields: &str, rows: Vec<Vec<Value>>, suffix: &str) -> anyhow::Result<()> {
+    let row = format!("({})", vec!["?"; fields.split(',').count()].join(","));
+    for chunk in rows.chunks(INSERT_CHUNK_ROWS) {
+        let sql = format!("INSERT INTO {} ({}) VALUES {} {}", table, fields, vec![row.as_str(); chunk.len()].join(","), suffix);
+        tx.exec_drop(sql, Params::Positional(chunk.concat()))?;
+    }
+    Ok(())
+}
 
 
 impl<'a,N> Storage<N> for MysqlClient where N: Network {
@@ -25,56 +73,351 @@ impl<'a,N> Storage<N> for MysqlClient where N: Network {
 
     fn record_block(&self, block: &BlockReward<N>) -> anyhow::Result<bool>{
         let mut conn = self.pool.get_conn()?;
-        let fields = "block_height,block_hash,previous_block_hash,network,coinbase_target,proof_target,last_coinbase_target,last_coinbase_timestamp,timestamp,solutions_num,block_reward";
-        let sql = format!("INSERT INTO {} ({}) VALUES(?,?,?,?,?,?,?,?,?,?,?)", TABLE_BLOCKS_NAME, fields);
-        conn.exec_drop(
-            sql, 
-            (
-                block.block.height(),
-                block.block.hash().to_string(),
-                block.block.previous_hash().to_string(),
-                block.block.network(),
-                block.block.coinbase_target(),
-                block.block.proof_target(),
-                block.block.last_coinbase_target(),
-                block.block.last_coinbase_timestamp(),
-                block.block.timestamp(),
-                block.solutions_num,
-                block.block_reward
-            )
-        )?;
+        let sql = format!("INSERT INTO {} ({}) VALUES(?,?,?,?,?,?,?,?,?,?,?)", TABLE_BLOCKS_NAME, BLOCK_FIELDS);
+        conn.exec_drop(sql, Params::Positional(block_values(block)))?;
         Ok(true)
     }
 
     fn record_solutions(&self, solution: &Solution<N>) -> anyhow::Result<bool> {
         let mut conn = self.pool.get_conn()?;
-        let sql = format!("INSERT INTO {} (block_height, address, nonce, commitment, solution_reward, timestamp) VALUES(?, ?, ?, ?, ?, ?)", TABLE_SOLUTIONS_NAME);
-        conn.exec_drop(
-            sql, 
-            (
-                solution.block_height, 
-                solution.partial_solution.address().to_string(), 
-                solution.partial_solution.nonce(), 
-                solution.partial_solution.commitment().to_string(),
-                solution.solution_reward,
-                solution.timestamp,
-            )
-        )?;
+        let sql = format!("INSERT INTO {} ({}) VALUES(?, ?, ?, ?, ?, ?) {}", TABLE_SOLUTIONS_NAME, SOLUTION_FIELDS, SOLUTION_ON_DUPLICATE);
+        conn.exec_drop(sql, Params::Positional(solution_values(solution)))?;
         Ok(true)
     }
+
+    // 多个区块的数据在一个事务中用多行insert写入
+    fn record_batch(&self, messages: &[Message<N>]) -> anyhow::Result<()> {
+        let mut blocks = Vec::new();
+        let mut solutions = Vec::new();
+        for message in messages {
+            match message {
+                Message::BlockReward(block) => blocks.push(block_values(block)),
+                Message::Solution(solution) => solutions.push(solution_values(solution)),
+                Message::SyncHeight(_) => {}
+            }
+        }
+        if blocks.is_empty() && solutions.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.pool.get_conn()?;
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+        insert_rows(&mut tx, TABLE_BLOCKS_NAME, BLOCK_FIELDS, blocks, "")?;
+        insert_rows(&mut tx, TABLE_SOLUTIONS_NAME, SOLUTION_FIELDS, solutions, SOLUTION_ON_DUPLICATE)?;
+        tx.commit()?;
+        Ok(())
+    }
     
+    // timestamp -> [begin, end), ordered by (timestamp, id), one extra row is read to know whether there is a next page
+    fn get_solutions_page(&self, address: &str, begin: i64, end: i64, page: &Page) -> anyhow::Result<Paged<Reward>> {
+        let mut conn = self.pool.get_conn()?;
+        let (cmp, order) = match page.order {
+            Order::Asc => (">", "ASC"),
+            Order::Desc => ("<", "DESC"),
+        };
+        let mut sql = format!("SELECT id, address, block_height, nonce, solution_reward, timestamp FROM {} WHERE address = ? and timestamp >= ? and timestamp < ?", TABLE_SOLUTIONS_NAME);
+        let mut params: Vec<Value> = vec![address.into(), begin.into(), end.into()];
+        if let Some(cursor) = page.cursor {
+            sql.push_str(&format!(" and (timestamp {cmp} ? or (timestamp = ? and id {cmp} ?))"));
+            params.extend([cursor.timestamp.into(), cursor.timestamp.into(), cursor.id.into()]);
+        }
+        sql.push_str(&format!(" ORDER BY timestamp {order}, id {order} LIMIT ?"));
+        params.push((page.limit as u64 + 1).into());
+
+        let items = conn.exec_map(
+            sql,
+            Params::Positional(params),
+            |(id, address, height, nonce, reward, timestamp)| {
+                Reward {
+                    id,
+                    address,
+                    height,
+                    nonce,
+                    reward,
+                    timestamp,
+                }
+            }
+        )?;
+
+        Ok(Paged::from_rows(items, page.limit))
+    }
+
+    // timestamp -> [begin, end), ordered by total reward
+    fn get_top_provers(&self, begin: i64, end: i64, limit: u32) -> anyhow::Result<Vec<ProverRank>> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!("SELECT address, COUNT(*), CAST(SUM(solution_reward) AS UNSIGNED) AS reward FROM {} WHERE timestamp >= ? and timestamp < ? GROUP BY address ORDER BY reward DESC, COUNT(*) DESC LIMIT ?", TABLE_SOLUTIONS_NAME);
+        let result = conn.exec_map(
+            sql,
+            (begin, end, limit),
+            |(address, solutions, reward)| {
+                ProverRank {
+                    address,
+                    solutions,
+                    reward,
+                }
+            }
+        )?;
+
+        Ok(result)
+    }
+
+    // timestamp -> [begin, end), address totals against all provers
+    fn get_prover_share(&self, address: &str, begin: i64, end: i64) -> anyhow::Result<ProverShare> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!(
+            "SELECT CAST(COALESCE(SUM(address = ?), 0) AS UNSIGNED), CAST(COALESCE(SUM(IF(address = ?, solution_reward, 0)), 0) AS UNSIGNED), \
+            COUNT(*), CAST(COALESCE(SUM(solution_reward), 0) AS UNSIGNED) FROM {} WHERE timestamp >= ? and timestamp < ?", 
+            TABLE_SOLUTIONS_NAME
+        );
+        let (solutions, reward, total_solutions, total_reward): (u64, u64, u64, u64) = conn
+            .exec_first(sql, (address, address, begin, end))?
+            .unwrap_or_default();
+
+        let share = |part: u64, total: u64| if total == 0 { 0.0 } else { part as f64 / total as f64 };
+        Ok(ProverShare {
+            address: address.to_string(),
+            solutions,
+            reward,
+            total_solutions,
+            total_reward,
+            solutions_share: share(solutions, total_solutions),
+            reward_share: share(reward, total_reward),
+        })
+    }
+
+    fn create_group(&self, name: &str, label: &str) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!("INSERT IGNORE INTO {} (name, label) VALUES(?, ?)", TABLE_GROUPS_NAME);
+        conn.exec_drop(sql, (name, label))?;
+        Ok(conn.affected_rows() > 0)
+    }
+
+    fn delete_group(&self, name: &str) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+        let sql = format!("DELETE m FROM {} m JOIN {} g ON m.group_id = g.id WHERE g.name = ?", TABLE_GROUP_MEMBERS_NAME, TABLE_GROUPS_NAME);
+        tx.exec_drop(sql, (name,))?;
+        let sql = format!("DELETE FROM {} WHERE name = ?", TABLE_GROUPS_NAME);
+        tx.exec_drop(sql, (name,))?;
+        let deleted = tx.affected_rows() > 0;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    fn add_group_member(&self, name: &str, address: &str, label: &str) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!(
+            "INSERT INTO {} (group_id, address, label) SELECT id, ?, ? FROM {} WHERE name = ? ON DUPLICATE KEY UPDATE label = VALUES(label)",
+            TABLE_GROUP_MEMBERS_NAME, TABLE_GROUPS_NAME
+        );
+        conn.exec_drop(sql, (address, label, name))?;
+        // 0: group not found, 1: inserted, 2: label updated
+        if conn.affected_rows() > 0 {
+            return Ok(true)
+        }
+        let sql = format!("SELECT COUNT(*) FROM {} WHERE name = ?", TABLE_GROUPS_NAME);
+        let exists: Option<u64> = conn.exec_first(sql, (name,))?;
+        Ok(exists.unwrap_or(0) > 0)
+    }
+
+    fn remove_group_member(&self, name: &str, address: &str) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!("DELETE m FROM {} m JOIN {} g ON m.group_id = g.id WHERE g.name = ? and m.address = ?", TABLE_GROUP_MEMBERS_NAME, TABLE_GROUPS_NAME);
+        conn.exec_drop(sql, (name, address))?;
+        Ok(conn.affected_rows() > 0)
+    }
+
+    fn get_groups(&self) -> anyhow::Result<Vec<Group>> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!(
+            "SELECT g.name, g.label, m.address, m.label FROM {} g LEFT JOIN {} m ON m.group_id = g.id ORDER BY g.name, m.address",
+            TABLE_GROUPS_NAME, TABLE_GROUP_MEMBERS_NAME
+        );
+        let rows: Vec<(String, String, Option<String>, Option<String>)> = conn.exec(sql, ())?;
+
+        let mut groups: Vec<Group> = Vec::new();
+        for (name, label, address, member_label) in rows {
+            if groups.last().is_none_or(|group| group.name != name) {
+                groups.push(Group { name, label, members: vec![] });
+            }
+            if let (Some(address), Some(label)) = (address, member_label) {
+                groups.last_mut().unwrap().members.push(GroupMember { address, label });
+            }
+        }
+        Ok(groups)
+    }
+
+    // timestamp -> [begin, end)
+    fn get_group_rewards(&self, name: &str, begin: i64, end: i64) -> anyhow::Result<Option<GroupReward>> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!("SELECT COUNT(*) FROM {} WHERE name = ?", TABLE_GROUPS_NAME);
+        let exists: Option<u64> = conn.exec_first(sql, (name,))?;
+        if exists.unwrap_or(0) == 0 {
+            return Ok(None)
+        }
+
+        let sql = format!(
+            "SELECT m.address, COUNT(s.id), CAST(COALESCE(SUM(s.solution_reward), 0) AS UNSIGNED) AS reward FROM {} g \
+            JOIN {} m ON m.group_id = g.id \
+            LEFT JOIN {} s ON s.address = m.address and s.timestamp >= ? and s.timestamp < ? \
+            WHERE g.name = ? GROUP BY m.address ORDER BY reward DESC, m.address",
+            TABLE_GROUPS_NAME, TABLE_GROUP_MEMBERS_NAME, TABLE_SOLUTIONS_NAME
+        );
+        let members = conn.exec_map(
+            sql,
+            (begin, end, name),
+            |(address, solutions, reward)| {
+                ProverRank {
+                    address,
+                    solutions,
+                    reward,
+                }
+            }
+        )?;
+
+        Ok(Some(GroupReward {
+            name: name.to_string(),
+            solutions: members.iter().map(|m| m.solutions).sum(),
+            reward: members.iter().map(|m| m.reward).sum(),
+            members,
+        }))
+    }
+
     // timestamp -> [begin, end)
-    fn get_solutions_by_time_range(&self, address: &String, begin: i64, end: i64) -> anyhow::Result<Vec<Reward>> {
+    fn get_address_rewards(&self, addresses: &[String], begin: i64, end: i64) -> anyhow::Result<Vec<ProverRank>> {
+        if addresses.is_empty() {
+            return Ok(vec![])
+        }
         let mut conn = self.pool.get_conn()?;
-        let sql = format!("SELECT address, block_height, nonce, solution_reward, timestamp FROM {} WHERE address = ? and timestamp >= ? and timestamp < ?", TABLE_SOLUTIONS_NAME);
+        let placeholders = vec!["?"; addresses.len()].join(",");
+        let sql = format!(
+            "SELECT address, COUNT(*), CAST(SUM(solution_reward) AS UNSIGNED) FROM {} WHERE timestamp >= ? and timestamp < ? and address IN ({}) GROUP BY address",
+            TABLE_SOLUTIONS_NAME, placeholders
+        );
+        let mut params: Vec<Value> = vec![begin.into(), end.into()];
+        params.extend(addresses.iter().map(|address| Value::from(address.as_str())));
         let result = conn.exec_map(
             sql,
-            (address, begin, end),
-            |(address, height, nonce, reward, timestamp)| {
-                Reward {
+            Params::Positional(params),
+            |(address, solutions, reward)| {
+                ProverRank {
                     address,
+                    solutions,
+                    reward,
+                }
+            }
+        )?;
+
+        Ok(result)
+    }
+
+    fn close_payout_period(&self, scheme: &str, period_start: i64, period_end: i64, entries: &[LedgerEntry]) -> anyhow::Result<bool> {
+        let mut conn = self.pool.get_conn()?;
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+        // 周期已关闭则不重复写入
+        let sql = format!("INSERT IGNORE INTO {} (scheme, period_start, period_end, closed_at) VALUES(?, ?, ?, UNIX_TIMESTAMP())", TABLE_PAYOUT_PERIODS_NAME);
+        tx.exec_drop(sql, (scheme, period_start, period_end))?;
+        if tx.affected_rows() == 0 {
+            tx.rollback()?;
+            return Ok(false)
+        }
+
+        // line保存对账单的行序，手续费行在最后
+        let sql = format!(
+            "INSERT INTO {} (scheme, period_start, period_end, line, beneficiary, solutions, gross_reward, fee, net_reward) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            TABLE_PAYOUT_LEDGER_NAME
+        );
+        tx.exec_batch(
+            sql,
+            entries.iter().enumerate().map(|(line, entry)| (
+                &entry.scheme,
+                entry.period_start,
+                entry.period_end,
+                line as u32,
+                &entry.beneficiary,
+                entry.solutions,
+                entry.gross_reward,
+                entry.fee,
+                entry.net_reward,
+            ))
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    fn get_payout_period(&self, scheme: &str, period_start: i64) -> anyhow::Result<Option<Vec<LedgerEntry>>> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!("SELECT COUNT(*) FROM {} WHERE scheme = ? and period_start = ?", TABLE_PAYOUT_PERIODS_NAME);
+        let closed: Option<u64> = conn.exec_first(sql, (scheme, period_start))?;
+        if closed.unwrap_or(0) == 0 {
+            return Ok(None)
+        }
+
+        let sql = format!(
+            "SELECT scheme, period_start, period_end, beneficiary, solutions, gross_reward, fee, net_reward FROM {} WHERE scheme = ? and period_start = ? ORDER BY line",
+            TABLE_PAYOUT_LEDGER_NAME
+        );
+        let result = conn.exec_map(
+            sql,
+            (scheme, period_start),
+            |(scheme, period_start, period_end, beneficiary, solutions, gross_reward, fee, net_reward)| {
+                LedgerEntry {
+                    scheme,
+                    period_start,
+                    period_end,
+                    beneficiary,
+                    solutions,
+                    gross_reward,
+                    fee,
+                    net_reward,
+                }
+            }
+        )?;
+
+        Ok(Some(result))
+    }
+
+    // height -> [start, end)
+    fn get_blocks_by_height_range(&self, start: u32, end: u32) -> anyhow::Result<Vec<BlockRow>> {
+        let mut conn = self.pool.get_conn()?;
+        let fields = "block_height,block_hash,previous_block_hash,network,coinbase_target,proof_target,last_coinbase_target,last_coinbase_timestamp,timestamp,solutions_num,block_reward";
+        let sql = format!("SELECT {} FROM {} WHERE block_height >= ? and block_height < ? ORDER BY block_height", fields, TABLE_BLOCKS_NAME);
+        let result = conn.exec_map(
+            sql,
+            (start, end),
+            |(height, hash, previous_hash, network, coinbase_target, proof_target, last_coinbase_target, last_coinbase_timestamp, timestamp, solutions_num, block_reward)| {
+                BlockRow {
                     height,
+                    hash,
+                    previous_hash,
+                    network,
+                    coinbase_target,
+                    proof_target,
+                    last_coinbase_target,
+                    last_coinbase_timestamp,
+                    timestamp,
+                    solutions_num,
+                    block_reward,
+                }
+            }
+        )?;
+
+        Ok(result)
+    }
+
+    // height -> [start, end)
+    fn get_solutions_by_height_range(&self, start: u32, end: u32) -> anyhow::Result<Vec<SolutionRow>> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!("SELECT block_height, address, nonce, commitment, solution_reward, timestamp FROM {} WHERE block_height >= ? and block_height < ? ORDER BY block_height, id", TABLE_SOLUTIONS_NAME);
+        let result = conn.exec_map(
+            sql,
+            (start, end),
+            |(height, address, nonce, commitment, reward, timestamp)| {
+                SolutionRow {
+                    height,
+                    address,
                     nonce,
+                    commitment,
                     reward,
                     timestamp,
                 }
@@ -83,4 +426,114 @@ impl<'a,N> Storage<N> for MysqlClient where N: Network {
 
         Ok(result)
     }
-}
\ No newline at end of file
+
+    // 两个子查询都走索引：blocks按block_height唯一索引取最后一行，solutions的MAX(timestamp)走timestamp_address_idx
+    fn get_latest_timestamp(&self) -> anyhow::Result<Option<i64>> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!(
+            "SELECT GREATEST(COALESCE((SELECT timestamp FROM {} ORDER BY block_height DESC LIMIT 1), 0), COALESCE((SELECT MAX(timestamp) FROM {}), 0))",
+            TABLE_BLOCKS_NAME, TABLE_SOLUTIONS_NAME
+        );
+        let result: Option<i64> = conn.query_first(sql)?;
+        Ok(result.filter(|timestamp| *timestamp > 0))
+    }
+
+    fn get_last_solution_id(&self) -> anyhow::Result<Option<u64>> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!("SELECT MAX(id) FROM {}", TABLE_SOLUTIONS_NAME);
+        let result: Option<Option<u64>> = conn.query_first(sql)?;
+        Ok(result.flatten())
+    }
+
+    // 主键范围扫描，只读取新记录的行
+    fn get_earliest_timestamp_after(&self, id: u64) -> anyhow::Result<Option<i64>> {
+        let mut conn = self.pool.get_conn()?;
+        let sql = format!("SELECT MIN(timestamp) FROM {} WHERE id > ?", TABLE_SOLUTIONS_NAME);
+        let result: Option<Option<i64>> = conn.exec_first(sql, (id,))?;
+        Ok(result.flatten())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    /// Compares one insert per solution with the multi-row inserts of `record_batch`, on temporary copies of the
+    /// solutions table of `ALEO_BLOCKS_BENCH_MYSQL`. Run with `--ignored --nocapture` to see the rows per second.
+    #[test]
+    #[ignore = "needs a mysql database"]
+    fn bench_batched_inserts() {
+        let url = std::env::var("ALEO_BLOCKS_BENCH_MYSQL").expect("ALEO_BLOCKS_BENCH_MYSQL is not set");
+        let rows = std::env::var("BENCH_ROWS").map(|rows| rows.parse::<u32>().unwrap()).unwrap_or(20_000);
+        let mut conn = Pool::new(url.as_str()).unwrap().get_conn().unwrap();
+        let values = |table: &str| -> Vec<Vec<Value>> {
+            (0..rows).map(|i| vec![
+                (i / 50).into(),
+                format!("aleo1bench{}", i % 100).into(),
+                u64::from(i).into(),
+                format!("puzzle1{table}{i}").into(),
+                1_000_u64.into(),
+                (1_670_000_000 + i / 50).into(),
+            ]).collect()
+        };
+
+        conn.query_drop(format!("CREATE TEMPORARY TABLE bench_single LIKE {TABLE_SOLUTIONS_NAME}")).unwrap();
+        let sql = format!("INSERT INTO bench_single ({SOLUTION_FIELDS}) VALUES(?, ?, ?, ?, ?, ?) {SOLUTION_ON_DUPLICATE}");
+        let start = Instant::now();
+        for row in values("single") {
+            conn.exec_drop(&sql, Params::Positional(row)).unwrap();
+        }
+        let single = start.elapsed();
+
+        conn.query_drop(format!("CREATE TEMPORARY TABLE bench_batch LIKE {TABLE_SOLUTIONS_NAME}")).unwrap();
+        let start = Instant::now();
+        let mut tx = conn.start_transaction(TxOpts::default()).unwrap();
+        insert_rows(&mut tx, "bench_batch", SOLUTION_FIELDS, values("batch"), SOLUTION_ON_DUPLICATE).unwrap();
+        tx.commit().unwrap();
+        let batch = start.elapsed();
+
+        for table in ["bench_single", "bench_batch"] {
+            let count: Option<u32> = conn.query_first(format!("SELECT COUNT(*) FROM {table}")).unwrap();
+            assert_eq!(count, Some(rows));
+        }
+        let per_second = |elapsed: std::time::Duration| rows as f64 / elapsed.as_secs_f64();
+        println!("{rows} solutions: one insert per row {:.0} rows/s, multi-row inserts {:.0} rows/s, {:.1}x",
+            per_second(single), per_second(batch), single.as_secs_f64() / batch.as_secs_f64());
+    }
+
+    /// Closes a period in the payout tables of `ALEO_BLOCKS_TEST_MYSQL`, a database with the schema of
+    /// `aleo-blocks.sql`, and reads its statement back.
+    #[test]
+    #[ignore = "needs a mysql database"]
+    fn payout_period_keeps_the_line_order() {
+        use snarkvm_console_network::Testnet3;
+
+        let url = std::env::var("ALEO_BLOCKS_TEST_MYSQL").expect("ALEO_BLOCKS_TEST_MYSQL is not set");
+        let client = <MysqlClient as Storage<Testnet3>>::new(url);
+        let scheme = format!("test-{}", std::process::id());
+        let entry = |beneficiary: &str, gross_reward: u64| LedgerEntry {
+            scheme: scheme.clone(),
+            period_start: 0,
+            period_end: 86_400,
+            beneficiary: beneficiary.to_string(),
+            solutions: 1,
+            gross_reward,
+            fee: 0,
+            net_reward: gross_reward,
+        };
+        // 手续费行按名称排序不在最后
+        let entries = vec![entry("zoe", 3), entry("amy", 2), entry("fee:operator", 1)];
+
+        let closed = Storage::<Testnet3>::close_payout_period(&client, &scheme, 0, 86_400, &entries).unwrap();
+        let read = Storage::<Testnet3>::get_payout_period(&client, &scheme, 0).unwrap();
+        let mut conn = client.pool.get_conn().unwrap();
+        for table in [TABLE_PAYOUT_LEDGER_NAME, TABLE_PAYOUT_PERIODS_NAME] {
+            conn.exec_drop(format!("DELETE FROM {table} WHERE scheme = ?"), (&scheme,)).unwrap();
+        }
+
+        assert!(closed);
+        let lines: Vec<(String, u64)> = read.unwrap().into_iter().map(|e| (e.beneficiary, e.gross_reward)).collect();
+        assert_eq!(lines, vec![(String::from("zoe"), 3), (String::from("amy"), 2), (String::from("fee:operator"), 1)]);
+    }
+}
@@ -0,0 +1,443 @@
+use serde_json::{json, Value};
+
+/// The docs page served at `/docs`, rendering `/openapi.json` with the inline script only, no third-party code is loaded.
+pub const DOCS_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8"/>
+    <title>aleo-blocks api</title>
+    <style>
+        body { font-family: sans-serif; max-width: 960px; margin: 2em auto; padding: 0 1em; color: #222; }
+        h2 { font-family: monospace; font-size: 1.1em; margin-top: 2em; border-bottom: 1px solid #ddd; }
+        .method { text-transform: uppercase; margin-right: 0.5em; color: #06c; }
+        table { border-collapse: collapse; margin: 0.5em 0; }
+        th, td { border: 1px solid #ddd; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+        td:first-child { font-family: monospace; }
+    </style>
+</head>
+<body>
+    <div id="docs">loading /openapi.json</div>
+    <script>
+        const el = (tag, text, children = [], className = "") => {
+            const node = document.createElement(tag);
+            if (text) node.textContent = text;
+            if (className) node.className = className;
+            children.forEach(child => node.appendChild(child));
+            return node;
+        };
+        const type = schema => {
+            if (!schema) return "";
+            if (schema.$ref) return schema.$ref.split("/").pop();
+            if (schema.allOf) return schema.allOf.map(type).join(" with ");
+            if (schema.type === "array") return type(schema.items) + "[]";
+            if (schema.properties) return "{" + Object.entries(schema.properties).map(([name, p]) => name + ": " + type(p)).join(", ") + "}";
+            if (schema.enum) return schema.enum.join(" | ");
+            return schema.format || schema.type || "";
+        };
+        const table = (head, rows) => el("table", "", [
+            el("tr", "", head.map(h => el("th", h))),
+            ...rows.map(row => el("tr", "", row.map(cell => el("td", String(cell ?? ""))))),
+        ]);
+        fetch("/openapi.json").then(response => response.json()).then(doc => {
+            const root = document.getElementById("docs");
+            root.textContent = "";
+            root.appendChild(el("h1", doc.info.title + " " + doc.info.version));
+            root.appendChild(el("p", doc.info.description));
+            for (const [path, operations] of Object.entries(doc.paths)) {
+                for (const [method, op] of Object.entries(operations)) {
+                    root.appendChild(el("h2", "", [el("span", method, [], "method"), el("span", path)], ""));
+                    root.appendChild(el("p", op.summary));
+                    if (op.parameters) {
+                        root.appendChild(table(["parameter", "in", "required", "type", "description"],
+                            op.parameters.map(p => [p.name, p.in, p.required ? "yes" : "no", type(p.schema), p.schema.description])));
+                    }
+                    if (op.requestBody) {
+                        root.appendChild(el("p", "body: " + type(op.requestBody.content["application/json"].schema)));
+                    }
+                    root.appendChild(table(["status", "description", "type"], Object.entries(op.responses)
+                        .map(([status, r]) => [status, r.description, r.content ? type(r.content["application/json"].schema) : ""])));
+                }
+            }
+            root.appendChild(el("h2", "schemas"));
+            for (const [name, schema] of Object.entries(doc.components.schemas)) {
+                root.appendChild(el("h3", name));
+                root.appendChild(table(["field", "type", "description"], Object.entries(schema.properties || {})
+                    .map(([field, p]) => [field, type(p), p.description])));
+            }
+        }).catch(error => {
+            document.getElementById("docs").textContent = "failed to load /openapi.json: " + error;
+        });
+    </script>
+</body>
+</html>
+"#;
+
+fn integer(format: &str, description: &str) -> Value {
+    json!({"type": "integer", "format": format, "description": description})
+}
+
+fn string(description: &str) -> Value {
+    json!({"type": "string", "description": description})
+}
+
+fn reference(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{name}")})
+}
+
+fn array(items: Value) -> Value {
+    json!({"type": "array", "items": items})
+}
+
+fn path_param(name: &str, schema: Value) -> Value {
+    json!({"name": name, "in": "path", "required": true, "schema": schema})
+}
+
+fn query_param(name: &str, required: bool, schema: Value) -> Value {
+    json!({"name": name, "in": "query", "required": required, "schema": schema})
+}
+
+/// The `from` parameter of a `[from, to)` time window.
+fn from_param() -> Value {
+    query_param("from", true, integer("int64", "unix timestamp, inclusive"))
+}
+
+fn to_param() -> Value {
+    query_param("to", true, integer("int64", "unix timestamp, exclusive"))
+}
+
+/// The `Response` envelope around `data`.
+fn envelope(data: Value) -> Value {
+    json!({
+        "allOf": [
+            reference("Response"),
+            {"type": "object", "properties": {"data": data}},
+        ]
+    })
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({"description": description, "content": {"application/json": {"schema": schema}}})
+}
+
+/// The success response and every error response of an operation.
+fn responses(data: Value) -> Value {
+    let error = |description: &str| json_response(description, reference("Error"));
+    json!({
+        "200": json_response("success, code is 0", envelope(data)),
+        "400": error("invalid address, range or parameter, code 1001, 1002 or 1003"),
+        "401": error("missing or invalid api key, code 1006"),
+        "403": error("the api key is not allowed to query this, code 1007"),
+        "404": error("not found, code 1004"),
+        "429": {
+            "description": "rate limited, code 1008",
+            "headers": {"Retry-After": {"description": "seconds to wait", "schema": {"type": "integer"}}},
+            "content": {"application/json": {"schema": reference("Error")}},
+        },
+        "503": error("storage unavailable, code 2001"),
+    })
+}
+
+fn body(schema: &str) -> Value {
+    json!({"required": true, "content": {"application/json": {"schema": reference(schema)}}})
+}
+
+/// Returns the operation of every route in `server::ROUTES`, keyed by path and method.
+fn paths() -> Value {
+    let address = || path_param("address", string("aleo address"));
+    let name = || path_param("name", string("group name"));
+    json!({
+        "/testnet3/solutions/rewards/{address}/{begin}/{end}": {
+            "get": {
+                "operationId": "solutions_rewards",
+                "summary": "Solutions and rewards of an address in [begin, end), paged by cursor",
+                "parameters": [
+                    address(),
+                    path_param("begin", integer("int64", "unix timestamp, inclusive")),
+                    path_param("end", integer("int64", "unix timestamp, exclusive")),
+                    query_param("limit", false, json!({"type": "integer", "minimum": 1, "maximum": 1000, "default": 100})),
+                    query_param("cursor", false, string("the cursor of the previous page")),
+                    query_param("order", false, json!({"type": "string", "enum": ["asc", "desc"], "default": "asc"})),
+                ],
+                "responses": responses(array(reference("Reward"))),
+            }
+        },
+        "/testnet3/provers/top": {
+            "get": {
+                "operationId": "provers_top",
+                "summary": "Provers with the most rewards in [from, to), requires the * scope",
+                "parameters": [
+                    from_param(), to_param(),
+                    query_param("limit", false, json!({"type": "integer", "minimum": 1, "maximum": 100, "default": 10})),
+                ],
+                "responses": responses(array(reference("ProverRank"))),
+            }
+        },
+        "/testnet3/provers/{address}/share": {
+            "get": {
+                "operationId": "prover_share",
+                "summary": "Share of an address in the solutions and rewards of [from, to)",
+                "parameters": [address(), from_param(), to_param()],
+                "responses": responses(reference("ProverShare")),
+            }
+        },
+        "/testnet3/groups": {
+            "get": {
+                "operationId": "groups",
+                "summary": "Groups in the scopes of the api key",
+                "responses": responses(array(reference("Group"))),
+            },
+            "post": {
+                "operationId": "create_group",
+                "summary": "Create a group, requires the * scope",
+                "requestBody": body("GroupBody"),
+                "responses": responses(json!({"type": "boolean"})),
+            }
+        },
+        "/testnet3/groups/{name}": {
+            "delete": {
+                "operationId": "delete_group",
+                "summary": "Delete a group and its members, requires the * scope",
+                "parameters": [name()],
+                "responses": responses(json!({"type": "boolean"})),
+            }
+        },
+        "/testnet3/groups/{name}/members": {
+            "post": {
+                "operationId": "add_group_member",
+                "summary": "Add an address to a group or update its label, requires the * scope",
+                "parameters": [name()],
+                "requestBody": body("MemberBody"),
+                "responses": responses(json!({"type": "boolean"})),
+            }
+        },
+        "/testnet3/groups/{name}/members/{address}": {
+            "delete": {
+                "operationId": "remove_group_member",
+                "summary": "Remove an address from a group, requires the * scope",
+                "parameters": [name(), address()],
+                "responses": responses(json!({"type": "boolean"})),
+            }
+        },
+        "/testnet3/groups/{name}/rewards": {
+            "get": {
+                "operationId": "group_rewards",
+                "summary": "Aggregated rewards of the group members in [from, to)",
+                "parameters": [name(), from_param(), to_param()],
+                "responses": responses(reference("GroupReward")),
+            }
+        },
+        "/graphql": {
+            "post": {
+                "operationId": "graphql",
+                "summary": "GraphQL queries of blocks, solutions and provers, limited in complexity and depth",
+                "requestBody": body("GraphQLRequest"),
+                "responses": {
+                    "200": json_response("the graphql response, errors carry the api error code in their extensions", reference("GraphQLResponse")),
+                    "400": json_response("the body is not a graphql request", reference("Error")),
+                    "401": json_response("missing or invalid api key", reference("Error")),
+                    "429": json_response("rate limited, see Retry-After", reference("Error")),
+                },
+            }
+        },
+        "/metrics": {
+            "get": {
+                "operationId": "metrics",
+                "summary": "Counters in the prometheus text format, requires the * scope",
+                "responses": {
+                    "200": {"description": "success", "content": {"text/plain": {"schema": {"type": "string"}}}},
+                    "401": json_response("missing or invalid api key", reference("Error")),
+                    "403": json_response("the api key is not allowed to query this", reference("Error")),
+                    "429": json_response("rate limited, see Retry-After", reference("Error")),
+                },
+            }
+        },
+        "/openapi.json": {
+            "get": {
+                "operationId": "openapi",
+                "summary": "This document",
+                "security": [],
+                "responses": {"200": {"description": "the openapi document", "content": {"application/json": {}}}},
+            }
+        },
+        "/docs": {
+            "get": {
+                "operationId": "docs",
+                "summary": "The docs page of this document, enabled by api_docs",
+                "security": [],
+                "responses": {
+                    "200": {"description": "the docs page", "content": {"text/html": {}}},
+                    "404": json_response("api_docs is disabled", reference("Error")),
+                },
+            }
+        },
+    })
+}
+
+fn schemas() -> Value {
+    let int64 = |description: &str| integer("int64", description);
+    json!({
+        "Response": {
+            "type": "object",
+            "required": ["code", "message", "data"],
+            "properties": {
+                "code": {"type": "integer", "description": "0 on success"},
+                "message": {"type": "string"},
+                "data": {},
+                "cursor": string("cursor of the next page, only present on paged responses that have more rows"),
+            },
+        },
+        "Error": {
+            "type": "object",
+            "required": ["code", "message", "data"],
+            "properties": {
+                "code": {"type": "integer", "description": "1001-1008 for client errors, 2001-2002 for server errors"},
+                "message": {"type": "string"},
+                "data": {"nullable": true},
+            },
+        },
+        "Reward": {
+            "type": "object",
+            "required": ["address", "height", "nonce", "reward", "timestamp"],
+            "properties": {
+                "address": string("prover address"),
+                "height": integer("int32", "block height"),
+                "nonce": int64("solution nonce"),
+                "reward": int64("reward in microcredits"),
+                "timestamp": int64("block timestamp"),
+            },
+        },
+        "ProverRank": {
+            "type": "object",
+            "required": ["address", "solutions", "reward"],
+            "properties": {
+                "address": string("prover address"),
+                "solutions": int64("number of solutions"),
+                "reward": int64("reward in microcredits"),
+            },
+        },
+        "ProverShare": {
+            "type": "object",
+            "required": ["address", "solutions", "reward", "total_solutions", "total_reward", "solutions_share", "reward_share"],
+            "properties": {
+                "address": string("prover address"),
+                "solutions": int64("solutions of the address"),
+                "reward": int64("reward of the address in microcredits"),
+                "total_solutions": int64("solutions of every prover"),
+                "total_reward": int64("reward of every prover in microcredits"),
+                "solutions_share": {"type": "number", "description": "solutions / total_solutions"},
+                "reward_share": {"type": "number", "description": "reward / total_reward"},
+            },
+        },
+        "GroupMember": {
+            "type": "object",
+            "required": ["address", "label"],
+            "properties": {"address": string("aleo address"), "label": {"type": "string"}},
+        },
+        "Group": {
+            "type": "object",
+            "required": ["name", "label", "members"],
+            "properties": {
+                "name": {"type": "string"},
+                "label": {"type": "string"},
+                "members": array(reference("GroupMember")),
+            },
+        },
+        "GroupReward": {
+            "type": "object",
+            "required": ["name", "solutions", "reward", "members"],
+            "properties": {
+                "name": {"type": "string"},
+                "solutions": int64("solutions of the members"),
+                "reward": int64("reward of the members in microcredits"),
+                "members": array(reference("ProverRank")),
+            },
+        },
+        "GraphQLRequest": {
+            "type": "object",
+            "required": ["query"],
+            "properties": {
+                "query": {"type": "string"},
+                "operationName": {"type": "string"},
+                "variables": {"type": "object"},
+            },
+        },
+        "GraphQLResponse": {
+            "type": "object",
+            "properties": {
+                "data": {"type": "object", "nullable": true},
+                "errors": array(json!({"type": "object"})),
+            },
+        },
+        "GroupBody": {
+            "type": "object",
+            "required": ["name"],
+            "properties": {"name": {"type": "string"}, "label": {"type": "string", "default": ""}},
+        },
+        "MemberBody": {
+            "type": "object",
+            "required": ["address"],
+            "properties": {"address": string("aleo address"), "label": {"type": "string", "default": ""}},
+        },
+    })
+}
+
+/// Returns the openapi 3 document of the api.
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "aleo-blocks api",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Solutions and rewards of the synced blocks. Every response is wrapped in `Response`, `code` is 0 on success.",
+        },
+        "paths": paths(),
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "apiKey": {"type": "apiKey", "in": "header", "name": "X-Api-Key"},
+                "bearer": {"type": "http", "scheme": "bearer"},
+            },
+        },
+        // 仅在开启api_auth时需要
+        "security": [{"apiKey": []}, {"bearer": []}],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::ROUTES;
+
+    #[test]
+    fn every_route_is_documented() {
+        let document = document();
+        for route in ROUTES {
+            let operation = &document["paths"][route.path][route.method];
+            assert!(operation.is_object(), "route {} {} {} is not documented", route.name, route.method, route.path);
+            assert_eq!(operation["operationId"], route.name, "operation id of {} {}", route.method, route.path);
+        }
+    }
+
+    #[test]
+    fn every_documented_operation_is_a_route() {
+        let document = document();
+        for (path, operations) in document["paths"].as_object().unwrap() {
+            for method in operations.as_object().unwrap().keys() {
+                assert!(
+                    ROUTES.iter().any(|route| route.path == path && route.method == method),
+                    "{method} {path} is documented but not in ROUTES"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_reference_resolves() {
+        let document = document();
+        let text = document.to_string();
+        for part in text.split("\"#/components/schemas/").skip(1) {
+            let name = &part[..part.find('"').unwrap()];
+            assert!(document["components"]["schemas"][name].is_object(), "schema {name} is referenced but not defined");
+        }
+    }
+}
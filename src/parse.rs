@@ -1,98 +1,134 @@
 use log::{info, trace};
 use tokio::sync::mpsc;
+use std::collections::HashSet;
 
 use snarkvm_synthesizer::Block;
 use snarkvm_console_network::Network;
 use snarkvm_console_types_address::Address;
-use snarkos_node_consensus::coinbase_reward;
 
 use crate::message::{Message, Solution, BlockReward, SyncHeight};
+use crate::reward::block_rewards;
+
+/// The messages of one parsed block, in the order they are sent.
+#[derive(Debug)]
+pub struct ParsedBlock<N: Network> {
+    pub height: u32,
+    pub messages: Vec<Message<N>>,
+}
+
+impl<N: Network> ParsedBlock<N> {
+    /// A block that could not be parsed, only the synced height moves past it.
+    pub fn skipped(height: u32) -> Self {
+        let messages = vec![Message::SyncHeight(SyncHeight { height, _p: std::marker::PhantomData })];
+        Self { height, messages }
+    }
+}
+
+/// Why a block could not be parsed, the block or its previous block is likely broken.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The block does not follow the previous block.
+    Discontinuity { height: u32, previous_height: u32, round: u64, previous_round: u64 },
+    /// The block follows the previous block by height, but builds on another block of the same height.
+    Fork { height: u32, previous_hash: String, expected_hash: String },
+    /// An intermediate value of the reward does not fit.
+    Overflow { height: u32, step: &'static str },
+    /// The target of a partial solution can not be computed.
+    InvalidSolution { height: u32, index: usize, reason: String },
+    /// The coinbase reward can not be computed.
+    RewardCalculation { height: u32, reason: String },
+}
+
+impl ParseError {
+    /// The height of the block that failed to parse.
+    pub fn height(&self) -> u32 {
+        match self {
+            Self::Discontinuity { height, .. }
+            | Self::Fork { height, .. }
+            | Self::Overflow { height, .. }
+            | Self::InvalidSolution { height, .. }
+            | Self::RewardCalculation { height, .. } => *height,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Discontinuity { .. } => "discontinuity",
+            Self::Fork { .. } => "fork",
+            Self::Overflow { .. } => "overflow",
+            Self::InvalidSolution { .. } => "invalid_solution",
+            Self::RewardCalculation { .. } => "reward_calculation",
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Discontinuity { height, previous_height, round, previous_round } => write!(
+                f, "block {height} round {round} does not follow block {previous_height} round {previous_round}"
+            ),
+            Self::Fork { height, previous_hash, expected_hash } => write!(
+                f, "block {height} builds on {previous_hash}, not on the synced block {expected_hash}"
+            ),
+            Self::Overflow { height, step } => write!(f, "block {height}: {step} overflows"),
+            Self::InvalidSolution { height, index, reason } => write!(f, "block {height}: invalid partial solution {index}: {reason}"),
+            Self::RewardCalculation { height, reason } => write!(f, "block {height}: failed to calculate the coinbase reward: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Sends the messages of a parsed block, blocks must be emitted in height order.
+pub async fn emit_block<N: Network>(parsed: ParsedBlock<N>, sender: &mpsc::Sender<Message<N>>) -> anyhow::Result<()> {
+    for message in parsed.messages {
+        sender.send(message).await?;
+    }
+    Ok(())
+}
 
-pub async fn parse_block<N: Network>(
+/// Computes the rewards of the block without any io, cpu bound, run it on a blocking thread for many blocks.
+pub fn compute_block<N: Network>(
     current_block: &Block<N>, 
     latest_block: &Block<N>, 
-    address: &Vec<String>, 
-    sender: mpsc::Sender<Message<N>>,
+    address: &HashSet<Address<N>>, 
     store_block: bool,
-) -> anyhow::Result<()>{
-    let latest_height = latest_block.height();
-
-    let coinbase_solution = current_block.coinbase();
+) -> Result<ParsedBlock<N>, ParseError>{
+    let breakdown = block_rewards(current_block, latest_block)?;
+    let next_height = breakdown.height;
     let next_timestamp = current_block.timestamp();
-    let next_height = latest_height.saturating_add(1);
-    assert_eq!(next_height, current_block.height(), "current calc block height not equal latest block add one");
-    let next_round = latest_block.round().saturating_add(1);
-    assert_eq!(next_round, current_block.round(), "current calc block round not equal latest block add one");
 
-    let mut total_reward = 0_u64;
-    let mut solutions_num = 0_usize;
+    let mut messages = Vec::new();
     let mut flag = false;
-    if let Some(coinbase) = coinbase_solution {
-        let partial_solutions = coinbase.partial_solutions();
-        solutions_num = partial_solutions.len();
-        let cumulative_proof_target: u128 = partial_solutions.iter().fold(0u128, |cumulative, solution| {
-            cumulative.checked_add(solution.to_target().unwrap() as u128).unwrap()
-        });
-
-        let coinbase_reward = coinbase_reward(
-            latest_block.last_coinbase_timestamp(),
-            next_timestamp,
-            next_height,
-            N::STARTING_SUPPLY,
-            N::ANCHOR_TIME,
-        ).unwrap();
-
-        let address = address.clone();
-        let mut prover_rewards: Vec<(Address<N>, u64)> = Vec::new();
-        // 每个solution的奖励
-        for partial_solution in partial_solutions {
-            // Prover compensation is defined as:
-            //   1/2 * coinbase_reward * (prover_target / cumulative_prover_target)
-            //   = (coinbase_reward * prover_target) / (2 * cumulative_prover_target)
-
-            // Compute the numerator.
-            let numerator = (coinbase_reward as u128)
-                .checked_mul(partial_solution.to_target().unwrap() as u128).unwrap();
-
-            // Compute the denominator.
-            let denominator = cumulative_proof_target.checked_mul(2).unwrap();
-
-            // Compute the prover reward.
-            let prover_reward = u64::try_from(
-                numerator.checked_div(denominator).unwrap(),
-            ).unwrap();
-
-            prover_rewards.push((partial_solution.address(), prover_reward));
-
-            // 入库存储 
-            if address.is_empty() || address.contains(&partial_solution.address().to_string()) {
-                flag = true;
-                let data = Solution { 
-                    block_height: next_height, 
-                    partial_solution: *partial_solution, 
-                    solution_reward: prover_reward,
-                    timestamp: next_timestamp,
-                };
-                sender.send(Message::Solution(data)).await?;
+    match current_block.coinbase() {
+        Some(coinbase) => {
+            // 每个solution的奖励，与partial solution顺序一致
+            for (partial_solution, solution) in coinbase.partial_solutions().iter().zip(&breakdown.solutions) {
+                trace!("prover {} coinbase reward is {}", solution.address, solution.reward);
+                // 入库存储 
+                if address.is_empty() || address.contains(&solution.address) {
+                    flag = true;
+                    let data = Solution { 
+                        block_height: next_height, 
+                        partial_solution: *partial_solution, 
+                        solution_reward: solution.reward,
+                        timestamp: next_timestamp,
+                    };
+                    messages.push(Message::Solution(data));
+                }
             }
+            info!("block {} coinbase reward is {}, total {} solutions", next_height, breakdown.prover_reward, breakdown.solutions.len());
         }
-
-        // block reward
-        for prover in prover_rewards {
-            trace!("prover {} coinbase reward is {}", prover.0, prover.1);
-            total_reward = total_reward.saturating_add(prover.1);
-        }
-
-        info!("block {} coinbase reward is {}, total {} solutions", next_height, total_reward, partial_solutions.len());
-    } else {
-        info!("block {} had no solutions, maybe no reward, empty block", next_height)
+        None => info!("block {} had no solutions, maybe no reward, empty block", next_height),
     }
 
     if store_block && flag {
         let block = current_block.clone();
-        sender.send(Message::BlockReward(BlockReward { block, block_reward: total_reward, solutions_num })).await?;
+        let solutions_num = breakdown.solutions.len();
+        messages.push(Message::BlockReward(BlockReward { block, block_reward: breakdown.prover_reward, solutions_num }));
     }
 
-    sender.send(Message::SyncHeight(SyncHeight {height: next_height, _p: std::marker::PhantomData})).await?;
-    Ok(())
-}
\ No newline at end of file
+    messages.push(Message::SyncHeight(SyncHeight {height: next_height, _p: std::marker::PhantomData}));
+    Ok(ParsedBlock { height: next_height, messages })
+}
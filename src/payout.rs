@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use snarkvm_console_network::Network;
+
+use crate::storage::{LedgerEntry, ProverRank, Storage, Store};
+use crate::utils::parse_address;
+
+/// The ledger beneficiary collecting the pool fee when `fee_beneficiary` is set.
+const FEE_LINE: &str = "fee";
+
+/// A payout scheme, loaded from a yaml file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutScheme {
+    /// Ledger entries are keyed by the scheme name, renaming it starts a new ledger.
+    pub name: String,
+    /// Pool fee in basis points, 250 = 2.5%.
+    pub fee_bps: u32,
+    /// Payout period in seconds, periods are aligned to the unix epoch.
+    pub period: i64,
+    /// Records the collected fee as a ledger line for this beneficiary.
+    #[serde(default)]
+    pub fee_beneficiary: Option<String>,
+    /// beneficiary -> prover addresses
+    pub beneficiaries: BTreeMap<String, Vec<String>>,
+}
+
+impl PayoutScheme {
+    pub fn load<N: Network>(path: &str) -> Result<Self> {
+        let fobj = std::fs::File::open(path).with_context(|| format!("failed to open payout scheme {path}"))?;
+        let mut scheme: Self = serde_yaml::from_reader(fobj).with_context(|| format!("failed to parse payout scheme {path}"))?;
+
+        if scheme.fee_bps > 10_000 {
+            bail!("fee_bps must not be greater than 10000");
+        }
+        if scheme.period <= 0 {
+            bail!("period must be greater than 0");
+        }
+        // 地址统一为规范格式，且一个地址只能属于一个受益人
+        let mut owners: HashMap<String, String> = HashMap::new();
+        for (beneficiary, addresses) in scheme.beneficiaries.iter_mut() {
+            for address in addresses.iter_mut() {
+                *address = parse_address::<N>(address)?.to_string();
+                if let Some(owner) = owners.insert(address.clone(), beneficiary.clone()) {
+                    bail!("address {address} belongs to both {owner} and {beneficiary}");
+                }
+            }
+        }
+        Ok(scheme)
+    }
+
+    /// Returns the period `[start, end)`, the start must be aligned to the period.
+    pub fn period_range(&self, period_start: i64) -> Result<(i64, i64)> {
+        if period_start % self.period != 0 {
+            bail!("period start {period_start} is not aligned to the period {}", self.period);
+        }
+        Ok((period_start, period_start + self.period))
+    }
+
+    /// Computes the ledger entries of the period from the stored solution rewards.
+    pub async fn compute<N: Network, S: Storage<N>>(&self, store: &Store<N, S>, period_start: i64) -> Result<Vec<LedgerEntry>> {
+        let (begin, end) = self.period_range(period_start)?;
+        let addresses: Vec<String> = self.beneficiaries.values().flatten().cloned().collect();
+        let ranks = store.get_address_rewards(&addresses, begin, end).await?;
+        Ok(self.ledger(begin, end, ranks))
+    }
+
+    /// Sums the rewards of the addresses per beneficiary and takes the fee, ranks of other addresses are ignored.
+    fn ledger(&self, begin: i64, end: i64, ranks: Vec<ProverRank>) -> Vec<LedgerEntry> {
+        let owners: HashMap<&String, &String> = self.beneficiaries.iter()
+            .flat_map(|(beneficiary, addresses)| addresses.iter().map(move |address| (address, beneficiary)))
+            .collect();
+
+        // beneficiary -> (solutions, gross reward)
+        let mut totals: BTreeMap<&String, (u64, u64)> = self.beneficiaries.keys().map(|b| (b, (0, 0))).collect();
+        for rank in ranks {
+            if let Some(beneficiary) = owners.get(&rank.address) {
+                let total = totals.get_mut(beneficiary).unwrap();
+                total.0 += rank.solutions;
+                total.1 += rank.reward;
+            }
+        }
+
+        let entry = |beneficiary: &str, solutions, gross_reward, fee| LedgerEntry {
+            scheme: self.name.clone(),
+            period_start: begin,
+            period_end: end,
+            beneficiary: beneficiary.to_string(),
+            solutions,
+            gross_reward,
+            fee,
+            net_reward: gross_reward - fee,
+        };
+
+        let mut total_fee = 0_u64;
+        let mut entries = Vec::new();
+        for (beneficiary, (solutions, gross_reward)) in totals {
+            let fee = fee(gross_reward, self.fee_bps);
+            total_fee += fee;
+            entries.push(entry(beneficiary.as_str(), solutions, gross_reward, fee));
+        }
+        if let Some(fee_beneficiary) = &self.fee_beneficiary {
+            entries.push(entry(&format!("{FEE_LINE}:{fee_beneficiary}"), 0, total_fee, 0));
+        }
+        entries
+    }
+
+    /// Closes the period and returns its statement, closing an already closed period returns the stored statement.
+    pub async fn close<N: Network, S: Storage<N>>(&self, store: &Store<N, S>, period_start: i64, now: i64) -> Result<Vec<LedgerEntry>> {
+        let (begin, end) = self.period_range(period_start)?;
+        if let Some(entries) = store.get_payout_period(&self.name, begin).await? {
+            info!("payout period {} [{begin}, {end}) is already closed", self.name);
+            return Ok(entries);
+        }
+        if end > now {
+            bail!("payout period [{begin}, {end}) is not over yet");
+        }
+        // 区块同步到周期结束之后才能关闭，否则会按部分数据永久关闭
+        match store.get_latest_timestamp().await? {
+            Some(synced) if synced >= end => {}
+            synced => bail!(
+                "payout period [{begin}, {end}) is not synced yet, the latest synced block is at {}",
+                synced.map_or(String::from("none"), |t| t.to_string())
+            ),
+        }
+
+        let entries = self.compute(store, begin).await?;
+        if !store.close_payout_period(&self.name, begin, end, &entries).await? {
+            // 并发关闭时以先写入的为准
+            return store.get_payout_period(&self.name, begin).await?
+                .context("payout period closed concurrently but not found");
+        }
+        info!("payout period {} [{begin}, {end}) closed with {} entries", self.name, entries.len());
+        Ok(entries)
+    }
+}
+
+/// The pool fee of a gross reward, `fee_bps` is at most 10000, rounded down so the fee never exceeds its share.
+fn fee(gross_reward: u64, fee_bps: u32) -> u64 {
+    (gross_reward as u128 * fee_bps as u128 / 10_000) as u64
+}
+
+/// Formats the statement as csv or json.
+pub fn statement(entries: &[LedgerEntry], format: &str) -> Result<String> {
+    match format {
+        "json" => Ok(serde_json::to_string_pretty(entries)?),
+        "csv" => {
+            // 受益人名称可能包含逗号和引号，由csv writer转义
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            if entries.is_empty() {
+                writer.write_record(["scheme", "period_start", "period_end", "beneficiary", "solutions", "gross_reward", "fee", "net_reward"])?;
+            }
+            for entry in entries {
+                writer.serialize(entry)?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+        _ => bail!("unknown statement format {format}, expected csv or json"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheme(fee_bps: u32, fee_beneficiary: Option<&str>) -> PayoutScheme {
+        PayoutScheme {
+            name: String::from("pool"),
+            fee_bps,
+            period: 86_400,
+            fee_beneficiary: fee_beneficiary.map(String::from),
+            beneficiaries: BTreeMap::from([
+                (String::from("alice"), vec![String::from("aleo1a"), String::from("aleo1b")]),
+                (String::from("bob"), vec![String::from("aleo1c")]),
+                (String::from("carol"), vec![String::from("aleo1d")]),
+            ]),
+        }
+    }
+
+    fn rank(address: &str, solutions: u64, reward: u64) -> ProverRank {
+        ProverRank { address: address.to_string(), solutions, reward }
+    }
+
+    #[test]
+    fn fee_is_rounded_down() {
+        assert_eq!(fee(399, 250), 9);
+        assert_eq!(fee(400, 250), 10);
+        assert_eq!(fee(39, 250), 0);
+        assert_eq!(fee(1_000_000, 0), 0);
+        assert_eq!(fee(1_000_000, 10_000), 1_000_000);
+        assert_eq!(fee(u64::MAX, 10_000), u64::MAX);
+        assert_eq!(fee(u64::MAX, 9_999), (u64::MAX as u128 * 9_999 / 10_000) as u64);
+    }
+
+    #[test]
+    fn ledger_splits_fee_and_net_per_beneficiary() {
+        let ranks = vec![rank("aleo1a", 2, 300), rank("aleo1b", 1, 99), rank("aleo1c", 4, 1_000), rank("aleo1x", 9, 5_000)];
+        let entries = scheme(250, Some("operator")).ledger(0, 86_400, ranks);
+
+        let lines: Vec<(&str, u64, u64, u64, u64)> = entries.iter()
+            .map(|e| (e.beneficiary.as_str(), e.solutions, e.gross_reward, e.fee, e.net_reward))
+            .collect();
+        assert_eq!(lines, vec![
+            // 两个地址合并后再计算手续费
+            ("alice", 3, 399, 9, 390),
+            ("bob", 4, 1_000, 25, 975),
+            // 没有solution的受益人也有一行
+            ("carol", 0, 0, 0, 0),
+            ("fee:operator", 0, 34, 0, 34),
+        ]);
+        for e in &entries {
+            assert_eq!(e.fee + e.net_reward, e.gross_reward);
+            assert_eq!((e.period_start, e.period_end), (0, 86_400));
+        }
+    }
+
+    #[test]
+    fn ledger_without_fee_beneficiary_has_no_fee_line() {
+        let entries = scheme(10_000, None).ledger(0, 86_400, vec![rank("aleo1c", 1, 7)]);
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[1].fee, entries[1].net_reward), (7, 0));
+    }
+
+    #[test]
+    fn period_start_must_be_aligned() {
+        assert_eq!(scheme(0, None).period_range(172_800).unwrap(), (172_800, 259_200));
+        assert!(scheme(0, None).period_range(172_801).is_err());
+    }
+
+    #[test]
+    fn csv_statement_escapes_names() {
+        let mut entries = scheme(0, None).ledger(0, 86_400, vec![]);
+        entries[0].beneficiary = String::from("Acme, Inc. \"west\"");
+        let csv = statement(&entries[..1], "csv").unwrap();
+        assert_eq!(csv, "scheme,period_start,period_end,beneficiary,solutions,gross_reward,fee,net_reward\npool,0,86400,\"Acme, Inc. \"\"west\"\"\",0,0,0,0\n");
+        assert_eq!(statement(&[], "csv").unwrap(), "scheme,period_start,period_end,beneficiary,solutions,gross_reward,fee,net_reward\n");
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use warp::{Filter, Rejection};
+
+use crate::auth::Scopes;
+use crate::metrics::Metrics;
+use crate::server::ApiError;
+use crate::tls::RemoteAddr;
+
+/// The key of the limits applied to every route without its own limits.
+pub const DEFAULT_ROUTE: &str = "default";
+/// How often the idle buckets are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A token bucket, `rate` requests per second on average with bursts of up to `burst` requests.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// The seconds an untouched bucket takes to fill up again, after which it can be dropped.
+    fn refill(&self) -> f64 {
+        self.burst as f64 / self.rate
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Limits the requests per route of every api key, or of every remote address without a key.
+pub struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    // (route, client) -> bucket
+    buckets: Mutex<HashMap<(&'static str, String), Bucket>>,
+    metrics: Arc<Metrics>,
+}
+
+impl RateLimiter {
+    /// `limits` is keyed by route name, the `default` entry applies to the other routes; no entry means no limit.
+    pub fn new(limits: HashMap<String, RateLimit>, metrics: Arc<Metrics>) -> Self {
+        Self { limits, buckets: Default::default(), metrics }
+    }
+
+    fn limit(&self, route: &str) -> Option<RateLimit> {
+        self.limits.get(route).or_else(|| self.limits.get(DEFAULT_ROUTE)).copied()
+    }
+
+    /// Takes a token of the client, returns the seconds to wait when the bucket is empty.
+    pub fn check(&self, route: &'static str, client: String) -> Result<(), u64> {
+        self.take(route, client, Instant::now())
+    }
+
+    fn take(&self, route: &'static str, client: String, now: Instant) -> Result<(), u64> {
+        let limit = match self.limit(route) {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets.entry((route, client)).or_insert(Bucket { tokens: limit.burst as f64, updated: now });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.rate).min(limit.burst as f64);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        self.metrics.rate_limited(route);
+        Err(((1.0 - bucket.tokens) / limit.rate).ceil().max(1.0) as u64)
+    }
+
+    /// Gives back the token taken by `check`.
+    fn refund(&self, route: &'static str, client: String) {
+        if let (Some(limit), Some(bucket)) = (self.limit(route), self.buckets.lock().get_mut(&(route, client))) {
+            bucket.tokens = (bucket.tokens + 1.0).min(limit.burst as f64);
+        }
+    }
+
+    /// Drops the buckets that are full again, returns how many were dropped.
+    fn sweep_idle(&self, now: Instant) -> usize {
+        let mut buckets = self.buckets.lock();
+        let before = buckets.len();
+        buckets.retain(|(route, _), bucket| match self.limit(route) {
+            Some(limit) => now.saturating_duration_since(bucket.updated).as_secs_f64() < limit.refill(),
+            None => false,
+        });
+        before - buckets.len()
+    }
+
+    /// Drops the buckets that are full again, so clients passing by do not pile up.
+    pub async fn sweep(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            debug!("drop {} idle rate limit buckets", self.sweep_idle(Instant::now()));
+        }
+    }
+}
+
+/// Rejects the request with 429 when the client of the route is out of tokens, passes the scopes of `auth` through.
+///
+/// Every request takes a token of its remote address before it is authenticated, so requests with a missing or
+/// invalid key are limited too. A request with a valid key gives that token back and takes one of its key instead.
+pub fn with_rate_limit(
+    auth: impl Filter<Extract = (Arc<Scopes>,), Error = Rejection> + Clone + Send + Sync,
+    limiter: Arc<RateLimiter>,
+    route: &'static str,
+) -> impl Filter<Extract = (Arc<Scopes>,), Error = Rejection> + Clone {
+    let by_addr = limiter.clone();
+    warp::addr::remote()
+        .and(warp::ext::optional::<RemoteAddr>())
+        .and_then(move |remote: Option<SocketAddr>, tls_remote: Option<RemoteAddr>| {
+            let limiter = by_addr.clone();
+            async move {
+                // 使用反向代理时所有请求来自同一地址，应开启api key
+                let remote = remote.or(tls_remote.map(|RemoteAddr(addr)| addr));
+                let client = format!("addr:{}", remote.map(|addr| addr.ip().to_string()).unwrap_or_default());
+                match limiter.check(route, client.clone()) {
+                    Ok(()) => Ok(client),
+                    Err(retry_after) => Err(Rejection::from(ApiError::TooManyRequests { retry_after })),
+                }
+            }
+        })
+        .and(auth)
+        .and_then(move |client: String, scopes: Arc<Scopes>| {
+            let limiter = limiter.clone();
+            async move {
+                let id = match scopes.key() {
+                    Some(id) => id,
+                    None => return Ok(scopes),
+                };
+                limiter.refund(route, client);
+                match limiter.check(route, format!("key:{id}")) {
+                    Ok(()) => Ok(scopes),
+                    Err(retry_after) => Err(Rejection::from(ApiError::TooManyRequests { retry_after })),
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUTE: &str = "top";
+
+    /// One request per 2 seconds with bursts of 2, and no limit on the other routes.
+    fn limiter() -> RateLimiter {
+        let limits = HashMap::from([(String::from(ROUTE), RateLimit { rate: 0.5, burst: 2 })]);
+        RateLimiter::new(limits, Arc::new(Metrics::default()))
+    }
+
+    #[test]
+    fn the_burst_is_spent_then_refilled_at_the_rate() {
+        let limiter = limiter();
+        let start = Instant::now();
+        let take = |secs: f64| limiter.take(ROUTE, String::from("addr:1"), start + Duration::from_secs_f64(secs));
+        assert_eq!(take(0.0), Ok(()));
+        assert_eq!(take(0.0), Ok(()));
+        assert_eq!(take(0.0), Err(2));
+        assert_eq!(take(1.0), Err(1));
+        assert_eq!(take(2.0), Ok(()));
+        // 令牌不超过burst
+        assert_eq!(take(100.0), Ok(()));
+        assert_eq!(take(100.0), Ok(()));
+        assert_eq!(take(100.0), Err(2));
+    }
+
+    #[test]
+    fn clients_and_routes_have_their_own_buckets() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..2 {
+            assert_eq!(limiter.take(ROUTE, String::from("addr:1"), now), Ok(()));
+        }
+        assert!(limiter.take(ROUTE, String::from("addr:1"), now).is_err());
+        assert_eq!(limiter.take(ROUTE, String::from("addr:2"), now), Ok(()));
+        assert_eq!(limiter.take("share", String::from("addr:1"), now), Ok(()));
+        assert_eq!(limiter.metrics.render().lines().last(), Some("aleo_blocks_rate_limited_total{route=\"top\"} 1"));
+    }
+
+    #[test]
+    fn the_default_limit_applies_to_the_other_routes() {
+        let limiter = RateLimiter::new(HashMap::from([(String::from(DEFAULT_ROUTE), RateLimit { rate: 1.0, burst: 1 })]), Default::default());
+        let now = Instant::now();
+        assert_eq!(limiter.take(ROUTE, String::from("addr:1"), now), Ok(()));
+        assert_eq!(limiter.take(ROUTE, String::from("addr:1"), now), Err(1));
+    }
+
+    #[test]
+    fn a_refund_gives_the_token_back() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..2 {
+            assert_eq!(limiter.take(ROUTE, String::from("addr:1"), now), Ok(()));
+        }
+        limiter.refund(ROUTE, String::from("addr:1"));
+        assert_eq!(limiter.take(ROUTE, String::from("addr:1"), now), Ok(()));
+    }
+
+    #[test]
+    fn only_full_buckets_are_swept() {
+        let limiter = limiter();
+        let start = Instant::now();
+        limiter.take(ROUTE, String::from("addr:1"), start).unwrap();
+        limiter.take(ROUTE, String::from("addr:2"), start + Duration::from_secs(3)).unwrap();
+        // 补满需要 burst / rate = 4秒
+        assert_eq!(limiter.sweep_idle(start + Duration::from_secs(3)), 0);
+        assert_eq!(limiter.sweep_idle(start + Duration::from_secs(4)), 1);
+        assert_eq!(limiter.buckets.lock().len(), 1);
+        assert_eq!(limiter.sweep_idle(start + Duration::from_secs(7)), 1);
+    }
+}
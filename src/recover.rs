@@ -0,0 +1,218 @@
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use snarkvm_console_network::Network;
+use snarkvm_console_types_address::Address;
+use snarkvm_synthesizer::Block;
+
+use crate::manage::ApiManage;
+use crate::parse::{compute_block, ParseError, ParsedBlock};
+
+/// What the sync does with a block that still fails to parse after refetching it from the other apis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseErrorPolicy {
+    /// Stops the sync at the block.
+    #[default]
+    Stop,
+    /// Records the block in the quarantine file and moves past it, its solutions are not recorded.
+    Skip,
+}
+
+/// How blocks are parsed by `Batch` and `Single`.
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub store_block: bool,
+    pub on_parse_error: ParseErrorPolicy,
+    /// The json lines file of the skipped blocks.
+    pub quarantine_file: String,
+}
+
+/// A skipped block, appended to the quarantine file.
+#[derive(Debug, Serialize)]
+struct QuarantineEntry<'a> {
+    height: u32,
+    kind: &'static str,
+    error: String,
+    /// The apis the block was fetched from, every one of them returned a block that failed to parse.
+    apis: &'a [String],
+    timestamp: u64,
+}
+
+fn quarantine(path: &str, error: &ParseError, apis: &[String]) -> Result<()> {
+    let entry = QuarantineEntry {
+        height: error.height(),
+        kind: error.kind(),
+        error: error.to_string(),
+        apis,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    let mut file = OpenOptions::new().create(true).append(true).open(path)
+        .with_context(|| format!("failed to open quarantine file {path}"))?;
+    writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+    Ok(())
+}
+
+/// Fetches the block at `height` and the one before it from the api.
+async fn fetch_pair<N: Network>(api_manage: &ApiManage, api: &str, height: u32) -> Result<(Block<N>, Block<N>)> {
+    let start = height.checked_sub(1).ok_or_else(|| anyhow!("the genesis block has no previous block"))?;
+    let response = api_manage.get_from(api, &format!("/blocks?start={start}&end={}", height + 1)).await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        bail!("status {status}, body {body}");
+    }
+    let mut blocks: Vec<Block<N>> = serde_json::from_str(&body)?;
+    match (blocks.pop(), blocks.pop()) {
+        (Some(current), Some(previous)) if current.height() == height => Ok((previous, current)),
+        _ => bail!("the api did not return the blocks {start} and {height}"),
+    }
+}
+
+/// Refetches the block that failed to parse from every api but `source`, the api it came from,
+/// then applies the parse error policy.
+///
+/// Returns the parsed block with the refetched block, the previous block of the next height, or the skipped
+/// block without one when it is quarantined.
+pub async fn recover<N: Network>(
+    api_manage: &ApiManage,
+    source: &str,
+    options: &ParseOptions,
+    address: &HashSet<Address<N>>,
+    error: ParseError,
+) -> Result<(ParsedBlock<N>, Option<Block<N>>)> {
+    let height = error.height();
+    warn!("parse block {} from {} failed: {}, refetching it from the other apis", height, source, error);
+
+    let apis = api_manage.apis();
+    for api in apis.iter().filter(|api| *api != source) {
+        let (previous, current) = match fetch_pair::<N>(api_manage, api, height).await {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                warn!("refetch block {} from {} failed: {:?}", height, api, e);
+                continue;
+            }
+        };
+        match compute_block(&current, &previous, address, options.store_block) {
+            Ok(parsed) => {
+                warn!("block {} parsed with the blocks of {}", height, api);
+                return Ok((parsed, Some(current)));
+            }
+            Err(e) => warn!("block {} from {} failed to parse: {}", height, api, e),
+        }
+    }
+
+    match options.on_parse_error {
+        ParseErrorPolicy::Stop => Err(anyhow!(error)),
+        ParseErrorPolicy::Skip => {
+            quarantine(&options.quarantine_file, &error, &apis)?;
+            error!("block {} is skipped and quarantined in {}: {}", height, options.quarantine_file, error);
+            Ok((ParsedBlock::skipped(height), None))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::time::Duration;
+
+    use backoff::ExponentialBackoff;
+    use reqwest::Client;
+    use snarkvm_console_network::Testnet3;
+
+    use super::*;
+    use crate::message::Message;
+    use crate::mock::MockApi;
+
+    /// Blocks that are not valid aleo blocks, so every refetch fails to deserialize.
+    fn broken_blocks() -> BTreeMap<u32, String> {
+        (0..10).map(|height| (height, format!("{{\"height\":{height}}}"))).collect()
+    }
+
+    fn api_manage(apis: Vec<String>) -> ApiManage {
+        let backoff = ExponentialBackoff { max_elapsed_time: Some(Duration::from_millis(100)), ..Default::default() };
+        ApiManage::new(Client::new(), apis).with_backoff(backoff)
+    }
+
+    fn options(name: &str, on_parse_error: ParseErrorPolicy) -> ParseOptions {
+        let quarantine_file = std::env::temp_dir().join(format!("aleo-blocks-quarantine-{name}-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&quarantine_file);
+        ParseOptions { store_block: false, on_parse_error, quarantine_file: quarantine_file.to_str().unwrap().to_string() }
+    }
+
+    fn error(height: u32) -> ParseError {
+        ParseError::Discontinuity { height, previous_height: height - 1, round: 7, previous_round: 9 }
+    }
+
+    #[tokio::test]
+    async fn every_api_but_the_source_is_tried() {
+        let (first, source, last) = (MockApi::start(broken_blocks()), MockApi::start(broken_blocks()), MockApi::start(broken_blocks()));
+        let api_manage = api_manage(vec![first.url(), source.url(), last.url()]);
+        let options = options("tried", ParseErrorPolicy::Stop);
+
+        assert!(recover::<Testnet3>(&api_manage, &source.url(), &options, &HashSet::new(), error(5)).await.is_err());
+        assert!(source.requests().is_empty());
+        for mock in [&first, &last] {
+            assert_eq!(mock.requests(), vec![String::from("/testnet3/blocks?start=4&end=6")]);
+        }
+    }
+
+    #[tokio::test]
+    async fn stop_returns_the_parse_error() {
+        let source = MockApi::start(broken_blocks());
+        let other = MockApi::start(broken_blocks());
+        let api_manage = api_manage(vec![source.url(), other.url()]);
+        let options = options("stop", ParseErrorPolicy::Stop);
+
+        let error = recover::<Testnet3>(&api_manage, &source.url(), &options, &HashSet::new(), error(5)).await.unwrap_err();
+        assert_eq!(error.to_string(), "block 5 round 7 does not follow block 4 round 9");
+        assert!(!std::path::Path::new(&options.quarantine_file).exists());
+    }
+
+    #[tokio::test]
+    async fn skip_quarantines_the_block_and_moves_past_it() {
+        let source = MockApi::start(broken_blocks());
+        let other = MockApi::start(broken_blocks());
+        let api_manage = api_manage(vec![source.url(), other.url()]);
+        let options = options("skip", ParseErrorPolicy::Skip);
+
+        for height in [5, 6] {
+            let (parsed, block) = recover::<Testnet3>(&api_manage, &source.url(), &options, &HashSet::new(), error(height)).await.unwrap();
+            assert!(block.is_none());
+            assert_eq!(parsed.height, height);
+            assert!(matches!(parsed.messages.as_slice(), [Message::SyncHeight(sync)] if sync.height == height));
+        }
+
+        let lines: Vec<serde_json::Value> = fs::read_to_string(&options.quarantine_file).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["height"], 5);
+        assert_eq!(lines[0]["kind"], "discontinuity");
+        assert_eq!(lines[0]["error"], "block 5 round 7 does not follow block 4 round 9");
+        assert_eq!(lines[0]["apis"], serde_json::json!([source.url(), other.url()]));
+        assert_eq!(lines[1]["height"], 6);
+        fs::remove_file(&options.quarantine_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn the_genesis_block_can_not_be_refetched() {
+        let source = MockApi::start(broken_blocks());
+        let other = MockApi::start(broken_blocks());
+        let api_manage = api_manage(vec![source.url(), other.url()]);
+        let options = options("genesis", ParseErrorPolicy::Stop);
+
+        let error = ParseError::Overflow { height: 0, step: "prover reward" };
+        assert!(recover::<Testnet3>(&api_manage, &source.url(), &options, &HashSet::new(), error).await.is_err());
+        assert!(other.requests().is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+
+use snarkvm_console_network::Network;
+use snarkvm_console_types_address::Address;
+use snarkvm_synthesizer::Block;
+use snarkos_node_consensus::coinbase_reward;
+
+use crate::parse::ParseError;
+
+/// The reward of one partial solution.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolutionReward<A> {
+    pub address: A,
+    pub target: u64,
+    pub reward: u64,
+}
+
+/// How the coinbase reward of a block is split between its solutions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewardBreakdown<A> {
+    pub height: u32,
+    /// 0 for a block without solutions, its coinbase reward is not computed.
+    pub coinbase_reward: u64,
+    /// A decimal string in json, it may not fit in a json number.
+    #[serde(with = "u128_string")]
+    pub cumulative_proof_target: u128,
+    /// In the order of the partial solutions of the block.
+    pub solutions: Vec<SolutionReward<A>>,
+    /// The sum of the solution rewards.
+    pub prover_reward: u64,
+    /// Half the coinbase reward minus `prover_reward`, lost by rounding every solution reward down.
+    pub remainder: u64,
+}
+
+mod u128_string {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+/// Splits half of the coinbase reward between the solutions in proportion to their targets.
+///
+/// Prover compensation is defined as:
+///   1/2 * coinbase_reward * (prover_target / cumulative_prover_target)
+///   = (coinbase_reward * prover_target) / (2 * cumulative_prover_target)
+pub fn split_coinbase<A>(height: u32, coinbase_reward: u64, solutions: Vec<(A, u64)>) -> Result<RewardBreakdown<A>, ParseError> {
+    let overflow = |step| ParseError::Overflow { height, step };
+
+    let cumulative_proof_target: u128 = solutions.iter()
+        .try_fold(0u128, |cumulative, (_, target)| cumulative.checked_add(*target as u128))
+        .ok_or_else(|| overflow("cumulative proof target"))?;
+    let denominator = cumulative_proof_target.checked_mul(2).ok_or_else(|| overflow("prover reward denominator"))?;
+
+    let mut rewards = Vec::with_capacity(solutions.len());
+    let mut prover_reward = 0_u64;
+    for (address, target) in solutions {
+        let numerator = (coinbase_reward as u128)
+            .checked_mul(target as u128)
+            .ok_or_else(|| overflow("prover reward numerator"))?;
+        let quotient = numerator.checked_div(denominator).ok_or_else(|| ParseError::RewardCalculation {
+            height,
+            reason: String::from("the cumulative proof target is zero"),
+        })?;
+        let reward = u64::try_from(quotient).map_err(|_| overflow("prover reward"))?;
+        prover_reward = prover_reward.checked_add(reward).ok_or_else(|| overflow("total prover reward"))?;
+        rewards.push(SolutionReward { address, target, reward });
+    }
+
+    // 每个solution的奖励向下取整，总和不会超过coinbase奖励的一半
+    let remainder = (coinbase_reward / 2).checked_sub(prover_reward).ok_or_else(|| overflow("rounding remainder"))?;
+    Ok(RewardBreakdown { height, coinbase_reward, cumulative_proof_target, solutions: rewards, prover_reward, remainder })
+}
+
+/// Computes the reward breakdown of `current`, `previous` is the block before it and `current` must build on it.
+pub fn block_rewards<N: Network>(current: &Block<N>, previous: &Block<N>) -> Result<RewardBreakdown<Address<N>>, ParseError> {
+    let height = previous.height().saturating_add(1);
+    let round = previous.round().saturating_add(1);
+    if height != current.height() || round != current.round() {
+        return Err(ParseError::Discontinuity {
+            height: current.height(),
+            previous_height: previous.height(),
+            round: current.round(),
+            previous_round: previous.round(),
+        });
+    }
+    // 高度连续但不是基于同一个块，其中一个块在分叉上
+    if current.previous_hash() != previous.hash() {
+        return Err(ParseError::Fork {
+            height: current.height(),
+            previous_hash: current.previous_hash().to_string(),
+            expected_hash: previous.hash().to_string(),
+        });
+    }
+
+    let partial_solutions = match current.coinbase() {
+        Some(coinbase) => coinbase.partial_solutions(),
+        None => return split_coinbase(height, 0, Vec::new()),
+    };
+    // to_target 计算量较大，只计算一次
+    let solutions = partial_solutions.iter().enumerate()
+        .map(|(index, solution)| match solution.to_target() {
+            Ok(target) => Ok((solution.address(), target)),
+            Err(e) => Err(ParseError::InvalidSolution { height, index, reason: e.to_string() }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let coinbase_reward = coinbase_reward(
+        previous.last_coinbase_timestamp(),
+        current.timestamp(),
+        height,
+        N::STARTING_SUPPLY,
+        N::ANCHOR_TIME,
+    ).map_err(|e| ParseError::RewardCalculation { height, reason: e.to_string() })?;
+
+    split_coinbase(height, coinbase_reward, solutions)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use serde_json::{json, Value};
+    use snarkvm_console_network::Testnet3;
+
+    use super::*;
+    use crate::mock::{fixtures_dir as blocks_dir, fork_dir, load_blocks};
+
+    /// The fields of a block the reward depends on, the coinbase reward is taken as given.
+    #[derive(Debug, Deserialize)]
+    struct Fixture {
+        height: u32,
+        coinbase_reward: u64,
+        solutions: Vec<FixtureSolution>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct FixtureSolution {
+        address: String,
+        target: u64,
+    }
+
+    fn fixtures_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/rewards")
+    }
+
+    fn breakdown(fixture: Fixture) -> Value {
+        let solutions = fixture.solutions.into_iter().map(|s| (s.address, s.target)).collect();
+        match split_coinbase(fixture.height, fixture.coinbase_reward, solutions) {
+            Ok(breakdown) => serde_json::to_value(breakdown).unwrap(),
+            Err(e) => json!({"error": e.kind(), "height": e.height(), "message": e.to_string()}),
+        }
+    }
+
+    /// Compares every fixture with its `.golden.json`, run with `UPDATE_GOLDEN=1` to rewrite the golden files.
+    #[test]
+    fn golden_breakdowns() {
+        let update = std::env::var("UPDATE_GOLDEN").is_ok();
+        let mut checked = 0;
+        for entry in fs::read_dir(fixtures_dir()).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            if !name.ends_with(".json") || name.ends_with(".golden.json") {
+                continue;
+            }
+            let fixture: Fixture = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+            let actual = breakdown(fixture);
+            let golden = path.with_file_name(name.replace(".json", ".golden.json"));
+            if update {
+                fs::write(&golden, serde_json::to_string_pretty(&actual).unwrap() + "\n").unwrap();
+            } else {
+                let expected: Value = serde_json::from_slice(&fs::read(&golden).unwrap()).unwrap();
+                assert_eq!(actual, expected, "{name} does not match {}", golden.display());
+            }
+            checked += 1;
+        }
+        assert!(checked > 0, "no fixture in {}", fixtures_dir().display());
+    }
+
+    #[test]
+    fn rewards_never_exceed_half_the_coinbase() {
+        for coinbase_reward in [0, 1, 2, 3, 999_999_999, u64::MAX] {
+            let targets = vec![("a", 1), ("b", 7), ("c", u64::MAX / 3), ("d", 12_345)];
+            let breakdown = split_coinbase(1, coinbase_reward, targets).unwrap();
+            assert_eq!(breakdown.prover_reward + breakdown.remainder, coinbase_reward / 2);
+            assert!(breakdown.remainder < breakdown.solutions.len() as u64 || breakdown.solutions.is_empty());
+        }
+    }
+
+    #[test]
+    fn zero_targets_are_a_parse_error() {
+        let error = split_coinbase(42, 1000, vec![("a", 0), ("b", 0)]).unwrap_err();
+        assert!(matches!(error, ParseError::RewardCalculation { height: 42, .. }), "{error:?}");
+        assert_eq!(error.height(), 42);
+        assert_eq!(error.kind(), "reward_calculation");
+        assert_eq!(error.to_string(), "block 42: failed to calculate the coinbase reward: the cumulative proof target is zero");
+    }
+
+    /// The breakdown with the addresses as strings.
+    fn block_breakdown(breakdown: RewardBreakdown<Address<Testnet3>>) -> RewardBreakdown<String> {
+        RewardBreakdown {
+            height: breakdown.height,
+            coinbase_reward: breakdown.coinbase_reward,
+            cumulative_proof_target: breakdown.cumulative_proof_target,
+            solutions: breakdown.solutions.into_iter()
+                .map(|s| SolutionReward { address: s.address.to_string(), target: s.target, reward: s.reward })
+                .collect(),
+            prover_reward: breakdown.prover_reward,
+            remainder: breakdown.remainder,
+        }
+    }
+
+    /// Compares the breakdown of every pair of fixture blocks with `blocks/{height}.golden.json`.
+    ///
+    /// The goldens are written with `UPDATE_GOLDEN=1`, check every solution reward against the explorer
+    /// before committing goldens of recorded blocks. The split is also recomputed here without `split_coinbase`.
+    #[test]
+    fn fixture_block_breakdowns() {
+        let update = std::env::var("UPDATE_GOLDEN").is_ok();
+        let blocks: BTreeMap<u32, Block<Testnet3>> = load_blocks(&blocks_dir()).unwrap().into_iter()
+            .map(|(height, block)| (height, serde_json::from_str(&block).unwrap()))
+            .collect();
+        let golden_dir = fixtures_dir().join("blocks");
+        let mut checked = 0;
+        for (height, current) in &blocks {
+            let previous = match height.checked_sub(1).and_then(|height| blocks.get(&height)) {
+                Some(previous) => previous,
+                None => continue,
+            };
+            let breakdown = block_breakdown(block_rewards(current, previous).unwrap());
+
+            let cumulative: u128 = breakdown.solutions.iter().map(|s| s.target as u128).sum();
+            assert_eq!(breakdown.cumulative_proof_target, cumulative, "block {height}");
+            for solution in &breakdown.solutions {
+                let reward = breakdown.coinbase_reward as u128 * solution.target as u128 / (2 * cumulative);
+                assert_eq!(solution.reward as u128, reward, "block {height} solution of {}", solution.address);
+            }
+            assert_eq!(breakdown.prover_reward + breakdown.remainder, breakdown.coinbase_reward / 2, "block {height}");
+
+            let golden = golden_dir.join(format!("{height}.golden.json"));
+            if update {
+                fs::create_dir_all(&golden_dir).unwrap();
+                fs::write(&golden, serde_json::to_string_pretty(&breakdown).unwrap() + "\n").unwrap();
+            } else {
+                let expected: RewardBreakdown<String> = serde_json::from_slice(&fs::read(&golden).unwrap()).unwrap();
+                assert_eq!(breakdown, expected, "block {height} does not match {}", golden.display());
+            }
+            checked += 1;
+        }
+        assert!(checked > 0, "no pair of consecutive blocks in {}", blocks_dir().display());
+    }
+
+    /// A block is refused after any block but the one before it.
+    #[test]
+    fn fixture_blocks_out_of_order_are_a_discontinuity() {
+        let blocks = load_blocks(&blocks_dir()).unwrap();
+        let (&start, _) = blocks.iter().next().expect("no fixture block");
+        let block = |height: u32| -> Block<Testnet3> { serde_json::from_str(&blocks[&height]).unwrap() };
+        let (first, third) = (block(start), block(start + 2));
+
+        for (current, previous) in [(&third, &first), (&first, &third), (&first, &first)] {
+            let error = block_rewards(current, previous).unwrap_err();
+            assert!(matches!(error, ParseError::Discontinuity { .. }), "{error:?}");
+            assert_eq!(error.height(), current.height());
+        }
+    }
+
+    /// A block of another branch is refused after the block of the same height it does not build on.
+    #[test]
+    fn a_block_of_another_branch_is_a_fork() {
+        let blocks = load_blocks(&blocks_dir()).unwrap();
+        let branch = load_blocks(&fork_dir()).unwrap();
+        let (&height, _) = branch.iter().next().expect("no block of another branch");
+        let block = |blocks: &BTreeMap<u32, String>, height: u32| -> Block<Testnet3> { serde_json::from_str(&blocks[&height]).unwrap() };
+
+        // 分支的第一个块基于同一个前块
+        assert!(block_rewards(&block(&branch, height), &block(&blocks, height - 1)).is_ok());
+        let (current, previous) = (block(&branch, height + 1), block(&blocks, height));
+        let error = block_rewards(&current, &previous).unwrap_err();
+        assert!(matches!(error, ParseError::Fork { .. }), "{error:?}");
+        assert_eq!(error.height(), height + 1);
+        assert_eq!(error.kind(), "fork");
+        assert_eq!(
+            error.to_string(),
+            format!("block {} builds on {}, not on the synced block {}", height + 1, current.previous_hash(), previous.hash())
+        );
+    }
+}
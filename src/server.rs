@@ -1,16 +1,26 @@
 use std::{
     sync::Arc, 
+    collections::HashMap,
     marker::{Send, Sync}, 
     net::SocketAddr,
+    convert::Infallible,
 };
-use log::debug;
-use serde::Serialize;
+use log::{debug, error};
+use serde::{Serialize, Deserialize};
 
 use tokio::task::JoinHandle;
-use http::header::HeaderName;
+use http::{header::{HeaderName, RETRY_AFTER}, HeaderValue, StatusCode};
+use async_graphql_warp::GraphQLResponse;
 use warp::{reply, Filter, Rejection, Reply};
 
-use crate::storage::{Storage, Store};
+use crate::auth::{with_auth, KeyStore, Scopes};
+use crate::cache::ResponseCache;
+use crate::graphql::ApiSchema;
+use crate::metrics::Metrics;
+use crate::tls::TlsConfig;
+use crate::ratelimit::{with_rate_limit, RateLimit, RateLimiter};
+use crate::storage::{Storage, Store, Cursor, Order, Page};
+use crate::utils::parse_address;
 use snarkvm_console_network::Network;
 
 #[derive(Debug, Serialize)]
@@ -18,22 +28,228 @@ pub struct Response<T> {
     pub code: i32,
     pub message: String,
     pub data: T,
+    /// Cursor of the next page, only present on paged responses that have more rows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
 }
 
 impl<T: Serialize> Response<T> {
     pub fn new(code: i32, message: String, data: T) -> Self {
-        Self { code, message, data }
+        Self { code, message, data, cursor: None }
     }
 
     pub fn success(data: T) -> Self {
         Self::new(0, String::from("success"), data)
     }
 
+    pub fn page(data: T, cursor: Option<Cursor>) -> Self {
+        Self { cursor: cursor.map(|c| c.to_string()), ..Self::success(data) }
+    }
+
     pub fn json(&self) -> reply::Json {
         reply::json(&self)
     }
+
+    pub fn with_status(&self, status: StatusCode) -> reply::WithStatus<reply::Json> {
+        reply::with_status(self.json(), status)
+    }
+}
+
+impl Response<()> {
+    pub fn error(error: &ApiError) -> Self {
+        Self::new(error.code(), error.to_string(), ())
+    }
+}
+
+/// Errors returned by the api, each one has a non-zero code and a matching http status.
+#[derive(Debug)]
+pub enum ApiError {
+    InvalidAddress(String),
+    InvalidRange { begin: i64, end: i64 },
+    InvalidParam(String),
+    NotFound,
+    MethodNotAllowed,
+    Unauthorized,
+    Forbidden,
+    TooManyRequests { retry_after: u64 },
+    Storage(anyhow::Error),
+    /// The details are logged, the client only gets a generic message.
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::InvalidAddress(_) => 1001,
+            Self::InvalidRange { .. } => 1002,
+            Self::InvalidParam(_) => 1003,
+            Self::NotFound => 1004,
+            Self::MethodNotAllowed => 1005,
+            Self::Unauthorized => 1006,
+            Self::Forbidden => 1007,
+            Self::TooManyRequests { .. } => 1008,
+            Self::Storage(_) => 2001,
+            Self::Internal(_) => 2002,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidAddress(_) | Self::InvalidRange { .. } | Self::InvalidParam(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::Storage(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidAddress(address) => write!(f, "invalid address {address}"),
+            Self::InvalidRange { begin, end } => write!(f, "invalid range, begin {begin} must be less than end {end}"),
+            Self::InvalidParam(message) => write!(f, "invalid parameter: {message}"),
+            Self::NotFound => write!(f, "not found"),
+            Self::MethodNotAllowed => write!(f, "method not allowed"),
+            Self::Unauthorized => write!(f, "missing or invalid api key"),
+            Self::Forbidden => write!(f, "the api key is not allowed to query this"),
+            Self::TooManyRequests { retry_after } => write!(f, "too many requests, retry after {retry_after} seconds"),
+            Self::Storage(_) => write!(f, "storage unavailable"),
+            Self::Internal(_) => write!(f, "internal error"),
+        }
+    }
+}
+
+impl warp::reject::Reject for ApiError {}
+
+/// Returns the error as a `Response` with its http status, a rate limited request also gets `Retry-After`.
+fn error_reply(error: &ApiError) -> reply::Response {
+    let mut response = Response::error(error).with_status(error.status()).into_response();
+    if let ApiError::TooManyRequests { retry_after } = error {
+        response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(*retry_after));
+    }
+    response
+}
+
+/// Converts every rejection into a `Response` with a non-zero code, storage errors are logged and never hidden.
+pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Infallible> {
+    let error = if let Some(error) = rejection.find::<ApiError>() {
+        match error {
+            ApiError::Storage(e) => error!("storage error: {:?}", e),
+            ApiError::Internal(message) => error!("internal error: {}", message),
+            _ => {}
+        }
+        return Ok(error_reply(error));
+    } else if rejection.is_not_found() {
+        ApiError::NotFound
+    } else if let Some(e) = rejection.find::<warp::reject::InvalidQuery>() {
+        ApiError::InvalidParam(e.to_string())
+    } else if let Some(e) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        ApiError::InvalidParam(e.to_string())
+    } else if let Some(e) = rejection.find::<async_graphql_warp::GraphQLBadRequest>() {
+        ApiError::InvalidParam(e.to_string())
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        ApiError::MethodNotAllowed
+    } else {
+        // 细节只记日志，不返回给客户端
+        error!("unhandled rejection: {:?}", rejection);
+        ApiError::Internal(String::from("unhandled rejection"))
+    };
+    Ok(error_reply(&error))
+}
+
+/// Parses the address and returns its canonical form.
+pub(crate) fn canonical_address<N: Network>(address: &str) -> Result<String, ApiError> {
+    parse_address::<N>(address)
+        .map(|address| address.to_string())
+        .map_err(|_| ApiError::InvalidAddress(address.to_string()))
+}
+
+/// Checks the time range `[begin, end)` is not empty.
+fn validate_range(begin: i64, end: i64) -> Result<(), ApiError> {
+    if begin < end {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidRange { begin, end })
+    }
+}
+
+/// Query parameters of the prover leaderboard, timestamp -> [from, to).
+#[derive(Debug, Deserialize)]
+pub struct TopQuery {
+    pub from: i64,
+    pub to: i64,
+    pub limit: Option<u32>,
+}
+
+/// Query parameters of a time window, timestamp -> [from, to).
+#[derive(Debug, Deserialize)]
+pub struct RangeQuery {
+    pub from: i64,
+    pub to: i64,
+}
+
+/// Body of the group creation.
+#[derive(Debug, Deserialize)]
+pub struct GroupBody {
+    pub name: String,
+    #[serde(default)]
+    pub label: String,
+}
+
+/// Body of the group member addition.
+#[derive(Debug, Deserialize)]
+pub struct MemberBody {
+    pub address: String,
+    #[serde(default)]
+    pub label: String,
+}
+
+/// Query parameters of paged solution queries.
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    pub limit: Option<u32>,
+    pub cursor: Option<String>,
+    pub order: Option<Order>,
+}
+
+/// The default and maximum number of solutions returned in one page.
+const DEFAULT_PAGE_LIMIT: u32 = 100;
+const MAX_PAGE_LIMIT: u32 = 1000;
+
+/// The default and maximum number of provers returned by the leaderboard.
+const DEFAULT_TOP_LIMIT: u32 = 10;
+const MAX_TOP_LIMIT: u32 = 100;
+
+/// A route of the api, `name` is the key of its rate limit and the operation id in the openapi document.
+#[derive(Debug, Clone, Copy)]
+pub struct Route {
+    pub name: &'static str,
+    pub method: &'static str,
+    pub path: &'static str,
 }
 
+/// Every route served by `Server::routes`, the server tests request each one and the openapi tests check it is documented.
+pub const ROUTES: &[Route] = &[
+    Route { name: "solutions_rewards", method: "get", path: "/testnet3/solutions/rewards/{address}/{begin}/{end}" },
+    Route { name: "provers_top", method: "get", path: "/testnet3/provers/top" },
+    Route { name: "prover_share", method: "get", path: "/testnet3/provers/{address}/share" },
+    Route { name: "groups", method: "get", path: "/testnet3/groups" },
+    Route { name: "create_group", method: "post", path: "/testnet3/groups" },
+    Route { name: "delete_group", method: "delete", path: "/testnet3/groups/{name}" },
+    Route { name: "add_group_member", method: "post", path: "/testnet3/groups/{name}/members" },
+    Route { name: "remove_group_member", method: "delete", path: "/testnet3/groups/{name}/members/{address}" },
+    Route { name: "group_rewards", method: "get", path: "/testnet3/groups/{name}/rewards" },
+    Route { name: "graphql", method: "post", path: "/graphql" },
+    Route { name: "metrics", method: "get", path: "/metrics" },
+    Route { name: "openapi", method: "get", path: "/openapi.json" },
+    Route { name: "docs", method: "get", path: "/docs" },
+];
+
 /// A middleware to include the given item in the handler.
 pub fn with<T: Clone + Send>(item: T) -> impl Filter<Extract = (T,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || item.clone())
@@ -42,29 +258,76 @@ pub fn with<T: Clone + Send>(item: T) -> impl Filter<Extract = (T,), Error = std
 
 pub struct Server<N: Network, S: Storage<N> + Send + Sync + 'static> {
     store: Arc<Store<N, S>>,
+    cache: Arc<ResponseCache>,
+    /// None when the api key authentication is disabled.
+    keys: Option<Arc<KeyStore>>,
+    limiter: Arc<RateLimiter>,
+    metrics: Arc<Metrics>,
+    openapi: Arc<serde_json::Value>,
+    graphql: ApiSchema,
+    /// Serves the docs page at `/docs`.
+    docs: bool,
     handles: Vec<Arc<JoinHandle<()>>>,
 }
 
 impl<N: Network, S: Storage<N> + Send + Sync + 'static> Server<N, S> {
-    pub fn start(listen_ip: SocketAddr, store: Store<N, S>) -> Self {
+    /// `cache_size` is the size of the response cache in bytes, 0 disables it.
+    /// `rate_limits` is keyed by route name or `default`, empty disables the rate limit.
+    /// `graphql_complexity` is the maximum complexity of a graphql query.
+    /// `tls` serves https instead of http, it requires the tls feature.
+    #[allow(clippy::too_many_arguments)]
+    pub fn start(
+        listen_ip: SocketAddr,
+        store: Store<N, S>,
+        cache_size: usize,
+        keys: Option<KeyStore>,
+        rate_limits: HashMap<String, RateLimit>,
+        docs: bool,
+        graphql_complexity: usize,
+        tls: Option<TlsConfig>,
+    ) -> Self {
+        let metrics = Arc::new(Metrics::default());
+        let store = Arc::new(store);
         let mut server = Self { 
-            store: Arc::new(store),
+            graphql: crate::graphql::schema(store.clone(), graphql_complexity),
+            store,
+            cache: Arc::new(ResponseCache::new(cache_size)),
+            keys: keys.map(Arc::new),
+            limiter: Arc::new(RateLimiter::new(rate_limits, metrics.clone())),
+            metrics,
+            openapi: Arc::new(crate::openapi::document()),
+            docs,
             handles: vec![],
         };
 
-        server.spawn_server(listen_ip);
+        server.handles.push(Arc::new(tokio::spawn(server.limiter.clone().sweep())));
+        if let Some(keys) = &server.keys {
+            server.handles.push(Arc::new(tokio::spawn(keys.clone().watch())));
+        }
+        if cache_size > 0 {
+            let (cache, store) = (server.cache.clone(), server.store.clone());
+            server.handles.push(Arc::new(tokio::spawn(crate::cache::watch_finalized(cache, store))));
+        }
+        server.spawn_server(listen_ip, tls);
         server
     }
 
      /// Initializes the server.
-     fn spawn_server(&mut self, rest_ip: SocketAddr) {
+     fn spawn_server(&mut self, rest_ip: SocketAddr, tls: Option<TlsConfig>) {
         let cors = warp::cors()
             .allow_any_origin()
-            .allow_header(HeaderName::from_static("content-type"))
-            .allow_methods(vec!["GET", "POST", "OPTIONS"]);
+            .allow_headers(vec![
+                HeaderName::from_static("content-type"),
+                HeaderName::from_static("authorization"),
+                HeaderName::from_static("x-api-key"),
+            ])
+            .allow_methods(vec!["GET", "POST", "DELETE", "OPTIONS"]);
 
-        // Initialize the routes.
-        let routes = self.routes();
+        // Initialize the routes, every rejection goes through the same handler.
+        for route in ROUTES {
+            debug!("serving {} {}", route.method.to_uppercase(), route.path);
+        }
+        let routes = self.routes().recover(handle_rejection);
 
         // Add custom logging for each request.
         let custom_log = warp::log::custom(|info| match info.remote_addr() {
@@ -73,33 +336,345 @@ impl<N: Network, S: Storage<N> + Send + Sync + 'static> Server<N, S> {
         });
 
         // Spawn the server.
-        self.handles.push(Arc::new(tokio::spawn(async move {
-            // Start the server.
-            warp::serve(routes.with(cors).with(custom_log)).run(rest_ip).await
-        })))
+        let routes = routes.with(cors).with(custom_log);
+        let handle = match tls {
+            #[cfg(feature = "tls")]
+            Some(tls) => tokio::spawn(async move {
+                if let Err(e) = crate::tls::serve(rest_ip, tls, routes).await {
+                    error!("api server stopped: {:?}", e);
+                }
+            }),
+            _ => tokio::spawn(async move {
+                // Start the server.
+                warp::serve(routes).run(rest_ip).await
+            }),
+        };
+        self.handles.push(Arc::new(handle))
     }
 
     pub fn routes(&self) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
-        // GET /testnet3/solutions/rewards/{address}/{begin}/{end}
+        // GET /testnet3/solutions/rewards/{address}/{begin}/{end}?limit={limit}&cursor={cursor}&order={asc|desc}
         let solutions_rewards = warp::get()
             .and(warp::path!("testnet3" / "solutions" / "rewards" / String / i64 / i64))
+            .and(self.guard("solutions_rewards"))
+            .and(warp::query::<PageQuery>())
             .and(with(self.store.clone()))
+            .and(with(self.cache.clone()))
             .and_then(Self::get_solutions_rewards);
 
-        
+        // GET /testnet3/provers/top?from={begin}&to={end}&limit={limit}
+        let provers_top = warp::get()
+            .and(warp::path!("testnet3" / "provers" / "top"))
+            .and(self.guard("provers_top"))
+            .and(warp::query::<TopQuery>())
+            .and(with(self.store.clone()))
+            .and(with(self.cache.clone()))
+            .and_then(Self::get_provers_top);
+
+        // GET /testnet3/provers/{address}/share?from={begin}&to={end}
+        let prover_share = warp::get()
+            .and(warp::path!("testnet3" / "provers" / String / "share"))
+            .and(self.guard("prover_share"))
+            .and(warp::query::<RangeQuery>())
+            .and(with(self.store.clone()))
+            .and(with(self.cache.clone()))
+            .and_then(Self::get_prover_share);
+
+        // GET /testnet3/groups
+        let groups = warp::get()
+            .and(warp::path!("testnet3" / "groups"))
+            .and(self.guard("groups"))
+            .and(with(self.store.clone()))
+            .and_then(Self::get_groups);
+
+        // POST /testnet3/groups {"name": "", "label": ""}
+        let create_group = warp::post()
+            .and(warp::path!("testnet3" / "groups"))
+            .and(self.guard("create_group"))
+            .and(warp::body::json::<GroupBody>())
+            .and(with(self.store.clone()))
+            .and_then(Self::create_group);
+
+        // DELETE /testnet3/groups/{name}
+        let delete_group = warp::delete()
+            .and(warp::path!("testnet3" / "groups" / String))
+            .and(self.guard("delete_group"))
+            .and(with(self.store.clone()))
+            .and_then(Self::delete_group);
+
+        // POST /testnet3/groups/{name}/members {"address": "", "label": ""}
+        let add_group_member = warp::post()
+            .and(warp::path!("testnet3" / "groups" / String / "members"))
+            .and(self.guard("add_group_member"))
+            .and(warp::body::json::<MemberBody>())
+            .and(with(self.store.clone()))
+            .and_then(Self::add_group_member);
+
+        // DELETE /testnet3/groups/{name}/members/{address}
+        let remove_group_member = warp::delete()
+            .and(warp::path!("testnet3" / "groups" / String / "members" / String))
+            .and(self.guard("remove_group_member"))
+            .and(with(self.store.clone()))
+            .and_then(Self::remove_group_member);
+
+        // GET /testnet3/groups/{name}/rewards?from={begin}&to={end}
+        let group_rewards = warp::get()
+            .and(warp::path!("testnet3" / "groups" / String / "rewards"))
+            .and(self.guard("group_rewards"))
+            .and(warp::query::<RangeQuery>())
+            .and(with(self.store.clone()))
+            .and_then(Self::get_group_rewards);
+
+        // POST /graphql {"query": "", "variables": {}}
+        let graphql = warp::post()
+            .and(warp::path!("graphql"))
+            .and(self.guard("graphql"))
+            .and(async_graphql_warp::graphql(self.graphql.clone()))
+            .and_then(Self::post_graphql);
+
+        // GET /metrics
+        let metrics = warp::get()
+            .and(warp::path!("metrics"))
+            .and(self.guard("metrics"))
+            .and(with(self.metrics.clone()))
+            .and_then(Self::get_metrics);
+
+        // GET /openapi.json
+        let openapi = warp::get()
+            .and(warp::path!("openapi.json"))
+            .and(with(self.openapi.clone()))
+            .map(|document: Arc<serde_json::Value>| reply::json(&*document));
+
+        // GET /docs
+        let docs = warp::get()
+            .and(warp::path!("docs"))
+            .and(with(self.docs))
+            .and_then(Self::get_docs);
 
         solutions_rewards
+            .or(provers_top)
+            .or(prover_share)
+            .or(groups)
+            .or(create_group)
+            .or(delete_group)
+            .or(add_group_member)
+            .or(remove_group_member)
+            .or(group_rewards)
+            .or(graphql)
+            .or(metrics)
+            .or(openapi)
+            .or(docs)
+    }
+
+    /// Authenticates the request and applies the rate limit of the route, extracts the scopes of the key.
+    fn guard(&self, route: &'static str) -> impl Filter<Extract = (Arc<Scopes>,), Error = Rejection> + Clone {
+        debug_assert!(ROUTES.iter().any(|r| r.name == route), "route {route} is not registered in ROUTES");
+        with_rate_limit(with_auth(self.keys.clone()), self.limiter.clone(), route)
     }
 
    
 }
 
 impl<N: Network, S: Storage<N> + Send + Sync + 'static> Server<N, S> {
-    pub async fn get_solutions_rewards(address: String, begin: i64, end: i64, store: Arc<Store<N, S>>) -> anyhow::Result<impl Reply, Rejection> {
-        let result = store.get_solutions_by_time_range(&address, begin, end).map_or(Response::success(vec![]),|v| { 
-            Response::success(v)
-        });
-        Ok(result.json())
+    pub async fn get_solutions_rewards(address: String, begin: i64, end: i64, scopes: Arc<Scopes>, query: PageQuery, store: Arc<Store<N, S>>, cache: Arc<ResponseCache>) -> anyhow::Result<impl Reply, Rejection> {
+        let address = canonical_address::<N>(&address)?;
+        scopes.check_address(&address, &store).await?;
+        validate_range(begin, end)?;
+        let cursor = query.cursor.as_deref()
+            .map(str::parse::<Cursor>)
+            .transpose()
+            .map_err(|e| ApiError::InvalidParam(e.to_string()))?;
+        let page = Page {
+            limit: query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT),
+            cursor,
+            order: query.order.unwrap_or_default(),
+        };
+        let cursor = page.cursor.map(|c| c.to_string()).unwrap_or_default();
+        let key = format!("solutions/rewards/{address}/{begin}/{end}?limit={}&cursor={cursor}&order={:?}", page.limit, page.order);
+        if let Some(body) = cache.get(&key) {
+            return Ok(crate::cache::reply(body));
+        }
+        let result = store.get_solutions_page(&address, begin, end, &page).await.map_err(ApiError::Storage)?;
+        let body = cache.insert(key, end, &Response::page(result.items, result.next)).map_err(|e| ApiError::Internal(e.to_string()))?;
+        Ok(crate::cache::reply(body))
+    }
+
+    pub async fn get_provers_top(scopes: Arc<Scopes>, query: TopQuery, store: Arc<Store<N, S>>, cache: Arc<ResponseCache>) -> anyhow::Result<impl Reply, Rejection> {
+        scopes.require_all()?;
+        validate_range(query.from, query.to)?;
+        let limit = query.limit.unwrap_or(DEFAULT_TOP_LIMIT).clamp(1, MAX_TOP_LIMIT);
+        let key = format!("provers/top?from={}&to={}&limit={limit}", query.from, query.to);
+        if let Some(body) = cache.get(&key) {
+            return Ok(crate::cache::reply(body));
+        }
+        let result = store.get_top_provers(query.from, query.to, limit).await.map_err(ApiError::Storage)?;
+        let body = cache.insert(key, query.to, &Response::success(result)).map_err(|e| ApiError::Internal(e.to_string()))?;
+        Ok(crate::cache::reply(body))
+    }
+
+    pub async fn get_prover_share(address: String, scopes: Arc<Scopes>, query: RangeQuery, store: Arc<Store<N, S>>, cache: Arc<ResponseCache>) -> anyhow::Result<impl Reply, Rejection> {
+        let address = canonical_address::<N>(&address)?;
+        scopes.check_address(&address, &store).await?;
+        validate_range(query.from, query.to)?;
+        let key = format!("provers/{address}/share?from={}&to={}", query.from, query.to);
+        if let Some(body) = cache.get(&key) {
+            return Ok(crate::cache::reply(body));
+        }
+        let result = store.get_prover_share(&address, query.from, query.to).await.map_err(ApiError::Storage)?;
+        let body = cache.insert(key, query.to, &Response::success(result)).map_err(|e| ApiError::Internal(e.to_string()))?;
+        Ok(crate::cache::reply(body))
+    }
+
+    pub async fn get_groups(scopes: Arc<Scopes>, store: Arc<Store<N, S>>) -> anyhow::Result<impl Reply, Rejection> {
+        let mut result = store.get_groups().await.map_err(ApiError::Storage)?;
+        // 只返回有权限的分组
+        result.retain(|group| scopes.allows_group(&group.name));
+        Ok(Response::success(result).json())
+    }
+
+    pub async fn create_group(scopes: Arc<Scopes>, body: GroupBody, store: Arc<Store<N, S>>) -> anyhow::Result<impl Reply, Rejection> {
+        scopes.require_all()?;
+        if body.name.trim().is_empty() {
+            return Err(ApiError::InvalidParam(String::from("group name is empty")).into());
+        }
+        if !store.create_group(&body.name, &body.label).await.map_err(ApiError::Storage)? {
+            return Err(ApiError::InvalidParam(format!("group {} already exists", body.name)).into());
+        }
+        Ok(Response::success(true).json())
+    }
+
+    pub async fn delete_group(name: String, scopes: Arc<Scopes>, store: Arc<Store<N, S>>) -> anyhow::Result<impl Reply, Rejection> {
+        scopes.require_all()?;
+        if !store.delete_group(&name).await.map_err(ApiError::Storage)? {
+            return Err(ApiError::NotFound.into());
+        }
+        Ok(Response::success(true).json())
+    }
+
+    pub async fn add_group_member(name: String, scopes: Arc<Scopes>, body: MemberBody, store: Arc<Store<N, S>>) -> anyhow::Result<impl Reply, Rejection> {
+        scopes.require_all()?;
+        let address = canonical_address::<N>(&body.address)?;
+        if !store.add_group_member(&name, &address, &body.label).await.map_err(ApiError::Storage)? {
+            return Err(ApiError::NotFound.into());
+        }
+        Ok(Response::success(true).json())
+    }
+
+    pub async fn remove_group_member(name: String, address: String, scopes: Arc<Scopes>, store: Arc<Store<N, S>>) -> anyhow::Result<impl Reply, Rejection> {
+        scopes.require_all()?;
+        let address = canonical_address::<N>(&address)?;
+        if !store.remove_group_member(&name, &address).await.map_err(ApiError::Storage)? {
+            return Err(ApiError::NotFound.into());
+        }
+        Ok(Response::success(true).json())
+    }
+
+    pub async fn post_graphql(scopes: Arc<Scopes>, (schema, request): (ApiSchema, async_graphql::Request)) -> anyhow::Result<impl Reply, Rejection> {
+        Ok(GraphQLResponse::from(schema.execute(request.data(scopes)).await))
+    }
+
+    pub async fn get_metrics(scopes: Arc<Scopes>, metrics: Arc<Metrics>) -> anyhow::Result<impl Reply, Rejection> {
+        scopes.require_all()?;
+        Ok(reply::with_header(metrics.render(), http::header::CONTENT_TYPE, "text/plain; version=0.0.4"))
+    }
+
+    pub async fn get_docs(docs: bool) -> anyhow::Result<impl Reply, Rejection> {
+        if !docs {
+            return Err(ApiError::NotFound.into());
+        }
+        Ok(reply::html(crate::openapi::DOCS_PAGE))
+    }
+
+    pub async fn get_group_rewards(name: String, scopes: Arc<Scopes>, query: RangeQuery, store: Arc<Store<N, S>>) -> anyhow::Result<impl Reply, Rejection> {
+        if !scopes.allows_group(&name) {
+            return Err(ApiError::Forbidden.into());
+        }
+        validate_range(query.from, query.to)?;
+        let result = store.get_group_rewards(&name, query.from, query.to).await
+            .map_err(ApiError::Storage)?
+            .ok_or(ApiError::NotFound)?;
+        Ok(Response::success(result).json())
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use anyhow::bail;
+    use snarkvm_console_network::Testnet3;
+
+    use super::*;
+    use crate::message::{BlockReward, Solution};
+    use crate::storage::{BlockRow, Group, GroupReward, LedgerEntry, Paged, ProverRank, ProverShare, Reward, SolutionRow};
+
+    /// A storage failing every call, the routes are matched before the storage is queried.
+    struct NoStore;
+
+    impl Storage<Testnet3> for NoStore {
+        fn new(_url: String) -> Self { Self }
+        fn record_block(&self, _block: &BlockReward<Testnet3>) -> anyhow::Result<bool> { bail!("no storage") }
+        fn record_solutions(&self, _solutions: &Solution<Testnet3>) -> anyhow::Result<bool> { bail!("no storage") }
+        fn get_solutions_page(&self, _address: &str, _begin: i64, _end: i64, _page: &Page) -> anyhow::Result<Paged<Reward>> { bail!("no storage") }
+        fn get_top_provers(&self, _begin: i64, _end: i64, _limit: u32) -> anyhow::Result<Vec<ProverRank>> { bail!("no storage") }
+        fn get_prover_share(&self, _address: &str, _begin: i64, _end: i64) -> anyhow::Result<ProverShare> { bail!("no storage") }
+        fn create_group(&self, _name: &str, _label: &str) -> anyhow::Result<bool> { bail!("no storage") }
+        fn delete_group(&self, _name: &str) -> anyhow::Result<bool> { bail!("no storage") }
+        fn add_group_member(&self, _name: &str, _address: &str, _label: &str) -> anyhow::Result<bool> { bail!("no storage") }
+        fn remove_group_member(&self, _name: &str, _address: &str) -> anyhow::Result<bool> { bail!("no storage") }
+        fn get_groups(&self) -> anyhow::Result<Vec<Group>> { bail!("no storage") }
+        fn get_group_rewards(&self, _name: &str, _begin: i64, _end: i64) -> anyhow::Result<Option<GroupReward>> { bail!("no storage") }
+        fn get_address_rewards(&self, _addresses: &[String], _begin: i64, _end: i64) -> anyhow::Result<Vec<ProverRank>> { bail!("no storage") }
+        fn close_payout_period(&self, _scheme: &str, _period_start: i64, _period_end: i64, _entries: &[LedgerEntry]) -> anyhow::Result<bool> { bail!("no storage") }
+        fn get_payout_period(&self, _scheme: &str, _period_start: i64) -> anyhow::Result<Option<Vec<LedgerEntry>>> { bail!("no storage") }
+        fn get_blocks_by_height_range(&self, _start: u32, _end: u32) -> anyhow::Result<Vec<BlockRow>> { bail!("no storage") }
+        fn get_solutions_by_height_range(&self, _start: u32, _end: u32) -> anyhow::Result<Vec<SolutionRow>> { bail!("no storage") }
+        fn get_latest_timestamp(&self) -> anyhow::Result<Option<i64>> { bail!("no storage") }
+        fn get_last_solution_id(&self) -> anyhow::Result<Option<u64>> { bail!("no storage") }
+        fn get_earliest_timestamp_after(&self, _id: u64) -> anyhow::Result<Option<i64>> { bail!("no storage") }
+    }
+
+    /// A server without authentication, rate limits and cache, nothing is spawned.
+    fn server() -> Server<Testnet3, NoStore> {
+        let store = Arc::new(Store::new(String::new()));
+        Server {
+            graphql: crate::graphql::schema(store.clone(), 1000),
+            store,
+            cache: Arc::new(ResponseCache::new(0)),
+            keys: None,
+            limiter: Arc::new(RateLimiter::new(HashMap::new(), Default::default())),
+            metrics: Default::default(),
+            openapi: Arc::new(crate::openapi::document()),
+            docs: true,
+            handles: vec![],
+        }
+    }
+
+    /// Whether no route has the path or the method, the rejections are not recovered so any other
+    /// rejection of a matched route is a 500.
+    fn unrouted(status: StatusCode) -> bool {
+        status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED
+    }
+
+    #[tokio::test]
+    async fn every_registered_route_is_served() {
+        let routes = server().routes();
+        for route in ROUTES {
+            let path = route.path
+                .replace("{address}", "aleo1abc")
+                .replace("{begin}", "0")
+                .replace("{end}", "1")
+                .replace("{name}", "pool");
+            // 参数或存储的错误说明已匹配到路由
+            let response = warp::test::request().method(&route.method.to_uppercase()).path(&path).reply(&routes).await;
+            assert!(!unrouted(response.status()), "{} {} {path} is not served: {:?}", route.name, route.method, response.body());
+        }
+    }
+
+    #[tokio::test]
+    async fn other_paths_and_methods_are_not_served() {
+        let routes = server().routes();
+        for (method, path) in [("GET", "/testnet3/unknown"), ("GET", "/graphql"), ("PUT", "/testnet3/groups")] {
+            let response = warp::test::request().method(method).path(path).reply(&routes).await;
+            assert!(unrouted(response.status()), "{method} {path} is served");
+        }
+    }
+}
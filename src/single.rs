@@ -3,34 +3,37 @@ use tokio::time::{sleep, Duration};
 use std::marker::PhantomData;
 use tokio::sync::mpsc;
 use crate::message::Message;
+use crate::parse::{compute_block, emit_block};
+use crate::recover::{recover, ParseOptions};
 use crate::manage::ApiManage;
+use crate::watch::Watchlist;
 
 use snarkvm_console_network::Network;
 use snarkvm_synthesizer::Block;
 
-pub struct Single<'a, N: Network> {
+pub struct Single<N: Network> {
     api_manage: ApiManage,
     latest_height: u32,
-    address: &'a Vec<String>,
+    address: Watchlist<N>,
     sender: mpsc::Sender<Message<N>>,
-    store_block: bool,
+    parse: ParseOptions,
     _n: PhantomData<N>,
 }
 
-impl<'a, N:Network> Single<'a, N> {
+impl<N:Network> Single<N> {
     pub fn new(
         api_manage: ApiManage,
         latest_height: u32, 
-        address: &'a Vec<String>, 
+        address: Watchlist<N>, 
         sender: mpsc::Sender<Message<N>>,
-        store_block: bool,
+        parse: ParseOptions,
     ) -> Self {
         Self { 
             api_manage,
             latest_height, 
             address,
             sender,
-            store_block,
+            parse,
             _n: PhantomData}
     }
 
@@ -43,7 +46,7 @@ impl<'a, N:Network> Single<'a, N> {
         loop { 
             result = self.api_manage.get("/latest/height").await;
             match result {
-                Ok(response) => {
+                Ok((api, response)) => {
                     let status = response.status();
                     let body = response.text().await?;
                     if status != reqwest::StatusCode::OK {
@@ -51,7 +54,16 @@ impl<'a, N:Network> Single<'a, N> {
                         sleep(Duration::from_secs(block_duration)).await;
                         continue;
                     }
-                    chain_height = body.parse::<u32>().unwrap();
+                    chain_height = match body.trim().parse::<u32>() {
+                        Ok(height) => height,
+                        // 返回的高度无法解析，换下一个api重试
+                        Err(e) => {
+                            error!("invalid latest chain height {:?} from {}, {:?}", body, api, e);
+                            self.api_manage.fail_over(&api);
+                            sleep(Duration::from_secs(block_duration)).await;
+                            continue;
+                        }
+                    };
                     info!("get latest chain height {} from api", chain_height);
                 },
                 Err(e) => {
@@ -78,7 +90,7 @@ impl<'a, N:Network> Single<'a, N> {
                 info!("get first block: {}", self.latest_height);
                 result = self.api_manage.get(format!("/block/{}", self.latest_height).as_str()).await;
                 match result {
-                    Ok(response)=> {
+                    Ok((_, response)) => {
                         let status = response.status();
                         let body = response.text().await?;
                         if status != reqwest::StatusCode::OK {
@@ -86,7 +98,13 @@ impl<'a, N:Network> Single<'a, N> {
                             sleep(Duration::from_secs(block_duration)).await;
                             continue;
                         }
-                        let latest_block = serde_json::from_str(&body).unwrap();
+                        let latest_block = match deserialize_block(self.latest_height, &body) {
+                            Some(block) => block,
+                            None => {
+                                sleep(Duration::from_secs(block_duration)).await;
+                                continue;
+                            }
+                        };
                         blocks.replace((self.latest_height, latest_block));
                     },
                     Err(e) => {
@@ -102,7 +120,7 @@ impl<'a, N:Network> Single<'a, N> {
             info!("get current block: {}", current_height);  
             result = self.api_manage.get(format!("/block/{}", current_height).as_str()).await;
             match result {
-                Ok(response )=> {
+                Ok((api, response)) => {
                     let status = response.status();
                     let body = response.text().await?;
                     if status != reqwest::StatusCode::OK {
@@ -110,15 +128,25 @@ impl<'a, N:Network> Single<'a, N> {
                         sleep(Duration::from_secs(block_duration)).await;
                         continue;
                     }
-                    let current_block: Block<N> = serde_json::from_str(&body).unwrap();
+                    let current_block: Block<N> = match deserialize_block(current_height, &body) {
+                        Some(block) => block,
+                        None => {
+                            sleep(Duration::from_secs(block_duration)).await;
+                            continue;
+                        }
+                    };
                     let latest_block = blocks.take().unwrap();
-                    crate::parse::parse_block::<N>(
-                        &current_block, 
-                        &latest_block.1, 
-                        self.address, 
-                        self.sender.clone(),
-                        self.store_block,
-                    ).await?;
+                    let (address, generation) = self.address.snapshot();
+                    let (parsed, current_block) = match compute_block(&current_block, &latest_block.1, &address, self.parse.store_block) {
+                        Ok(parsed) => (parsed, current_block),
+                        // 从其他api重新获取，仍失败则按配置跳过或停止；下一个块基于重新获取的块解析
+                        Err(error) => match recover(&self.api_manage, &api, &self.parse, &address, error).await? {
+                            (parsed, Some(refetched)) => (parsed, refetched),
+                            (parsed, None) => (parsed, current_block),
+                        },
+                    };
+                    emit_block(parsed, &self.sender).await?;
+                    self.address.observe(generation, current_height);
                     latest_height_mut = current_height;
                     blocks.replace((current_height, current_block));
                 },
@@ -153,3 +181,114 @@ impl<'a, N:Network> Single<'a, N> {
 
 
 
+
+/// Deserializes the block of the response, a truncated or malformed body is logged and fetched again later.
+fn deserialize_block<N: Network>(height: u32, body: &str) -> Option<Block<N>> {
+    match serde_json::from_str(body) {
+        Ok(block) => Some(block),
+        Err(e) => {
+            error!("deserialize block {}, {:?}", height, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use reqwest::Client;
+    use snarkvm_console_network::Testnet3;
+
+    use super::*;
+    use crate::mock::{fixtures_dir, fork_dir, load_blocks, Endpoint, Fault, MockApi};
+    use crate::recover::ParseErrorPolicy;
+
+    #[test]
+    fn a_truncated_block_is_fetched_again() {
+        assert!(deserialize_block::<Testnet3>(1, "{\"block_hash\":\"ab1").is_none());
+        assert!(deserialize_block::<Testnet3>(1, "").is_none());
+    }
+
+    fn parse_options() -> ParseOptions {
+        ParseOptions {
+            store_block: false,
+            on_parse_error: ParseErrorPolicy::Stop,
+            quarantine_file: String::from("quarantine.jsonl"),
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_the_tip_of_the_fixture_blocks() {
+        let mock = MockApi::from_fixtures().unwrap();
+        let start = *load_blocks(&fixtures_dir()).unwrap().keys().next().unwrap();
+        // 离链上最新高度10个块以内不再同步
+        let tip = mock.height() - 10;
+        mock.fault(Endpoint::Block, Fault::Truncate);
+        let (sender, mut receiver) = mpsc::channel(4096);
+        let single = Single::<Testnet3>::new(
+            ApiManage::new(Client::new(), vec![mock.url()]),
+            start,
+            Watchlist::new(HashSet::new()),
+            sender,
+            parse_options(),
+        );
+
+        let synced = async {
+            let mut heights = Vec::new();
+            while let Some(message) = receiver.recv().await {
+                if let Message::SyncHeight(sync) = message {
+                    heights.push(sync.height);
+                    if sync.height == tip {
+                        return heights;
+                    }
+                }
+            }
+            heights
+        };
+        let heights = tokio::select! {
+            result = single.get_blocks() => panic!("single sync stopped: {result:?}"),
+            heights = tokio::time::timeout(Duration::from_secs(60), synced) => heights.unwrap(),
+        };
+        assert_eq!(heights, (start + 1..=tip).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn stops_at_a_block_of_another_branch() {
+        let mock = MockApi::from_fixtures().unwrap();
+        let branch = load_blocks(&fork_dir()).unwrap();
+        let height = *branch.keys().next().unwrap();
+        // 同步到height + 1后离链上最新高度只有10个块，等待新块
+        mock.set_height(height + 11);
+        let (sender, mut receiver) = mpsc::channel(4096);
+        let single = Single::<Testnet3>::new(
+            ApiManage::new(Client::new(), vec![mock.url()]),
+            height - 1,
+            Watchlist::new(HashSet::new()),
+            sender,
+            parse_options(),
+        );
+
+        let mut heights = Vec::new();
+        let switch = async {
+            while let Some(message) = receiver.recv().await {
+                if let Message::SyncHeight(sync) = message {
+                    heights.push(sync.height);
+                    // api切换到另一条分支，下一个块不是基于已同步的块
+                    if sync.height == height + 1 {
+                        mock.fork(height, branch.values().cloned().collect());
+                    }
+                }
+            }
+        };
+        let stopped = async {
+            tokio::select! {
+                result = single.get_blocks() => result.unwrap_err(),
+                _ = switch => panic!("single sync dropped the sender"),
+            }
+        };
+        let error = tokio::time::timeout(Duration::from_secs(60), stopped).await.unwrap();
+        assert!(error.to_string().starts_with(&format!("block {} builds on ", height + 2)), "{error}");
+        assert_eq!(heights, vec![height, height + 1]);
+    }
+}
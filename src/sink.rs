@@ -0,0 +1,428 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use futures::future::BoxFuture;
+use log::{error, info, warn};
+use memmap2::MmapMut;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep, Duration};
+
+use snarkvm_console_network::Network;
+
+use crate::adaptive::Adaptive;
+use crate::manage::ApiManage;
+use crate::message::Message;
+use crate::recover::ParseOptions;
+use crate::storage::{Storage, Store};
+use crate::utils::open_file;
+use crate::watch::Watchlist;
+
+/// Messages buffered per secondary sink, a full buffer detaches the sink.
+const SINK_CHANNEL_SIZE: usize = 4096;
+const RETRY_MIN_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// What a secondary sink does when recording a message fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorPolicy {
+    /// Stops the sink, the other sinks keep running.
+    #[default]
+    Stop,
+    /// Logs the error and drops the message.
+    Skip,
+    /// Retries the message with a backoff until it succeeds.
+    Retry,
+}
+
+/// A secondary storage receiving the same messages as the primary one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SinkConfig {
+    pub name: String,
+    /// The storage implementation, `mysql` or `jsonl`.
+    #[serde(default = "default_storage")]
+    pub storage: String,
+    /// The mysql dsn or the jsonl directory.
+    pub url: String,
+    /// The synced height file of the sink.
+    pub checkpoint_file: String,
+    #[serde(default)]
+    pub on_error: ErrorPolicy,
+}
+
+fn default_storage() -> String {
+    #[cfg(feature = "mysql")]
+    return String::from("mysql");
+    #[cfg(all(feature = "jsonl", not(feature = "mysql")))]
+    return String::from("jsonl");
+}
+
+/// The write side of a storage, object safe so sinks of different storages can be mixed.
+pub trait Sink<N: Network> {
+    fn record_message(&self, message: Message<N>) -> BoxFuture<'_, Result<()>>;
+}
+
+impl<N: Network, S: Storage<N>> Sink<N> for Store<N, S> {
+    fn record_message(&self, message: Message<N>) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            match message {
+                Message::SyncHeight(_) => self.flush().await,
+                message => self.record_batch(Arc::new(vec![message])).await,
+            }
+        })
+    }
+}
+
+/// Opens the storage of the sink, add an arm here to plug in another `Storage` implementation.
+pub fn open<N: Network>(config: &SinkConfig) -> Result<Box<dyn Sink<N>>> {
+    let url = config.url.clone();
+    match config.storage.as_str() {
+        #[cfg(feature = "mysql")]
+        "mysql" => Ok(Box::new(Store::<N, crate::mysql::MysqlClient>::new(url))),
+        #[cfg(feature = "jsonl")]
+        "jsonl" => Ok(Box::new(Store::<N, crate::jsonl::JsonlStore>::new(url))),
+        storage => bail!("sink {}: storage {storage} is not supported by this build", config.name),
+    }
+}
+
+struct Forward<N: Network> {
+    message: Message<N>,
+    /// A solution of the address backfill, below the synced height.
+    backfill: bool,
+}
+
+/// A request of a sink to receive the live messages.
+pub struct Attach<N: Network> {
+    index: usize,
+    sender: mpsc::Sender<Forward<N>>,
+    /// Replies the synced height of the primary storage, live messages start at the next block.
+    reply: oneshot::Sender<u32>,
+}
+
+/// The dispatching side of the secondary sinks, driven by `message::handle` after the primary storage recorded a message.
+///
+/// Messages are forwarded without waiting, a sink whose buffer is full is detached and catches up on its own.
+/// Sinks are only attached between two blocks, so they never see a partial block.
+pub struct Fanout<N: Network> {
+    names: Vec<String>,
+    sinks: Vec<Option<mpsc::Sender<Forward<N>>>>,
+    control: mpsc::UnboundedReceiver<Attach<N>>,
+    pending: Vec<Attach<N>>,
+    primary_height: Arc<AtomicU32>,
+    boundary: bool,
+}
+
+impl<N: Network> Fanout<N> {
+    pub fn new(names: Vec<String>, primary_height: Arc<AtomicU32>) -> (Self, mpsc::UnboundedSender<Attach<N>>) {
+        let (sender, control) = mpsc::unbounded_channel();
+        let sinks = names.iter().map(|_| None).collect();
+        let fanout = Self { names, sinks, control, pending: Vec::new(), primary_height, boundary: true };
+        (fanout, sender)
+    }
+
+    /// Waits for the next attach request, returns None once every sink is gone.
+    pub async fn attach_request(&mut self) -> Option<Attach<N>> {
+        self.control.recv().await
+    }
+
+    pub fn attach(&mut self, attach: Attach<N>) {
+        if self.boundary {
+            self.connect(attach);
+        } else {
+            self.pending.push(attach);
+        }
+    }
+
+    fn connect(&mut self, attach: Attach<N>) {
+        let height = self.primary_height.load(Ordering::SeqCst);
+        if attach.reply.send(height).is_ok() {
+            info!("sink {} attached after height {}", self.names[attach.index], height);
+            self.sinks[attach.index] = Some(attach.sender);
+        }
+    }
+
+    /// Forwards a message recorded by the primary storage.
+    pub fn forward(&mut self, message: &Message<N>) {
+        let synced = self.primary_height.load(Ordering::SeqCst);
+        let backfill = matches!(message, Message::Solution(msg) if msg.block_height <= synced);
+        match message {
+            Message::SyncHeight(msg) => {
+                self.primary_height.store(msg.height, Ordering::SeqCst);
+                self.boundary = true;
+            }
+            _ if !backfill => self.boundary = false,
+            _ => {}
+        }
+
+        for (name, slot) in self.names.iter().zip(self.sinks.iter_mut()) {
+            if let Some(sender) = slot {
+                let forward = Forward { message: message.clone(), backfill };
+                match sender.try_send(forward) {
+                    Ok(()) => {}
+                    Err(mpsc::error::TrySendError::Full(_)) => {
+                        warn!("sink {name} is lagging behind, detached at height {synced}");
+                        *slot = None;
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => *slot = None,
+                }
+            }
+        }
+
+        if self.boundary {
+            for attach in std::mem::take(&mut self.pending) {
+                self.connect(attach);
+            }
+        }
+    }
+}
+
+/// Records the messages into one secondary sink and keeps its own checkpoint.
+///
+/// Must run inside a `LocalSet`, a lagging sink refetches the blocks it missed with its own `Batch`.
+pub struct SinkRunner<N: Network> {
+    index: usize,
+    name: String,
+    sink: Box<dyn Sink<N>>,
+    on_error: ErrorPolicy,
+    mmap: MmapMut,
+    checkpoint: u32,
+    primary_height: Arc<AtomicU32>,
+    control: mpsc::UnboundedSender<Attach<N>>,
+    api_manage: ApiManage,
+    address: Watchlist<N>,
+    adaptive: Adaptive,
+    parse: ParseOptions,
+}
+
+impl<N: Network> SinkRunner<N> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        index: usize,
+        config: &SinkConfig,
+        primary_height: Arc<AtomicU32>,
+        control: mpsc::UnboundedSender<Attach<N>>,
+        api_manage: ApiManage,
+        address: Watchlist<N>,
+        adaptive: Adaptive,
+        parse: ParseOptions,
+    ) -> Result<Self> {
+        let sink = open::<N>(config)?;
+        let file = open_file(config.checkpoint_file.clone());
+        let mmap = unsafe { MmapMut::map_mut(&file)? };
+        let mut buf: [u8; 4] = [0, 0, 0, 0];
+        buf.copy_from_slice(&mmap[0..4]);
+        let checkpoint = u32::from_le_bytes(buf);
+        info!("sink {} starts at height {}", config.name, checkpoint);
+
+        Ok(Self {
+            index,
+            name: config.name.clone(),
+            sink,
+            on_error: config.on_error,
+            mmap,
+            checkpoint,
+            primary_height,
+            control,
+            api_manage,
+            address,
+            adaptive,
+            parse,
+        })
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            // 先追到主存储的高度，再请求实时消息，避免接入后缓冲区立即写满
+            let target = self.primary_height.load(Ordering::SeqCst);
+            match self.catch_up(target).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    sleep(RETRY_MIN_DELAY).await;
+                    continue;
+                }
+                Err(e) => {
+                    error!("sink {} stopped at height {}: {:?}", self.name, self.checkpoint, e);
+                    return;
+                }
+            }
+
+            let (sender, mut receiver) = mpsc::channel(SINK_CHANNEL_SIZE);
+            let (reply, attached) = oneshot::channel();
+            if self.control.send(Attach { index: self.index, sender, reply }).is_err() {
+                return;
+            }
+            let height = match attached.await {
+                Ok(height) => height,
+                Err(_) => return,
+            };
+            // 接入前主存储又前进的区块，实时消息在通道中等待
+            match self.catch_up(height).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    error!("sink {} stopped at height {}: {:?}", self.name, self.checkpoint, e);
+                    return;
+                }
+            }
+
+            while let Some(forward) = receiver.recv().await {
+                if let Err(e) = self.apply(forward.message, forward.backfill).await {
+                    error!("sink {} stopped at height {}: {:?}", self.name, self.checkpoint, e);
+                    return;
+                }
+            }
+            info!("sink {} detached at height {}, catching up", self.name, self.checkpoint);
+        }
+    }
+
+    /// Refetches the blocks up to `target`, returns false when the fetch failed and should be retried.
+    async fn catch_up(&mut self, target: u32) -> Result<bool> {
+        if self.checkpoint >= target {
+            return Ok(true);
+        }
+        info!("sink {} catching up from {} to {}", self.name, self.checkpoint, target);
+
+        let (sender, mut receiver) = mpsc::channel(SINK_CHANNEL_SIZE);
+        // 使用独立的地址表，避免影响实时同步的回填高度
+        let (address, _) = self.address.snapshot();
+        let batch_obj = crate::batch::Batch::<N>::new(
+            self.api_manage.clone(),
+            self.checkpoint,
+            Some(target + 1),
+            Watchlist::new((*address).clone()),
+            self.adaptive.clone(),
+            sender,
+            self.parse.clone(),
+        );
+        let scan = async move { batch_obj.get_blocks().await };
+        let record = async {
+            while let Some(message) = receiver.recv().await {
+                self.apply(message, false).await?;
+            }
+            anyhow::Ok(())
+        };
+        let (scanned, recorded) = futures::join!(scan, record);
+        recorded?;
+
+        if let Err((height, e)) = scanned {
+            warn!("sink {} catch up stopped at height {}: {}", self.name, height, e);
+            return Ok(false);
+        }
+        Ok(self.checkpoint >= target)
+    }
+
+    /// Records the message with the error policy, returns an error when the sink must stop.
+    async fn apply(&mut self, message: Message<N>, backfill: bool) -> Result<()> {
+        // 已经写入过的区块跳过，回填的solution除外
+        if !backfill && message.height() <= self.checkpoint {
+            return Ok(());
+        }
+
+        let mut delay = RETRY_MIN_DELAY;
+        while let Err(e) = self.sink.record_message(message.clone()).await {
+            match self.on_error {
+                ErrorPolicy::Stop => bail!("record {} of block {} failed: {:?}", message.name(), message.height(), e),
+                ErrorPolicy::Skip => {
+                    warn!("sink {} skips {} of block {}: {:?}", self.name, message.name(), message.height(), e);
+                    break;
+                }
+                ErrorPolicy::Retry => {
+                    warn!("sink {} retries {} of block {} in {:?}: {:?}", self.name, message.name(), message.height(), delay, e);
+                    sleep(delay).await;
+                    delay = (delay * 2).min(RETRY_MAX_DELAY);
+                }
+            }
+        }
+
+        if let Message::SyncHeight(msg) = &message {
+            self.mmap.copy_from_slice(&u32::to_le_bytes(msg.height)[..]);
+            self.checkpoint = msg.height;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use parking_lot::Mutex;
+    use reqwest::Client;
+    use snarkvm_console_network::Testnet3;
+
+    use super::*;
+    use crate::message::SyncHeight;
+    use crate::mock::MockApi;
+    use crate::recover::ParseErrorPolicy;
+
+    /// Keeps the synced heights it records.
+    #[derive(Clone, Default)]
+    struct Heights(Arc<Mutex<Vec<u32>>>);
+
+    impl<N: Network> Sink<N> for Heights {
+        fn record_message(&self, message: Message<N>) -> BoxFuture<'_, Result<()>> {
+            Box::pin(async move {
+                if let Message::SyncHeight(sync) = message {
+                    self.0.lock().push(sync.height);
+                }
+                Ok(())
+            })
+        }
+    }
+
+    fn runner(name: &str, sink: Heights, api: String, primary_height: Arc<AtomicU32>) -> (SinkRunner<Testnet3>, Fanout<Testnet3>) {
+        let checkpoint_file = std::env::temp_dir().join(format!("aleo-blocks-sink-{name}-{}.sync", std::process::id()));
+        let _ = std::fs::remove_file(&checkpoint_file);
+        let file = open_file(checkpoint_file.to_str().unwrap().to_string());
+        let (fanout, control) = Fanout::new(vec![name.to_string()], primary_height.clone());
+        let runner = SinkRunner {
+            index: 0,
+            name: name.to_string(),
+            sink: Box::new(sink),
+            on_error: ErrorPolicy::Stop,
+            mmap: unsafe { MmapMut::map_mut(&file).unwrap() },
+            checkpoint: 0,
+            primary_height,
+            control,
+            api_manage: ApiManage::new(Client::new(), vec![api]),
+            address: Watchlist::new(HashSet::new()),
+            adaptive: Adaptive::new(20, 4, (1, 50), (1, 8)),
+            parse: ParseOptions {
+                store_block: false,
+                on_parse_error: ParseErrorPolicy::Stop,
+                quarantine_file: String::from("quarantine.jsonl"),
+            },
+        };
+        (runner, fanout)
+    }
+
+    /// The primary follows the tip block by block, 10 blocks below it and above the rounded CDN height.
+    #[tokio::test]
+    async fn catches_up_with_a_primary_following_the_tip() {
+        let mock = MockApi::from_fixtures().unwrap();
+        let primary = mock.height() - 10;
+        let sink = Heights::default();
+        let (runner, mut fanout) = runner("tip", sink.clone(), mock.url(), Arc::new(AtomicU32::new(primary)));
+
+        let attached = async move {
+            let attach = fanout.attach_request().await.unwrap();
+            fanout.attach(attach);
+            // 接入后的实时消息
+            fanout.forward(&Message::SyncHeight(SyncHeight { height: primary + 1, _p: std::marker::PhantomData }));
+            fanout
+        };
+        let local = tokio::task::LocalSet::new();
+        local.spawn_local(runner.run());
+        let _fanout = local.run_until(tokio::time::timeout(Duration::from_secs(30), attached)).await.unwrap();
+        let recorded = async {
+            while sink.0.lock().last() != Some(&(primary + 1)) {
+                sleep(Duration::from_millis(10)).await;
+            }
+        };
+        local.run_until(tokio::time::timeout(Duration::from_secs(10), recorded)).await.unwrap();
+
+        assert_eq!(*sink.0.lock(), (1..=primary + 1).collect::<Vec<_>>());
+    }
+}
@@ -1,11 +1,14 @@
 use serde::{Serialize, Deserialize};
 use snarkvm_console_network::Network;
 
-use std::marker::PhantomData;
-use crate::message::{Solution, BlockReward};
+use std::{fmt, str::FromStr, marker::PhantomData, sync::Arc};
+use tokio::sync::Semaphore;
+use crate::message::{Message, Solution, BlockReward};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Reward {
+    #[serde(skip)]
+    pub id: u64,
     pub address: String,
     pub height: u32,
     pub nonce: u64,
@@ -13,38 +16,388 @@ pub struct Reward {
     pub timestamp: i64,
 }
 
-pub trait Storage<N: Network> {
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProverRank {
+    pub address: String,
+    pub solutions: u64,
+    pub reward: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProverShare {
+    pub address: String,
+    pub solutions: u64,
+    pub reward: u64,
+    pub total_solutions: u64,
+    pub total_reward: u64,
+    pub solutions_share: f64,
+    pub reward_share: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub address: String,
+    pub label: String,
+}
+
+/// A named group of addresses, e.g. the prover addresses of one customer.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+    pub label: String,
+    pub members: Vec<GroupMember>,
+}
+
+/// Aggregated rewards of the group members, members without solutions are included with zero.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupReward {
+    pub name: String,
+    pub solutions: u64,
+    pub reward: u64,
+    pub members: Vec<ProverRank>,
+}
+
+/// A row of the blocks table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockRow {
+    pub height: u32,
+    pub hash: String,
+    pub previous_hash: String,
+    pub network: u16,
+    pub coinbase_target: u64,
+    pub proof_target: u64,
+    pub last_coinbase_target: u64,
+    pub last_coinbase_timestamp: i64,
+    pub timestamp: i64,
+    pub solutions_num: u32,
+    pub block_reward: u64,
+}
+
+/// A row of the block solutions table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolutionRow {
+    pub height: u32,
+    pub address: String,
+    pub nonce: u64,
+    pub commitment: String,
+    pub reward: u64,
+    pub timestamp: i64,
+}
+
+/// One beneficiary line of a closed payout period, rewards are in microcredits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub scheme: String,
+    pub period_start: i64,
+    pub period_end: i64,
+    pub beneficiary: String,
+    pub solutions: u64,
+    pub gross_reward: u64,
+    pub fee: u64,
+    pub net_reward: u64,
+}
+
+/// Sort order of paged solution queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Position of the last returned solution, solutions are ordered by `(timestamp, id)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub timestamp: i64,
+    pub id: u64,
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.timestamp, self.id)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (timestamp, id) = s.split_once('_').ok_or_else(|| anyhow::anyhow!("invalid cursor {s}"))?;
+        Ok(Self { timestamp: timestamp.parse()?, id: id.parse()? })
+    }
+}
+
+/// A page request, `limit` must already be capped by the caller.
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+    pub limit: u32,
+    pub cursor: Option<Cursor>,
+    pub order: Order,
+}
+
+/// A page of rows and the cursor of the next page, `None` if it is the last page.
+#[derive(Debug)]
+pub struct Paged<T> {
+    pub items: Vec<T>,
+    pub next: Option<Cursor>,
+}
+
+impl Paged<Reward> {
+    /// Cuts the rows to the page, the rows are read with one extra row only to know whether there is a next page.
+    pub fn from_rows(mut items: Vec<Reward>, limit: u32) -> Self {
+        let mut next = None;
+        if items.len() > limit as usize {
+            items.truncate(limit as usize);
+            next = items.last().map(|last| Cursor { timestamp: last.timestamp, id: last.id });
+        }
+        Self { items, next }
+    }
+}
+
+/// The blocking storage implementations, called through `Store` on blocking threads.
+pub trait Storage<N: Network>: Send + Sync + 'static {
     fn new(url: String) -> Self; 
+    /// Opens the storage in a process other than the sync, which is the only writer of the jsonl files.
+    /// The same as `new` for the databases.
+    fn new_reader(url: String) -> Self where Self: Sized {
+        Self::new(url)
+    }
     fn record_block(&self, block: &BlockReward<N>) -> anyhow::Result<bool>;
     fn record_solutions(&self, solutions: &Solution<N>) -> anyhow::Result<bool>;
-    fn get_solutions_by_time_range(&self, address: &String, begin: i64, end: i64) -> anyhow::Result<Vec<Reward>>;
+    /// Records the solutions and blocks of several blocks at once, `SyncHeight` messages are ignored.
+    fn record_batch(&self, messages: &[Message<N>]) -> anyhow::Result<()> {
+        for message in messages {
+            match message {
+                Message::Solution(solution) => { self.record_solutions(solution)?; },
+                Message::BlockReward(block) => { self.record_block(block)?; },
+                Message::SyncHeight(_) => {},
+            }
+        }
+        Ok(())
+    }
+    /// Called after all the messages of one or more blocks are recorded.
+    fn flush(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+    fn get_solutions_page(&self, address: &str, begin: i64, end: i64, page: &Page) -> anyhow::Result<Paged<Reward>>;
+    fn get_top_provers(&self, begin: i64, end: i64, limit: u32) -> anyhow::Result<Vec<ProverRank>>;
+    fn get_prover_share(&self, address: &str, begin: i64, end: i64) -> anyhow::Result<ProverShare>;
+    fn create_group(&self, name: &str, label: &str) -> anyhow::Result<bool>;
+    fn delete_group(&self, name: &str) -> anyhow::Result<bool>;
+    fn add_group_member(&self, name: &str, address: &str, label: &str) -> anyhow::Result<bool>;
+    fn remove_group_member(&self, name: &str, address: &str) -> anyhow::Result<bool>;
+    fn get_groups(&self) -> anyhow::Result<Vec<Group>>;
+    fn get_group_rewards(&self, name: &str, begin: i64, end: i64) -> anyhow::Result<Option<GroupReward>>;
+    fn get_address_rewards(&self, addresses: &[String], begin: i64, end: i64) -> anyhow::Result<Vec<ProverRank>>;
+    fn close_payout_period(&self, scheme: &str, period_start: i64, period_end: i64, entries: &[LedgerEntry]) -> anyhow::Result<bool>;
+    fn get_payout_period(&self, scheme: &str, period_start: i64) -> anyhow::Result<Option<Vec<LedgerEntry>>>;
+    fn get_blocks_by_height_range(&self, start: u32, end: u32) -> anyhow::Result<Vec<BlockRow>>;
+    fn get_solutions_by_height_range(&self, start: u32, end: u32) -> anyhow::Result<Vec<SolutionRow>>;
+    fn get_latest_timestamp(&self) -> anyhow::Result<Option<i64>>;
+    /// The id of the last recorded solution, ids only grow.
+    fn get_last_solution_id(&self) -> anyhow::Result<Option<u64>>;
+    /// The earliest timestamp of the solutions recorded after the solution `id`.
+    fn get_earliest_timestamp_after(&self, id: u64) -> anyhow::Result<Option<i64>>;
 }
 
+/// The storage backend selected by the cargo features, mysql takes precedence over jsonl.
+#[cfg(feature = "mysql")]
+pub type Backend = crate::mysql::MysqlClient;
+#[cfg(all(feature = "jsonl", not(feature = "mysql")))]
+pub type Backend = crate::jsonl::JsonlStore;
+#[cfg(not(any(feature = "mysql", feature = "jsonl")))]
+compile_error!("enable the mysql or jsonl feature");
+
+/// Storage calls running at the same time, each one occupies a blocking thread.
+const STORAGE_THREADS: usize = 32;
+
+/// The async facade of a storage, the blocking calls run on a bounded set of blocking threads,
+/// so database io never stalls the runtime worker threads.
 #[derive(Debug)]
 pub struct Store<N:Network, S: Storage<N> + Sized> {
-    inner: S,
+    inner: Arc<S>,
+    permits: Arc<Semaphore>,
     _n: PhantomData<N>,
-    _s: PhantomData<S>,
+}
+
+impl<N: Network, S: Storage<N>> Clone for Store<N, S> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), permits: self.permits.clone(), _n: PhantomData }
+    }
 }
 
 impl<N:Network, S: Storage<N>> Store<N, S> {
     pub fn new(url: String) -> Self {
         Self {
-            inner: S::new(url),
+            inner: Arc::new(S::new(url)),
+            permits: Arc::new(Semaphore::new(STORAGE_THREADS)),
             _n: PhantomData,
-            _s: PhantomData,
         }
     }
 
-    pub fn record_block(&self, block: &BlockReward<N>) -> anyhow::Result<bool> {
-        self.inner.record_block(block)
+    /// See `Storage::new_reader`.
+    pub fn reader(url: String) -> Self {
+        Self {
+            inner: Arc::new(S::new_reader(url)),
+            permits: Arc::new(Semaphore::new(STORAGE_THREADS)),
+            _n: PhantomData,
+        }
+    }
+
+    /// Runs the storage call on a blocking thread.
+    async fn blocking<T, F>(&self, call: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&S) -> anyhow::Result<T> + Send + 'static,
+    {
+        let permit = self.permits.clone().acquire_owned().await?;
+        let inner = self.inner.clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            call(&inner)
+        }).await?
+    }
+
+    pub async fn record_batch(&self, messages: Arc<Vec<Message<N>>>) -> anyhow::Result<()> {
+        self.blocking(move |inner| inner.record_batch(&messages)).await
+    }
+
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        self.blocking(|inner| inner.flush()).await
+    }
+
+    pub async fn get_solutions_page(&self, address: &str, begin: i64, end: i64, page: &Page) -> anyhow::Result<Paged<Reward>> {
+        let (address, page) = (address.to_string(), *page);
+        self.blocking(move |inner| inner.get_solutions_page(&address, begin, end, &page)).await
     }
 
-    pub fn record_solutions(&self, solution: &Solution<N>) -> anyhow::Result<bool> {
-        self.inner.record_solutions(solution)
+    pub async fn get_top_provers(&self, begin: i64, end: i64, limit: u32) -> anyhow::Result<Vec<ProverRank>> {
+        self.blocking(move |inner| inner.get_top_provers(begin, end, limit)).await
     }
 
-    pub fn get_solutions_by_time_range(&self, address: &String, begin: i64, end: i64) -> anyhow::Result<Vec<Reward>> {
-        self.inner.get_solutions_by_time_range(address, begin, end)
+    pub async fn get_prover_share(&self, address: &str, begin: i64, end: i64) -> anyhow::Result<ProverShare> {
+        let address = address.to_string();
+        self.blocking(move |inner| inner.get_prover_share(&address, begin, end)).await
     }
-}
\ No newline at end of file
+
+    /// Returns false if the group already exists.
+    pub async fn create_group(&self, name: &str, label: &str) -> anyhow::Result<bool> {
+        let (name, label) = (name.to_string(), label.to_string());
+        self.blocking(move |inner| inner.create_group(&name, &label)).await
+    }
+
+    /// Returns false if the group does not exist.
+    pub async fn delete_group(&self, name: &str) -> anyhow::Result<bool> {
+        let name = name.to_string();
+        self.blocking(move |inner| inner.delete_group(&name)).await
+    }
+
+    /// Adds the address or updates its label, returns false if the group does not exist.
+    pub async fn add_group_member(&self, name: &str, address: &str, label: &str) -> anyhow::Result<bool> {
+        let (name, address, label) = (name.to_string(), address.to_string(), label.to_string());
+        self.blocking(move |inner| inner.add_group_member(&name, &address, &label)).await
+    }
+
+    /// Returns false if the address is not a member of the group.
+    pub async fn remove_group_member(&self, name: &str, address: &str) -> anyhow::Result<bool> {
+        let (name, address) = (name.to_string(), address.to_string());
+        self.blocking(move |inner| inner.remove_group_member(&name, &address)).await
+    }
+
+    pub async fn get_groups(&self) -> anyhow::Result<Vec<Group>> {
+        self.blocking(|inner| inner.get_groups()).await
+    }
+
+    /// timestamp -> [begin, end), same as `get_solutions_page`, `None` if the group does not exist.
+    pub async fn get_group_rewards(&self, name: &str, begin: i64, end: i64) -> anyhow::Result<Option<GroupReward>> {
+        let name = name.to_string();
+        self.blocking(move |inner| inner.get_group_rewards(&name, begin, end)).await
+    }
+
+    /// timestamp -> [begin, end), totals of the given addresses, addresses without solutions are omitted.
+    pub async fn get_address_rewards(&self, addresses: &[String], begin: i64, end: i64) -> anyhow::Result<Vec<ProverRank>> {
+        let addresses = addresses.to_vec();
+        self.blocking(move |inner| inner.get_address_rewards(&addresses, begin, end)).await
+    }
+
+    /// Stores the period and its entries atomically, returns false if the period is already closed.
+    pub async fn close_payout_period(&self, scheme: &str, period_start: i64, period_end: i64, entries: &[LedgerEntry]) -> anyhow::Result<bool> {
+        let (scheme, entries) = (scheme.to_string(), entries.to_vec());
+        self.blocking(move |inner| inner.close_payout_period(&scheme, period_start, period_end, &entries)).await
+    }
+
+    /// `None` if the period is not closed.
+    pub async fn get_payout_period(&self, scheme: &str, period_start: i64) -> anyhow::Result<Option<Vec<LedgerEntry>>> {
+        let scheme = scheme.to_string();
+        self.blocking(move |inner| inner.get_payout_period(&scheme, period_start)).await
+    }
+
+    /// height -> [start, end), ordered by height.
+    pub async fn get_blocks_by_height_range(&self, start: u32, end: u32) -> anyhow::Result<Vec<BlockRow>> {
+        self.blocking(move |inner| inner.get_blocks_by_height_range(start, end)).await
+    }
+
+    /// height -> [start, end), ordered by height and id.
+    pub async fn get_solutions_by_height_range(&self, start: u32, end: u32) -> anyhow::Result<Vec<SolutionRow>> {
+        self.blocking(move |inner| inner.get_solutions_by_height_range(start, end)).await
+    }
+
+    /// The timestamp of the latest recorded block or solution, `None` if nothing is recorded yet.
+    pub async fn get_latest_timestamp(&self) -> anyhow::Result<Option<i64>> {
+        self.blocking(|inner| inner.get_latest_timestamp()).await
+    }
+
+    /// The id of the last recorded solution, `None` if nothing is recorded yet.
+    pub async fn get_last_solution_id(&self) -> anyhow::Result<Option<u64>> {
+        self.blocking(|inner| inner.get_last_solution_id()).await
+    }
+
+    /// The earliest timestamp of the solutions recorded after the solution `id`, a backfill records solutions
+    /// below the latest timestamp.
+    pub async fn get_earliest_timestamp_after(&self, id: u64) -> anyhow::Result<Option<i64>> {
+        self.blocking(move |inner| inner.get_earliest_timestamp_after(id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reward(timestamp: i64, id: u64) -> Reward {
+        Reward { id, address: String::from("aleo1"), height: 1, nonce: 0, reward: 0, timestamp }
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        for cursor in [Cursor { timestamp: 0, id: 0 }, Cursor { timestamp: 1_680_000_000, id: 42 }, Cursor { timestamp: -1, id: u64::MAX }] {
+            assert_eq!(cursor.to_string().parse::<Cursor>().unwrap(), cursor);
+        }
+        assert_eq!("1680000000_42".parse::<Cursor>().unwrap(), Cursor { timestamp: 1_680_000_000, id: 42 });
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        for cursor in ["", "_", "42", "1_", "_1", "a_1", "1_b", "1_2_3", "1-2", "1_-2", " 1_2"] {
+            assert!(cursor.parse::<Cursor>().is_err(), "{cursor:?} parsed");
+        }
+    }
+
+    #[test]
+    fn the_extra_row_makes_the_next_cursor() {
+        let rows = |n: u64| (0..n).map(|id| reward(100 + id as i64, id)).collect::<Vec<_>>();
+
+        // 多读的一行只用来判断是否有下一页
+        let page = Paged::from_rows(rows(4), 3);
+        assert_eq!(page.items.len(), 3);
+        assert_eq!(page.next, Some(Cursor { timestamp: 102, id: 2 }));
+
+        let page = Paged::from_rows(rows(3), 3);
+        assert_eq!(page.items.len(), 3);
+        assert_eq!(page.next, None);
+
+        let page = Paged::from_rows(rows(0), 3);
+        assert!(page.items.is_empty());
+        assert_eq!(page.next, None);
+    }
+}
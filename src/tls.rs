@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+
+/// The certificate of the api server, the files are read again on SIGHUP.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// The pem certificate chain.
+    pub cert_file: String,
+    /// The pem private key, pkcs8, rsa or ec.
+    pub key_file: String,
+    /// The pem ca certificates of the clients, every client must present a certificate signed by one of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ca_file: Option<String>,
+}
+
+/// A remote address of a tls connection, warp only knows the remote address of the plain http server.
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteAddr(pub std::net::SocketAddr);
+
+#[cfg(feature = "tls")]
+pub use server::{load, serve};
+
+#[cfg(feature = "tls")]
+mod server {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use anyhow::{bail, Context, Result};
+    use log::{debug, error, info};
+    use parking_lot::RwLock;
+    use tokio::net::TcpListener;
+    use tokio::signal::unix::{signal, SignalKind};
+    use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+    use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+    use tokio_rustls::TlsAcceptor;
+    use warp::hyper::server::conn::Http;
+    use warp::hyper::service::{service_fn, Service};
+    use warp::hyper::{Body, Request};
+    use warp::{Filter, Reply};
+
+    use super::{RemoteAddr, TlsConfig};
+
+    /// A client must finish the handshake in this time, so idle connections do not pile up.
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+    /// The pause after a failed accept, e.g. out of file descriptors, instead of retrying in a busy loop.
+    const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+    fn certs(path: &str) -> Result<Vec<Certificate>> {
+        let mut reader = BufReader::new(File::open(path).with_context(|| format!("failed to open {path}"))?);
+        let certs = rustls_pemfile::certs(&mut reader).with_context(|| format!("failed to parse {path}"))?;
+        if certs.is_empty() {
+            bail!("no certificate in {path}");
+        }
+        Ok(certs.into_iter().map(Certificate).collect())
+    }
+
+    fn private_key(path: &str) -> Result<PrivateKey> {
+        let mut reader = BufReader::new(File::open(path).with_context(|| format!("failed to open {path}"))?);
+        for item in rustls_pemfile::read_all(&mut reader).with_context(|| format!("failed to parse {path}"))? {
+            match item {
+                rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key) => {
+                    return Ok(PrivateKey(key));
+                }
+                _ => {}
+            }
+        }
+        bail!("no private key in {path}")
+    }
+
+    /// Reads the certificate, the key and the client ca of the config.
+    pub fn load(config: &TlsConfig) -> Result<Arc<ServerConfig>> {
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &config.client_ca_file {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in certs(path)? {
+                    roots.add(&cert).with_context(|| format!("invalid client ca in {path}"))?;
+                }
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let mut server = builder
+            .with_single_cert(certs(&config.cert_file)?, private_key(&config.key_file)?)
+            .context("the certificate does not match the private key")?;
+        server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(server))
+    }
+
+    /// Reloads the certificate on SIGHUP, a broken certificate keeps the current one.
+    async fn reload_on_sighup(config: TlsConfig, current: Arc<RwLock<Arc<ServerConfig>>>) {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                error!("failed to listen to SIGHUP, certificate reload is disabled: {:?}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            match load(&config) {
+                Ok(server) => {
+                    *current.write() = server;
+                    info!("reload certificate {}", config.cert_file);
+                }
+                Err(e) => error!("reload certificate failed, keep the current one: {:?}", e),
+            }
+        }
+    }
+
+    /// Serves the routes over tls, new connections use the certificate loaded last.
+    pub async fn serve<F>(listen_ip: SocketAddr, config: TlsConfig, routes: F) -> Result<()>
+    where
+        F: Filter + Clone + Send + Sync + 'static,
+        F::Extract: Reply,
+    {
+        let current = Arc::new(RwLock::new(load(&config)?));
+        tokio::spawn(reload_on_sighup(config, current.clone()));
+
+        let listener = TcpListener::bind(listen_ip).await.with_context(|| format!("failed to listen on {listen_ip}"))?;
+        info!("api server listening on https://{listen_ip}");
+        let service = warp::service(routes);
+        loop {
+            let (stream, remote) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!("accept failed: {:?}", e);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            let acceptor = TlsAcceptor::from(current.read().clone());
+            let mut service = service.clone();
+            tokio::spawn(async move {
+                // 握手失败多为客户端问题，例如没有客户端证书
+                let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        debug!("tls handshake with {remote} failed: {:?}", e);
+                        return;
+                    }
+                    Err(_) => {
+                        debug!("tls handshake with {remote} timed out");
+                        return;
+                    }
+                };
+                let service = service_fn(move |mut request: Request<Body>| {
+                    request.extensions_mut().insert(RemoteAddr(remote));
+                    service.call(request)
+                });
+                if let Err(e) = Http::new().serve_connection(stream, service).await {
+                    debug!("connection with {remote} failed: {:?}", e);
+                }
+            });
+        }
+    }
+}
@@ -7,6 +7,12 @@ use std::time::{Duration, Instant};
 use tokio::runtime::{self, Runtime};
 use std::fs::{OpenOptions, File};
 use std::path::PathBuf;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use chrono::{Offset, TimeZone};
+use snarkvm_console_network::Network;
+use snarkvm_console_types_address::Address;
 
 pub fn backoffset() -> ExponentialBackoff {
     ExponentialBackoff {
@@ -85,6 +91,16 @@ where
         .expect("Failed to initialize a runtime for the router")
 }
 
+/// Reads the synced height from the sync height record file without creating it.
+#[cfg(feature = "parquet")]
+pub fn read_synced_height(filepath: &str) -> anyhow::Result<u32> {
+    let bytes = std::fs::read(filepath)?;
+    let buf: [u8; 4] = bytes.get(0..4)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!("invalid sync height file {filepath}"))?;
+    Ok(u32::from_le_bytes(buf))
+}
+
 pub fn open_file(filepath: String) -> File {
     let path: PathBuf = PathBuf::from(filepath);
     let file = OpenOptions::new()
@@ -95,4 +111,171 @@ pub fn open_file(filepath: String) -> File {
                         .unwrap();
     file.set_len(4).unwrap();
     file
-}
\ No newline at end of file
+}
+
+/// Parses an aleo address, the input is trimmed and lowercased so the canonical form is stored and queried.
+pub fn parse_address<N: Network>(address: &str) -> anyhow::Result<Address<N>> {
+    Address::<N>::from_str(&address.trim().to_lowercase())
+        .map_err(|e| anyhow::anyhow!("invalid aleo address {:?}: {e}", address))
+}
+
+/// Parses a list of aleo addresses into a set, an empty set means every address.
+pub fn parse_addresses<N: Network>(addresses: &[String]) -> anyhow::Result<HashSet<Address<N>>> {
+    addresses.iter().map(|address| parse_address::<N>(address)).collect()
+}
+
+/// Parses a fixed utc offset like `+08:00`, `-0530` or `Z` into seconds.
+pub fn parse_utc_offset(offset: &str) -> anyhow::Result<i64> {
+    if offset == "Z" || offset.is_empty() {
+        return Ok(0)
+    }
+    let (sign, rest) = if let Some(rest) = offset.strip_prefix('+') {
+        (1, rest)
+    } else if let Some(rest) = offset.strip_prefix('-') {
+        (-1, rest)
+    } else {
+        anyhow::bail!("invalid utc offset {offset}, expected a fixed offset like +08:00");
+    };
+    let digits = rest.replace(':', "");
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        anyhow::bail!("invalid utc offset {offset}, expected like +08:00");
+    }
+    let (hours, minutes): (i64, i64) = (digits[..2].parse()?, digits[2..].parse()?);
+    if hours > 14 || minutes > 59 {
+        anyhow::bail!("invalid utc offset {offset}");
+    }
+    Ok(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Returns the day number since the unix epoch of the timestamp at the given utc offset.
+pub fn day_of(timestamp: i64, offset: i64) -> i64 {
+    (timestamp + offset).div_euclid(86400)
+}
+
+/// The time zone of a day boundary, a fixed utc offset or an IANA zone whose offset follows its daylight saving time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DayZone {
+    /// Utc offset in seconds.
+    Fixed(i64),
+    Iana(chrono_tz::Tz),
+}
+
+impl DayZone {
+    /// Returns the utc offset in seconds of the zone at the timestamp.
+    pub fn offset_at(&self, timestamp: i64) -> i64 {
+        match self {
+            Self::Fixed(offset) => *offset,
+            Self::Iana(tz) => match chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0) {
+                Some(utc) => tz.offset_from_utc_datetime(&utc).fix().local_minus_utc() as i64,
+                None => 0,
+            },
+        }
+    }
+
+    /// Returns the day number since the unix epoch of the timestamp in the zone.
+    pub fn day_of(&self, timestamp: i64) -> i64 {
+        day_of(timestamp, self.offset_at(timestamp))
+    }
+}
+
+impl FromStr for DayZone {
+    type Err = anyhow::Error;
+
+    /// Parses a fixed utc offset like `+08:00` or an IANA zone name like `Asia/Shanghai`.
+    fn from_str(tz: &str) -> anyhow::Result<Self> {
+        if let Ok(offset) = parse_utc_offset(tz) {
+            return Ok(Self::Fixed(offset))
+        }
+        match tz.parse::<chrono_tz::Tz>() {
+            Ok(tz) => Ok(Self::Iana(tz)),
+            Err(_) => anyhow::bail!("invalid time zone {tz}, expected a utc offset like +08:00 or a zone name like Europe/Berlin"),
+        }
+    }
+}
+
+/// Formats a day number since the unix epoch as `YYYY-MM-DD`.
+pub fn format_day(day: i64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = day + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+/// Formats microcredits as credits without losing precision, 1 credit = 1_000_000 microcredits.
+pub fn format_credits(microcredits: u64) -> String {
+    format!("{}.{:06}", microcredits / 1_000_000, microcredits % 1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utc_offsets() {
+        assert_eq!(parse_utc_offset("Z").unwrap(), 0);
+        assert_eq!(parse_utc_offset("").unwrap(), 0);
+        assert_eq!(parse_utc_offset("+08:00").unwrap(), 8 * 3600);
+        assert_eq!(parse_utc_offset("-0530").unwrap(), -(5 * 3600 + 30 * 60));
+        assert_eq!(parse_utc_offset("+14:00").unwrap(), 14 * 3600);
+        // unicode负号等多字节字符不能panic
+        for invalid in ["\u{2212}05:00", "08:00", "+8:00", "+08:0", "+15:00", "+08:60", "+０８:00", "Asia/Shanghai", "+", "-"] {
+            assert!(parse_utc_offset(invalid).is_err(), "{invalid:?} parsed");
+        }
+    }
+
+    #[test]
+    fn days_at_an_offset() {
+        assert_eq!(day_of(0, 0), 0);
+        assert_eq!(day_of(86_399, 0), 0);
+        assert_eq!(day_of(86_400, 0), 1);
+        // 1970-01-01 16:00 utc 是 utc+8 的第二天
+        assert_eq!(day_of(16 * 3600, 8 * 3600), 1);
+        assert_eq!(day_of(0, -3600), -1);
+        assert_eq!(day_of(-1, 0), -1);
+    }
+
+    #[test]
+    fn day_zones() {
+        assert_eq!("+08:00".parse::<DayZone>().unwrap(), DayZone::Fixed(8 * 3600));
+        assert!("Mars/Olympus".parse::<DayZone>().is_err());
+        assert!("+15:00".parse::<DayZone>().is_err());
+
+        let berlin: DayZone = "Europe/Berlin".parse().unwrap();
+        // 2023-03-26 夏令时开始，2023-03-25 23:30 utc 是柏林的 03-26
+        assert_eq!(berlin.offset_at(1_679_700_000), 3600);
+        assert_eq!(format_day(berlin.day_of(1_679_787_000)), "2023-03-26");
+        assert_eq!(berlin.offset_at(1_679_792_400), 2 * 3600);
+        // 2023-06-30 22:30 utc 是柏林的 07-01
+        assert_eq!(format_day(berlin.day_of(1_688_164_200)), "2023-07-01");
+        assert_eq!(format_day(DayZone::Fixed(3600).day_of(1_688_164_200)), "2023-06-30");
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(format_day(0), "1970-01-01");
+        assert_eq!(format_day(-1), "1969-12-31");
+        assert_eq!(format_day(59), "1970-03-01");
+        // 闰年
+        assert_eq!(format_day(11_016), "2000-02-29");
+        assert_eq!(format_day(11_017), "2000-03-01");
+        assert_eq!(format_day(19_416), "2023-02-28");
+        assert_eq!(format_day(19_417), "2023-03-01");
+        assert_eq!(format_day(day_of(1_680_307_200, 0)), "2023-04-01");
+    }
+
+    #[test]
+    fn credits() {
+        assert_eq!(format_credits(0), "0.000000");
+        assert_eq!(format_credits(1), "0.000001");
+        assert_eq!(format_credits(1_000_000), "1.000000");
+        assert_eq!(format_credits(123_456_789), "123.456789");
+        assert_eq!(format_credits(u64::MAX), "18446744073709.551615");
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use log::{error, info, warn};
+use parking_lot::RwLock;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, Notify};
+
+use snarkvm_console_network::Network;
+use snarkvm_console_types_address::Address;
+
+use crate::cli::config::load_config;
+use crate::adaptive::Adaptive;
+use crate::manage::ApiManage;
+use crate::message::Message;
+use crate::recover::ParseOptions;
+use crate::utils::parse_addresses;
+
+#[derive(Debug)]
+struct State<N: Network> {
+    addresses: Arc<HashSet<Address<N>>>,
+    generation: u64,
+    // generation -> first block height parsed with it
+    first_heights: BTreeMap<u64, u32>,
+}
+
+/// The watched addresses shared by the sync tasks, an empty set means every address.
+///
+/// Every replacement bumps the generation, parsers report the first height they parsed with a generation,
+/// so a backfill of added addresses knows exactly where the live sync took over.
+#[derive(Debug, Clone)]
+pub struct Watchlist<N: Network> {
+    state: Arc<RwLock<State<N>>>,
+    observed: Arc<Notify>,
+}
+
+impl<N: Network> Watchlist<N> {
+    pub fn new(addresses: HashSet<Address<N>>) -> Self {
+        let state = State { addresses: Arc::new(addresses), generation: 0, first_heights: BTreeMap::new() };
+        Self { state: Arc::new(RwLock::new(state)), observed: Arc::new(Notify::new()) }
+    }
+
+    /// Returns the current addresses and their generation.
+    pub fn snapshot(&self) -> (Arc<HashSet<Address<N>>>, u64) {
+        let state = self.state.read();
+        (state.addresses.clone(), state.generation)
+    }
+
+    /// Records that the block at `height` was parsed with the addresses of `generation`.
+    pub fn observe(&self, generation: u64, height: u32) {
+        if let Entry::Vacant(entry) = self.state.write().first_heights.entry(generation) {
+            entry.insert(height);
+        } else {
+            return;
+        }
+        self.observed.notify_waiters();
+    }
+
+    /// Replaces the addresses, returns the added addresses and the new generation.
+    pub fn replace(&self, addresses: HashSet<Address<N>>) -> (HashSet<Address<N>>, u64) {
+        let mut state = self.state.write();
+        let added = addresses.difference(&state.addresses).copied().collect();
+        state.addresses = Arc::new(addresses);
+        state.generation += 1;
+        (added, state.generation)
+    }
+
+    /// Waits until a block is parsed with `generation` or a later one, and returns its height.
+    async fn first_height(&self, generation: u64) -> u32 {
+        loop {
+            let notified = self.observed.notified();
+            if let Some((_, height)) = self.state.read().first_heights.range(generation..).next() {
+                return *height;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Reloads the watched addresses from the config file on SIGHUP, and backfills the added addresses.
+///
+/// Must run inside a `LocalSet`, the backfill tasks are spawned locally.
+pub async fn reload_on_sighup<N: Network>(
+    config_path: String,
+    watchlist: Watchlist<N>,
+    api_manage: ApiManage,
+    adaptive: Adaptive,
+    sender: mpsc::Sender<Message<N>>,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("failed to listen to SIGHUP, address reload is disabled: {:?}", e);
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        let config = match load_config(&config_path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("reload config {config_path} failed, keep the current addresses: {:?}", e);
+                continue;
+            }
+        };
+        let addresses = match parse_addresses::<N>(&config.address) {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                error!("reload address failed, keep the current addresses: {:?}", e);
+                continue;
+            }
+        };
+
+        let (previous, _) = watchlist.snapshot();
+        let (added, generation) = watchlist.replace(addresses.clone());
+        info!("reload watched address: {:?}", addresses);
+
+        // 之前记录所有地址，无需回填
+        if previous.is_empty() {
+            continue;
+        }
+        if addresses.is_empty() {
+            warn!("address is empty now, every address is recorded from now on, history is not backfilled");
+            continue;
+        }
+        if added.is_empty() {
+            continue;
+        }
+
+        tokio::task::spawn_local(backfill(
+            watchlist.clone(),
+            generation,
+            added,
+            api_manage.clone(),
+            sender.clone(),
+            adaptive.clone(),
+            // 回填只记录solution
+            ParseOptions { store_block: false, ..config.parse_options() },
+        ));
+    }
+}
+
+/// Rescans the blocks below the height where the live sync took over, and records the solutions of `addresses` only.
+async fn backfill<N: Network>(
+    watchlist: Watchlist<N>,
+    generation: u64,
+    addresses: HashSet<Address<N>>,
+    api_manage: ApiManage,
+    sender: mpsc::Sender<Message<N>>,
+    adaptive: Adaptive,
+    parse: ParseOptions,
+) {
+    let end_height = watchlist.first_height(generation).await;
+    info!("backfill address {:?} below height {}", addresses, end_height);
+
+    // 只转发solution，区块和同步高度由实时同步负责
+    let (backfill_sender, mut backfill_receiver) = mpsc::channel(4096);
+    let forward = async move {
+        while let Some(message) = backfill_receiver.recv().await {
+            if let Message::Solution(_) = message {
+                if sender.send(message).await.is_err() {
+                    break;
+                }
+            }
+        }
+    };
+
+    let batch_obj = crate::batch::Batch::<N>::new(
+        api_manage,
+        0,
+        Some(end_height),
+        Watchlist::new(addresses),
+        adaptive,
+        backfill_sender,
+        parse,
+    );
+    let scan = async move {
+        match batch_obj.get_blocks().await {
+            Ok(height) => info!("backfill finished at height {}", height),
+            Err((height, error)) => error!("backfill stopped at height {}: {}", height, error),
+        }
+    };
+    futures::join!(scan, forward);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    use reqwest::Client;
+    use snarkvm_console_network::Testnet3;
+    use snarkvm_synthesizer::Block;
+
+    use super::*;
+    use crate::mock::{fixtures_dir, load_blocks, MockApi};
+    use crate::recover::ParseErrorPolicy;
+
+    /// The fixture blocks, deserialized once, a block is slow to deserialize without optimizations.
+    fn blocks() -> &'static [Block<Testnet3>] {
+        static BLOCKS: OnceLock<Vec<Block<Testnet3>>> = OnceLock::new();
+        BLOCKS.get_or_init(|| {
+            load_blocks(&fixtures_dir()).unwrap().values().map(|block| serde_json::from_str(block).unwrap()).collect()
+        })
+    }
+
+    /// The provers of the fixture blocks, in the order they first solve.
+    fn provers() -> Vec<Address<Testnet3>> {
+        let mut provers = Vec::new();
+        for block in blocks() {
+            for solution in block.coinbase().map(|coinbase| coinbase.partial_solutions()).unwrap_or_default() {
+                if !provers.contains(&solution.address()) {
+                    provers.push(solution.address());
+                }
+            }
+        }
+        provers
+    }
+
+    /// The (height, commitment) of the solutions of the address below the height.
+    fn solutions_below(address: Address<Testnet3>, end: u32) -> Vec<(u32, String)> {
+        blocks().iter()
+            .filter(|block| block.height() < end)
+            .flat_map(|block| {
+                let solutions = block.coinbase().map(|coinbase| coinbase.partial_solutions()).unwrap_or_default();
+                solutions.iter()
+                    .filter(|solution| solution.address() == address)
+                    .map(|solution| (block.height(), solution.commitment().to_string()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Runs the backfill of the added address, the live sync took over at `first_height`.
+    async fn backfill_below(mock: &MockApi, first_height: u32, added: Address<Testnet3>) -> Vec<Message<Testnet3>> {
+        let watchlist = Watchlist::new(HashSet::from([provers()[0]]));
+        let (_, generation) = watchlist.replace(HashSet::from([provers()[0], added]));
+        watchlist.observe(generation, first_height);
+
+        let (sender, mut receiver) = mpsc::channel(4096);
+        let parse = ParseOptions {
+            store_block: false,
+            on_parse_error: ParseErrorPolicy::Stop,
+            quarantine_file: String::from("quarantine.jsonl"),
+        };
+        let api_manage = ApiManage::new(Client::new(), vec![mock.url()]);
+        let adaptive = Adaptive::new(20, 4, (1, 50), (1, 8));
+        let backfilled = backfill(watchlist, generation, HashSet::from([added]), api_manage, sender, adaptive, parse);
+        tokio::time::timeout(Duration::from_secs(30), backfilled).await.unwrap();
+
+        let mut messages = Vec::new();
+        while let Ok(message) = receiver.try_recv() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn replace_bumps_the_generation_and_returns_only_the_added_addresses() {
+        let provers = provers();
+        let watchlist = Watchlist::new(HashSet::from([provers[0], provers[1]]));
+        assert_eq!(watchlist.snapshot().1, 0);
+
+        let (added, generation) = watchlist.replace(HashSet::from([provers[1], provers[2]]));
+        assert_eq!(added, HashSet::from([provers[2]]));
+        assert_eq!(generation, 1);
+        let (addresses, generation) = watchlist.snapshot();
+        assert_eq!(*addresses, HashSet::from([provers[1], provers[2]]));
+        assert_eq!(generation, 1);
+
+        // 只删除地址同样换代，但没有需要回填的地址
+        let (added, generation) = watchlist.replace(HashSet::from([provers[2]]));
+        assert!(added.is_empty());
+        assert_eq!(generation, 2);
+    }
+
+    #[tokio::test]
+    async fn first_height_is_the_first_block_parsed_with_the_generation() {
+        let provers = provers();
+        let watchlist = Watchlist::new(HashSet::from([provers[0]]));
+        watchlist.observe(0, 10);
+        watchlist.observe(0, 11);
+        let (_, generation) = watchlist.replace(HashSet::from([provers[0], provers[1]]));
+
+        // 换代前已取出旧地址的块不算新一代的起点
+        watchlist.observe(0, 12);
+        let waiting = tokio::time::timeout(Duration::from_millis(50), watchlist.first_height(generation));
+        assert!(waiting.await.is_err());
+
+        let first_height = tokio::spawn({
+            let watchlist = watchlist.clone();
+            async move { watchlist.first_height(generation).await }
+        });
+        tokio::task::yield_now().await;
+        watchlist.observe(generation, 13);
+        watchlist.observe(generation, 14);
+        assert_eq!(first_height.await.unwrap(), 13);
+        assert_eq!(watchlist.first_height(generation).await, 13);
+        assert_eq!(watchlist.first_height(0).await, 10);
+
+        // 后一代的起点同样结束前一代的等待
+        let (_, skipped) = watchlist.replace(HashSet::from([provers[1]]));
+        let (_, latest) = watchlist.replace(HashSet::from([provers[2]]));
+        watchlist.observe(latest, 20);
+        assert_eq!(watchlist.first_height(skipped).await, 20);
+    }
+
+    #[tokio::test]
+    async fn backfill_rescans_the_solutions_of_the_added_address_below_the_first_height() {
+        let mock = MockApi::from_fixtures().unwrap();
+        let added = provers()[1];
+        // 实时同步在CDN取整高度之上接手
+        let first_height = mock.height() - 10 - 3;
+        let expected = solutions_below(added, first_height);
+        assert!(expected.iter().any(|(height, _)| *height >= 20), "no solution of the added prover between 20 and {first_height}");
+
+        let messages = backfill_below(&mock, first_height, added).await;
+        let backfilled: Vec<(u32, String)> = messages.iter()
+            .map(|message| match message {
+                Message::Solution(solution) => (solution.block_height, solution.partial_solution.commitment().to_string()),
+                _ => panic!("backfill forwarded {}", message.name()),
+            })
+            .collect();
+        assert_eq!(backfilled, expected);
+    }
+
+    /// Backfilling an address twice, or one recorded before, records every solution once.
+    #[cfg(feature = "jsonl")]
+    #[tokio::test]
+    async fn a_repeated_backfill_records_nothing_new() {
+        use crate::jsonl::JsonlStore;
+        use crate::storage::Storage;
+
+        let dir = std::env::temp_dir().join(format!("aleo-blocks-watch-backfill-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = JsonlStore::open(dir.to_str().unwrap()).unwrap();
+        let mock = MockApi::from_fixtures().unwrap();
+        let added = provers()[1];
+        let first_height = mock.height() - 10;
+
+        for _ in 0..2 {
+            let messages = backfill_below(&mock, first_height, added).await;
+            Storage::<Testnet3>::record_batch(&store, &messages).unwrap();
+            Storage::<Testnet3>::flush(&store).unwrap();
+
+            let recorded: Vec<(u32, String)> = Storage::<Testnet3>::get_solutions_by_height_range(&store, 0, first_height).unwrap()
+                .into_iter()
+                .map(|row| (row.height, row.commitment))
+                .collect();
+            assert_eq!(recorded, solutions_below(added, first_height));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}